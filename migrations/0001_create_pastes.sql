@@ -0,0 +1,4 @@
+CREATE TABLE pastes (
+    id TEXT NOT NULL PRIMARY KEY,
+    content TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Unix timestamp (seconds) after which the paste is gone; NULL never expires
+ALTER TABLE pastes ADD COLUMN expires_at INTEGER;
+
+CREATE INDEX pastes_expires_at_idx ON pastes (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE pastes ADD COLUMN burn_after_reading INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Hex-encoded SHA-256 of the token that authorises deleting the paste
+ALTER TABLE pastes ADD COLUMN owner_token_hash TEXT;
@@ -0,0 +1,10 @@
+ALTER TABLE pastes ADD COLUMN created_at INTEGER;
+ALTER TABLE pastes ADD COLUMN updated_at INTEGER;
+ALTER TABLE pastes ADD COLUMN size INTEGER;
+ALTER TABLE pastes ADD COLUMN content_type TEXT;
+ALTER TABLE pastes ADD COLUMN views INTEGER NOT NULL DEFAULT 0;
+
+-- Existing pastes get the time of the migration as their creation time
+UPDATE pastes
+SET created_at = CAST(strftime('%s', 'now') AS INTEGER),
+    size = length(CAST(content AS BLOB));
@@ -0,0 +1,2 @@
+ALTER TABLE pastes ADD COLUMN language TEXT;
+ALTER TABLE pastes ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
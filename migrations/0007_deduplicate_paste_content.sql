@@ -0,0 +1,36 @@
+-- Each distinct content is stored once, keyed by its hex-encoded SHA-256, and
+-- counts the pastes that hold it. sha256() is provided by the application
+-- while migrating.
+CREATE TABLE blobs (
+    hash TEXT NOT NULL PRIMARY KEY,
+    content BLOB NOT NULL,
+    refs INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO blobs (hash, content, refs)
+SELECT sha256(CAST(content AS BLOB)), CAST(content AS BLOB), COUNT(*)
+FROM pastes
+GROUP BY 1;
+
+ALTER TABLE pastes ADD COLUMN content_hash TEXT NOT NULL DEFAULT '';
+UPDATE pastes SET content_hash = sha256(CAST(content AS BLOB));
+ALTER TABLE pastes DROP COLUMN content;
+
+CREATE INDEX pastes_content_hash_idx ON pastes (content_hash);
+
+-- Reference counts follow the pastes, however they come and go; a blob is
+-- removed as soon as nothing refers to it.
+CREATE TRIGGER pastes_insert_blob_ref AFTER INSERT ON pastes BEGIN
+    UPDATE blobs SET refs = refs + 1 WHERE hash = NEW.content_hash;
+END;
+
+CREATE TRIGGER pastes_update_blob_ref AFTER UPDATE OF content_hash ON pastes BEGIN
+    UPDATE blobs SET refs = refs + 1 WHERE hash = NEW.content_hash;
+    UPDATE blobs SET refs = refs - 1 WHERE hash = OLD.content_hash;
+    DELETE FROM blobs WHERE hash = OLD.content_hash AND refs = 0;
+END;
+
+CREATE TRIGGER pastes_delete_blob_ref AFTER DELETE ON pastes BEGIN
+    UPDATE blobs SET refs = refs - 1 WHERE hash = OLD.content_hash;
+    DELETE FROM blobs WHERE hash = OLD.content_hash AND refs = 0;
+END;
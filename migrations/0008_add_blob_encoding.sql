@@ -0,0 +1,3 @@
+-- Content coding the blob's content is compressed with, e.g. zstd; NULL when
+-- it is stored as is
+ALTER TABLE blobs ADD COLUMN encoding TEXT;
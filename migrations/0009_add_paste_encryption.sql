@@ -0,0 +1,2 @@
+-- Scheme the client encrypted the content with; NULL for plain pastes
+ALTER TABLE pastes ADD COLUMN encryption TEXT;
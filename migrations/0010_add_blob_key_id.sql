@@ -0,0 +1,3 @@
+-- Id of the key the blob's content is encrypted with at rest; NULL when it is
+-- stored in the clear
+ALTER TABLE blobs ADD COLUMN key_id TEXT;
@@ -0,0 +1,6 @@
+-- When a view of the paste was last recorded; NULL when it never was
+ALTER TABLE pastes ADD COLUMN last_read_at INTEGER;
+
+-- The orders pastes are evicted in when over quota
+CREATE INDEX pastes_by_last_read ON pastes (COALESCE(last_read_at, created_at), id);
+CREATE INDEX pastes_by_created_at ON pastes (created_at, id);
@@ -0,0 +1,10 @@
+-- API keys, stored by the hash of the key
+CREATE TABLE api_keys (
+    name TEXT PRIMARY KEY,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+
+-- Name of the API key the paste was created with; NULL for anonymous pastes
+ALTER TABLE pastes ADD COLUMN owner TEXT;
+CREATE INDEX pastes_by_owner ON pastes (owner) WHERE owner IS NOT NULL;
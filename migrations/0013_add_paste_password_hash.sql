@@ -0,0 +1,2 @@
+-- PHC string of the password reading the paste takes; NULL when it takes none
+ALTER TABLE pastes ADD COLUMN password_hash TEXT;
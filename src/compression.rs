@@ -0,0 +1,199 @@
+//! Codecs that repository backends compress paste content with at rest.
+//!
+//! Each codec produces a standard HTTP content coding, so compressed content
+//! can be served to clients that accept it without being recompressed.
+
+use std::io::{self, Read, Write};
+
+use actix_web::web::Bytes;
+
+use crate::domain::EncodedContent;
+
+pub trait Codec: Send + Sync {
+    /// The content coding produced, as named in `Content-Encoding`
+    fn encoding(&self) -> &'static str;
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+#[derive(Debug)]
+pub struct Gzip {
+    level: u32,
+}
+
+impl Gzip {
+    pub fn new(level: u32) -> Result<Self, String> {
+        if level > 9 {
+            return Err(format!("{} is not a gzip level - use 0 to 9", level));
+        }
+
+        Ok(Self { level })
+    }
+}
+
+impl Default for Gzip {
+    fn default() -> Self {
+        Self { level: 6 }
+    }
+}
+
+impl Codec for Gzip {
+    fn encoding(&self) -> &'static str {
+        "gzip"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(self.level));
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        flate2::read::GzDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+}
+
+#[derive(Debug)]
+pub struct Zstd {
+    level: i32,
+}
+
+impl Zstd {
+    pub fn new(level: i32) -> Result<Self, String> {
+        let levels = zstd::compression_level_range();
+        if !levels.contains(&level) {
+            return Err(format!(
+                "{} is not a zstd level - use {} to {}",
+                level,
+                levels.start(),
+                levels.end()
+            ));
+        }
+
+        Ok(Self { level })
+    }
+}
+
+impl Default for Zstd {
+    fn default() -> Self {
+        Self {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl Codec for Zstd {
+    fn encoding(&self) -> &'static str {
+        "zstd"
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::encode_all(data, self.level)
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::decode_all(data)
+    }
+}
+
+/// The codec to read back content stored with `encoding`, whichever codec new
+/// content is compressed with.
+fn decoder(encoding: &str) -> Option<Box<dyn Codec>> {
+    match encoding {
+        "gzip" => Some(Box::<Gzip>::default()),
+        "zstd" => Some(Box::<Zstd>::default()),
+        _ => None,
+    }
+}
+
+/// Compress `data` for storage, returning the content coding used. Content
+/// is stored as is when there is no codec or compressing would not shrink it.
+pub fn compress(
+    codec: Option<&dyn Codec>,
+    data: &[u8],
+) -> io::Result<(Option<&'static str>, Vec<u8>)> {
+    if let Some(codec) = codec {
+        let compressed = codec.compress(data)?;
+        if compressed.len() < data.len() {
+            return Ok((Some(codec.encoding()), compressed));
+        }
+    }
+
+    Ok((None, data.to_vec()))
+}
+
+/// Recover content stored by `compress`. Compressed content is also handed
+/// back as stored, so it can be served without compressing it again.
+pub fn decompress(
+    encoding: Option<String>,
+    stored: Vec<u8>,
+) -> io::Result<(Vec<u8>, Option<EncodedContent>)> {
+    let Some(encoding) = encoding else {
+        return Ok((stored, None));
+    };
+
+    let codec = decoder(&encoding).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown content coding {}", encoding),
+        )
+    })?;
+    let content = codec.decompress(&stored)?;
+
+    Ok((
+        content,
+        Some(EncodedContent {
+            encoding,
+            bytes: Bytes::from(stored),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, Codec, Gzip, Zstd};
+    use claims::assert_err;
+
+    fn round_trip(codec: &dyn Codec) {
+        let data = "the same line, over and over\n".repeat(100);
+
+        let (encoding, stored) = compress(Some(codec), data.as_bytes()).unwrap();
+        assert_eq!(Some(codec.encoding()), encoding);
+        assert!(stored.len() < data.len());
+
+        let (content, encoded) = decompress(encoding.map(str::to_owned), stored.clone()).unwrap();
+        assert_eq!(data.as_bytes(), content);
+        assert_eq!(stored, encoded.unwrap().bytes);
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        round_trip(&Gzip::default());
+    }
+
+    #[test]
+    fn zstd_round_trips() {
+        round_trip(&Zstd::default());
+    }
+
+    #[test]
+    fn incompressible_content_is_stored_as_is() {
+        let (encoding, stored) = compress(Some(&Zstd::default()), b"tiny").unwrap();
+        assert_eq!(None, encoding);
+        assert_eq!(b"tiny".to_vec(), stored);
+    }
+
+    #[test]
+    fn unknown_codings_are_rejected() {
+        assert_err!(decompress(Some("lzma".to_string()), vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn out_of_range_levels_are_rejected() {
+        assert_err!(Gzip::new(10));
+        assert_err!(Zstd::new(100));
+    }
+}
@@ -1,15 +1,235 @@
-use serde_aux::field_attributes::deserialize_number_from_string;
+use secrecy::Secret;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    compression::{Codec, Gzip, Zstd},
+    dao::EvictionOrder,
+    domain::{Base62Generator, IdGenerator, UuidGenerator, WordsGenerator},
+    keyring::Keyring,
+};
 
 #[derive(serde::Deserialize)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    /// Public address that paste URLs are built on, e.g. `https://whtpst.com`.
+    /// When unset, it is taken from each request's `Host` header, or from the
+    /// forwarding headers of requests from `rate_limits.trusted_proxies`.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Names of the API keys that may use operator routes such as `/usage`
+    #[serde(default)]
+    pub admin_keys: Vec<String>,
+}
+
+/// Where pastes are persisted
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    InMemory,
+    Sqlite,
+    FileSystem,
+}
+
+#[derive(serde::Deserialize)]
+pub struct StorageSettings {
+    pub backend: StorageBackend,
+    /// Database file used by the `sqlite` backend
+    pub database_path: String,
+    /// Root directory used by the `filesystem` backend
+    pub directory: String,
+    pub compression: CompressionSettings,
+    #[serde(default)]
+    pub encryption: EncryptionSettings,
+    #[serde(default)]
+    pub quota: QuotaSettings,
+}
+
+/// How the `sqlite` and `filesystem` backends compress new content
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct CompressionSettings {
+    pub algorithm: CompressionAlgorithm,
+    /// The algorithm's own default when unset
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub level: Option<i32>,
+}
+
+impl CompressionSettings {
+    pub fn codec(&self) -> Result<Option<Arc<dyn Codec>>, String> {
+        Ok(match (self.algorithm, self.level) {
+            (CompressionAlgorithm::None, _) => None,
+            (CompressionAlgorithm::Gzip, None) => Some(Arc::new(Gzip::default())),
+            (CompressionAlgorithm::Gzip, Some(level)) => {
+                Some(Arc::new(Gzip::new(level.try_into().map_err(|_| {
+                    format!("{} is not a gzip level - use 0 to 9", level)
+                })?)?))
+            }
+            (CompressionAlgorithm::Zstd, None) => Some(Arc::new(Zstd::default())),
+            (CompressionAlgorithm::Zstd, Some(level)) => Some(Arc::new(Zstd::new(level)?)),
+        })
+    }
+}
+
+/// How the `sqlite` and `filesystem` backends encrypt content at rest. Keys
+/// are best set through the environment, e.g.
+/// `APP__STORAGE__ENCRYPTION__KEYS__K1=<base64 key>`; ids are lowercased.
+#[derive(serde::Deserialize, Default)]
+pub struct EncryptionSettings {
+    /// Id of the key new content is encrypted with. New content is stored in
+    /// the clear when unset.
+    #[serde(default)]
+    pub current_key: Option<String>,
+    /// Base64-encoded 256-bit keys by id. Retired keys must stay until
+    /// `whtpst reencrypt` has moved content off them.
+    #[serde(default)]
+    pub keys: HashMap<String, Secret<String>>,
+    /// Base64-encoded 256-bit key content hashes are keyed with. Content is
+    /// stored under its hash in the clear (`content_hash` and `blobs.hash`,
+    /// or blob file names); without this key that is its plain SHA-256, which
+    /// lets anyone who can read the storage confirm guesses at encrypted
+    /// content. It cannot be rotated, and existing content keeps its old hash
+    /// until `whtpst reencrypt` has moved it.
+    #[serde(default)]
+    pub index_key: Option<Secret<String>>,
+}
+
+impl EncryptionSettings {
+    pub fn keyring(&self) -> Result<Option<Arc<Keyring>>, String> {
+        if self.current_key.is_none() && self.keys.is_empty() && self.index_key.is_none() {
+            return Ok(None);
+        }
+
+        let mut keyring = Keyring::new(self.current_key.clone(), &self.keys)?;
+        if let Some(key) = &self.index_key {
+            keyring = keyring.with_index_key(key)?;
+        }
+
+        Ok(Some(Arc::new(keyring)))
+    }
+}
+
+/// What happens to a new paste that would take storage over quota
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPolicy {
+    /// Turn the paste away with `507 Insufficient Storage`
+    #[default]
+    Reject,
+    /// Evict the pastes read longest ago to make room
+    EvictLeastRecentlyRead,
+    /// Evict the pastes created longest ago to make room
+    EvictOldest,
+}
+
+impl QuotaPolicy {
+    /// The order pastes are evicted in, unless the policy is to reject
+    pub fn eviction_order(&self) -> Option<EvictionOrder> {
+        match self {
+            QuotaPolicy::Reject => None,
+            QuotaPolicy::EvictLeastRecentlyRead => Some(EvictionOrder::LeastRecentlyRead),
+            QuotaPolicy::EvictOldest => Some(EvictionOrder::Oldest),
+        }
+    }
+}
+
+/// Limits on everything stored, whatever the backend
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct QuotaSettings {
+    /// Most pastes stored at once; unlimited when unset
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_pastes: Option<u64>,
+    /// Most bytes of paste content stored at once, counted before
+    /// compression and deduplication; unlimited when unset
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub policy: QuotaPolicy,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct LimitSettings {
+    /// Largest paste body accepted, in bytes
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_paste_bytes: usize,
+}
+
+/// Limits on how fast each client can make requests, by IP address
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RateLimitSettings {
+    /// Proxies whose `X-Forwarded-For` header is believed, as addresses or
+    /// CIDR ranges. Paste URLs follow their other forwarding headers too.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Requests that create, replace or delete pastes; unlimited when unset
+    #[serde(default)]
+    pub create: Option<BucketSettings>,
+    /// Requests that only read, such as fetching pastes; unlimited when unset
+    #[serde(default)]
+    pub read: Option<BucketSettings>,
+    /// Wrong guesses at the password of any one paste; unlimited when unset
+    #[serde(default)]
+    pub passwords: Option<BucketSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct BucketSettings {
+    /// Requests a client can make at once
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    /// Requests a client can make per minute, sustained
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_minute: u32,
+}
+
+/// How ids are generated for pastes created without one
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdStrategy {
+    /// The default: 122 random bits, far beyond guessing
+    Uuid,
+    /// Opt-in, and only as hard to guess as `length` allows
+    Base62,
+    /// Opt-in, and easy to read out but easy to guess too
+    Words,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct IdSettings {
+    pub strategy: IdStrategy,
+    /// Number of characters in a `base62` id; ignored otherwise
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub length: usize,
+}
+
+impl IdSettings {
+    pub fn generator(&self) -> Result<Arc<dyn IdGenerator>, String> {
+        Ok(match self.strategy {
+            IdStrategy::Uuid => Arc::new(UuidGenerator),
+            IdStrategy::Base62 => Arc::new(Base62Generator::new(self.length)?),
+            IdStrategy::Words => Arc::new(WordsGenerator),
+        })
+    }
 }
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub application: ApplicationSettings,
+    pub storage: StorageSettings,
+    pub limits: LimitSettings,
+    pub rate_limits: RateLimitSettings,
+    pub ids: IdSettings,
 }
 
 pub fn get_config() -> Result<Settings, config::ConfigError> {
@@ -0,0 +1,1326 @@
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{
+    repository::{blocking, EvictionOrder, Inserted, RepositoryError, Usage},
+    KeyStore, Repository,
+};
+use crate::{
+    compression::{self, Codec},
+    domain::{
+        ApiKeyHash, ContentHash, EncodedContent, Encryption, NewPaste, OwnerTokenHash, Paste,
+        PasteContent, PasteId, PastePasswordHash, StoredApiKey, Visibility,
+    },
+    keyring::{self, Keyring},
+};
+
+/// Number of locks guarding read-modify-write cycles on paste files. Pastes
+/// hash onto a lock, so unrelated pastes rarely contend.
+const LOCK_STRIPES: usize = 64;
+
+/// Directory beneath the root holding paste content, one file per distinct
+/// content.
+const BLOBS_DIR: &str = "blobs";
+
+/// Directory beneath the root holding API keys, one file per key named after
+/// the key's name.
+const KEYS_DIR: &str = "keys";
+
+/// Stores each paste as a single file beneath `root`.
+///
+/// Files are named after the SHA-256 of the paste id and sharded into two
+/// levels of directories by the leading bytes of that digest, e.g.
+/// `root/3f/a9/3fa9...`. Hashing keeps arbitrary ids out of the path and
+/// spreads pastes evenly across shards.
+///
+/// Each file holds a one-line JSON header. The content is kept apart under
+/// `root/blobs`, named and sharded by its SHA-256, so identical pastes share a
+/// single copy; a `.refs` sidecar counts the pastes holding each blob, and an
+/// `.encoding` sidecar names the content coding of a compressed one. Content
+/// encrypted at rest is kept in a `.sealed` file instead, headed by a line
+/// naming its key. The view count likewise lives in a `.views` sidecar so
+/// that counting a view does not rewrite the header; its modification time is
+/// when the paste was last read. API keys are kept apart under `root/keys`.
+pub struct FileSystemRepository {
+    store: Arc<Store>,
+}
+
+impl FileSystemRepository {
+    /// Use `root` as the storage directory, creating it if needed.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, RepositoryError> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+        Ok(Self {
+            store: Arc::new(Store {
+                root,
+                locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+                blob_locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+                codec: None,
+                keyring: None,
+            }),
+        })
+    }
+
+    /// Compress new content with `codec`. Content stored before, whatever it
+    /// was compressed with, stays readable.
+    pub fn with_compression(self, codec: Option<Arc<dyn Codec>>) -> Self {
+        let store = Arc::into_inner(self.store).expect("the store is not shared until in use");
+
+        Self {
+            store: Arc::new(Store { codec, ..store }),
+        }
+    }
+
+    /// Encrypt new content with the current key of `keyring`, and read back
+    /// content encrypted with any of its keys.
+    pub fn with_encryption(self, keyring: Option<Arc<Keyring>>) -> Self {
+        let store = Arc::into_inner(self.store).expect("the store is not shared until in use");
+
+        Self {
+            store: Arc::new(Store { keyring, ..store }),
+        }
+    }
+
+    /// Re-encrypt every blob not stored under the current key with it, or
+    /// store it in the clear if there is none, returning how many blobs and
+    /// pastes were rewritten. Pastes that still hold their content inline, or
+    /// in a blob other than the one it is now indexed by, are moved onto that
+    /// blob first, which reads the content of every paste.
+    pub async fn reencrypt(&self) -> Result<usize, RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, |store| store.reencrypt())
+            .await
+    }
+
+    /// Run `f` against the store on the blocking thread pool.
+    async fn with_store<T, F>(
+        &self,
+        err: fn(String) -> RepositoryError,
+        f: F,
+    ) -> Result<T, RepositoryError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Store) -> Result<T, RepositoryError> + Send + 'static,
+    {
+        let store = Arc::clone(&self.store);
+        blocking(err, move || f(&store)).await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    id: String,
+    /// Missing for pastes written before it was recorded; the file's
+    /// modification time stands in.
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    /// Missing for pastes written before it was recorded; the content length
+    /// stands in.
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    burn_after_reading: bool,
+    #[serde(default)]
+    owner_token_hash: Option<String>,
+    #[serde(default)]
+    language: Option<String>,
+    /// Missing for pastes written before it was recorded, which are public
+    #[serde(default)]
+    visibility: Option<String>,
+    #[serde(default)]
+    encryption: Option<String>,
+    /// The blob holding the content. Missing for pastes written before
+    /// content was deduplicated, which hold it inline after the header.
+    #[serde(default)]
+    content_hash: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    password_hash: Option<String>,
+}
+
+impl Header {
+    fn from_new_paste(entity: &NewPaste, hash: &ContentHash) -> Self {
+        Self {
+            id: entity.id.as_ref().to_owned(),
+            created_at: Some(entity.created_at),
+            updated_at: None,
+            expires_at: entity.expires_at,
+            size: Some(entity.size()),
+            content_type: entity.content_type.clone(),
+            burn_after_reading: entity.burn_after_reading,
+            owner_token_hash: Some(entity.owner_token_hash.as_ref().to_owned()),
+            language: entity.language.clone(),
+            visibility: Some(entity.visibility.as_ref().to_owned()),
+            encryption: entity.encryption.map(|e| e.as_ref().to_owned()),
+            content_hash: Some(hash.as_ref().to_owned()),
+            owner: entity.owner.clone(),
+            password_hash: entity.password_hash.as_ref().map(|h| h.as_ref().to_owned()),
+        }
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    fn content_hash(&self) -> Result<Option<ContentHash>, RepositoryError> {
+        self.content_hash
+            .clone()
+            .map(ContentHash::parse)
+            .transpose()
+            .map_err(RepositoryError::ReadFailure)
+    }
+
+    fn is_public(&self) -> bool {
+        self.visibility
+            .as_deref()
+            .is_none_or(|v| v == Visibility::Public.as_ref())
+    }
+}
+
+/// A paste file as read from disk. For pastes whose content lives in a blob,
+/// `content` stays empty until `Store::read_record` fills it in.
+struct Record {
+    header: Header,
+    content: Vec<u8>,
+    /// The blob as stored, when it is compressed
+    encoded: Option<EncodedContent>,
+    modified: Option<DateTime<Utc>>,
+}
+
+impl Record {
+    fn read(path: &Path, id: &PasteId) -> Result<Self, RepositoryError> {
+        let mut bytes = match fs::read(path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(RepositoryError::NotFound(id.as_ref().to_owned()))
+            }
+            Err(e) => return Err(RepositoryError::ReadFailure(e.to_string())),
+        };
+
+        let split = bytes
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| RepositoryError::ReadFailure("missing paste header".to_string()))?;
+
+        let content = bytes.split_off(split + 1);
+        let header = parse_header(&bytes[..split])?;
+        let modified = modified_at(path);
+
+        Ok(Self {
+            header,
+            content,
+            encoded: None,
+            modified,
+        })
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, RepositoryError> {
+        let mut bytes = serde_json::to_vec(&self.header)
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+        bytes.push(b'\n');
+        if self.header.content_hash.is_none() {
+            bytes.extend_from_slice(&self.content);
+        }
+
+        Ok(bytes)
+    }
+
+    fn into_paste(
+        self,
+        views: u64,
+        last_read_at: Option<DateTime<Utc>>,
+    ) -> Result<Paste, RepositoryError> {
+        let size = self.header.size.unwrap_or(self.content.len() as u64);
+        let header = self.header;
+
+        Ok(Paste {
+            id: PasteId::parse(header.id).map_err(RepositoryError::ReadFailure)?,
+            // Size is only limited when a paste is written, not when read back.
+            content: PasteContent::parse_bytes(self.content.into(), usize::MAX)
+                .map_err(RepositoryError::ReadFailure)?,
+            encoded: self.encoded,
+            created_at: header
+                .created_at
+                .or(self.modified)
+                .unwrap_or(DateTime::UNIX_EPOCH),
+            updated_at: header.updated_at,
+            expires_at: header.expires_at,
+            size,
+            content_type: header.content_type,
+            language: header.language,
+            visibility: header
+                .visibility
+                .map(Visibility::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?
+                .unwrap_or_default(),
+            encryption: header
+                .encryption
+                .map(Encryption::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+            views,
+            last_read_at,
+            burn_after_reading: header.burn_after_reading,
+            owner_token_hash: header
+                .owner_token_hash
+                .map(OwnerTokenHash::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+            owner: header.owner,
+            password_hash: header
+                .password_hash
+                .map(PastePasswordHash::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+        })
+    }
+}
+
+/// An API key as kept in its file.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    name: String,
+    key_hash: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<&StoredApiKey> for KeyFile {
+    fn from(key: &StoredApiKey) -> Self {
+        Self {
+            name: key.name.clone(),
+            key_hash: key.hash.as_ref().to_owned(),
+            created_at: key.created_at,
+        }
+    }
+}
+
+impl KeyFile {
+    fn into_key(self) -> Result<StoredApiKey, RepositoryError> {
+        Ok(StoredApiKey {
+            name: self.name,
+            hash: ApiKeyHash::parse(self.key_hash).map_err(RepositoryError::ReadFailure)?,
+            created_at: self.created_at,
+        })
+    }
+}
+
+/// What eviction needs to know of a paste.
+struct Summary {
+    id: String,
+    size: u64,
+    /// When the paste was created or last read, by the eviction order asked for
+    since: DateTime<Utc>,
+}
+
+struct Store {
+    root: PathBuf,
+    locks: Vec<Mutex<()>>,
+    /// Guard blob reference counts. Always taken after any paste lock, never
+    /// before.
+    blob_locks: Vec<Mutex<()>>,
+    codec: Option<Arc<dyn Codec>>,
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl Store {
+    fn path_for(&self, id: &PasteId) -> PathBuf {
+        let digest = Sha256::digest(id.as_ref().as_bytes());
+        let name = hex::encode(digest);
+
+        self.root.join(&name[0..2]).join(&name[2..4]).join(name)
+    }
+
+    fn blob_path(&self, hash: &ContentHash) -> PathBuf {
+        let name = hash.as_ref();
+
+        self.root
+            .join(BLOBS_DIR)
+            .join(&name[0..2])
+            .join(&name[2..4])
+            .join(name)
+    }
+
+    /// Serialise read-modify-write cycles on the paste at `path`. A poisoned
+    /// lock guards no data of its own, so it is simply reclaimed.
+    fn lock(&self, path: &Path) -> MutexGuard<'_, ()> {
+        stripe(&self.locks, path)
+    }
+
+    /// Store `content` as a blob unless it already is, and count one more
+    /// paste holding it.
+    fn acquire_blob(&self, content: &[u8]) -> Result<ContentHash, RepositoryError> {
+        let hash = keyring::index(self.keyring.as_deref(), ContentHash::of(content));
+        let path = self.blob_path(&hash);
+        let _guard = stripe(&self.blob_locks, &path);
+
+        if !path.exists() && !sealed_path(&path).exists() {
+            let write_err = |e: io::Error| RepositoryError::WriteFailure(e.to_string());
+            let (encoding, compressed) =
+                compression::compress(self.codec.as_deref(), content).map_err(write_err)?;
+
+            // The encoding goes first, so a blob is never seen without it
+            match encoding {
+                Some(encoding) => write_atomic(&encoding_path(&path), encoding.as_bytes(), true),
+                None => remove_if_exists(&encoding_path(&path)),
+            }
+            .map_err(write_err)?;
+
+            self.write_blob(&path, &hash, compressed)
+                .map_err(write_err)?;
+        }
+
+        let refs = read_count(&refs_path(&path))? + 1;
+        write_atomic(&refs_path(&path), refs.to_string().as_bytes(), true)
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+        Ok(hash)
+    }
+
+    /// Count one fewer paste holding a blob, removing the blob once none do.
+    fn release_blob(&self, hash: &ContentHash) -> Result<(), RepositoryError> {
+        let path = self.blob_path(hash);
+        let _guard = stripe(&self.blob_locks, &path);
+
+        let refs = read_count(&refs_path(&path))?.saturating_sub(1);
+        if refs > 0 {
+            return write_atomic(&refs_path(&path), refs.to_string().as_bytes(), true)
+                .map_err(|e| RepositoryError::WriteFailure(e.to_string()));
+        }
+
+        for file in [
+            path.clone(),
+            sealed_path(&path),
+            refs_path(&path),
+            encoding_path(&path),
+        ] {
+            remove_if_exists(&file).map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Release the blob a paste held, if it held one.
+    fn release_header_blob(&self, header: &Header) -> Result<(), RepositoryError> {
+        match header.content_hash()? {
+            Some(hash) => self.release_blob(&hash),
+            None => Ok(()),
+        }
+    }
+
+    /// Store the compressed content of the blob at `path`, encrypted if
+    /// there is a current key, in the clear if not. The new form is in place
+    /// before the old one is removed, and read in preference to it.
+    fn write_blob(&self, path: &Path, hash: &ContentHash, compressed: Vec<u8>) -> io::Result<()> {
+        match keyring::seal(self.keyring.as_deref(), hash, compressed)? {
+            (Some(key_id), stored) => {
+                let mut sealed = format!("{}\n", key_id).into_bytes();
+                sealed.extend_from_slice(&stored);
+                write_atomic(&sealed_path(path), &sealed, true)?;
+                remove_if_exists(path)
+            }
+            (None, stored) => {
+                write_atomic(path, &stored, true)?;
+                remove_if_exists(&sealed_path(path))
+            }
+        }
+    }
+
+    /// Read the blob at `path` as stored, decrypting it if need be. Returns
+    /// its content coding, the id of the key it was encrypted with and its
+    /// compressed content.
+    ///
+    /// Callers hold the blob's lock, as re-encryption rewrites blobs in place.
+    fn read_blob(
+        &self,
+        path: &Path,
+        hash: &ContentHash,
+    ) -> io::Result<(Option<String>, Option<String>, Vec<u8>)> {
+        let encoding = match fs::read_to_string(encoding_path(path)) {
+            Ok(e) => Some(e),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let (key_id, stored) = match fs::read(sealed_path(path)) {
+            Ok(mut sealed) => {
+                let split = sealed.iter().position(|b| *b == b'\n').ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "missing encryption key id")
+                })?;
+                let stored = sealed.split_off(split + 1);
+                sealed.truncate(split);
+                let key_id = String::from_utf8(sealed)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                (Some(key_id), stored)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (None, fs::read(path)?),
+            Err(e) => return Err(e),
+        };
+
+        let compressed = keyring::open(self.keyring.as_deref(), key_id.clone(), hash, stored)?;
+
+        Ok((encoding, key_id, compressed))
+    }
+
+    /// Read the paste at `path` along with its content.
+    fn read_record(&self, path: &Path, id: &PasteId) -> Result<Record, RepositoryError> {
+        let mut record = Record::read(path, id)?;
+
+        if let Some(hash) = record.header.content_hash()? {
+            let path = self.blob_path(&hash);
+            let read_err = |e: io::Error| {
+                RepositoryError::ReadFailure(format!("blob {}: {}", hash.as_ref(), e))
+            };
+
+            let (encoding, _, compressed) = {
+                let _guard = stripe(&self.blob_locks, &path);
+                self.read_blob(&path, &hash).map_err(read_err)?
+            };
+
+            (record.content, record.encoded) =
+                compression::decompress(encoding, compressed).map_err(read_err)?;
+        }
+
+        Ok(record)
+    }
+
+    fn find_one(&self, id: &PasteId) -> Result<Paste, RepositoryError> {
+        let path = self.path_for(id);
+        // Held until the blob is read too, or a concurrent write could
+        // release the blob the header names in between
+        let _guard = self.lock(&path);
+        let record = self.read_record(&path, id)?;
+
+        record.into_paste(read_views(&path)?, modified_at(&views_path(&path)))
+    }
+
+    fn find_by_hash(&self, hash: &ContentHash) -> Result<PasteId, RepositoryError> {
+        let files =
+            paste_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let key = keyring::index(self.keyring.as_deref(), hash.clone());
+        let now = Utc::now();
+
+        for file in files {
+            let header = match read_header(&file) {
+                Ok(h) => h,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable paste file {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+
+            if header.content_hash.as_deref() == Some(key.as_ref())
+                && header.is_public()
+                && !header.burn_after_reading
+                && header.password_hash.is_none()
+                && !header.is_expired(now)
+            {
+                return PasteId::parse(header.id).map_err(RepositoryError::ReadFailure);
+            }
+        }
+
+        Err(RepositoryError::NotFound(hash.as_ref().to_owned()))
+    }
+
+    fn find_by_owner(&self, owner: &str) -> Result<Vec<Paste>, RepositoryError> {
+        let files =
+            paste_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let mut pastes = Vec::new();
+
+        for file in files {
+            let header = match read_header(&file) {
+                Ok(h) => h,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable paste file {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+
+            if header.owner.as_deref() == Some(owner) {
+                let id = PasteId::parse(header.id).map_err(RepositoryError::ReadFailure)?;
+                match self.find_one(&id) {
+                    Ok(paste) => pastes.push(paste),
+                    Err(RepositoryError::NotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(pastes)
+    }
+
+    fn key_path(&self, name: &str) -> PathBuf {
+        self.root.join(KEYS_DIR).join(name)
+    }
+
+    fn insert_key(&self, key: &StoredApiKey) -> Result<(), RepositoryError> {
+        let bytes = serde_json::to_vec(&KeyFile::from(key))
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+        match write_atomic(&self.key_path(&key.name), &bytes, false) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                Err(RepositoryError::Conflict(key.name.clone()))
+            }
+            Err(e) => Err(RepositoryError::WriteFailure(e.to_string())),
+        }
+    }
+
+    fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError> {
+        let read_err = |e: io::Error| RepositoryError::ReadFailure(e.to_string());
+        let entries = match fs::read_dir(self.root.join(KEYS_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(read_err(e)),
+        };
+        let mut keys = Vec::new();
+
+        for entry in entries {
+            let path = entry.map_err(read_err)?.path();
+            // Skip in-flight temporary files
+            if path.extension().is_some() {
+                continue;
+            }
+
+            let bytes = match fs::read(&path) {
+                Ok(b) => b,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(read_err(e)),
+            };
+            let file: KeyFile = serde_json::from_slice(&bytes)
+                .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+            keys.push(file.into_key()?);
+        }
+
+        Ok(keys)
+    }
+
+    fn delete_key(&self, name: &str) -> Result<(), RepositoryError> {
+        match fs::remove_file(self.key_path(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(RepositoryError::NotFound(name.to_owned()))
+            }
+            Err(e) => Err(RepositoryError::WriteFailure(e.to_string())),
+        }
+    }
+
+    /// The id, size and eviction time of every paste, read from the headers
+    /// alone.
+    fn summaries(&self, order: EvictionOrder) -> Result<Vec<Summary>, RepositoryError> {
+        let files =
+            paste_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let mut summaries = Vec::with_capacity(files.len());
+
+        for file in files {
+            let header = match read_header(&file) {
+                Ok(h) => h,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable paste file {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+
+            let modified = modified_at(&file);
+            let created_at = header
+                .created_at
+                .or(modified)
+                .unwrap_or(DateTime::UNIX_EPOCH);
+            let since = match order {
+                EvictionOrder::LeastRecentlyRead => {
+                    modified_at(&views_path(&file)).unwrap_or(created_at)
+                }
+                EvictionOrder::Oldest => created_at,
+            };
+
+            summaries.push(Summary {
+                // Pastes from before sizes were recorded hold their content
+                // inline, so the file's length stands in, header and all.
+                size: header
+                    .size
+                    .unwrap_or_else(|| fs::metadata(&file).map(|m| m.len()).unwrap_or_default()),
+                id: header.id,
+                since,
+            });
+        }
+
+        Ok(summaries)
+    }
+
+    fn usage(&self) -> Result<Usage, RepositoryError> {
+        let summaries = self.summaries(EvictionOrder::Oldest)?;
+
+        Ok(Usage {
+            pastes: summaries.len() as u64,
+            bytes: summaries.iter().map(|s| s.size).sum(),
+        })
+    }
+
+    fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError> {
+        let mut summaries = self.summaries(order)?;
+        summaries.sort_by(|a, b| (a.since, &a.id).cmp(&(b.since, &b.id)));
+
+        summaries
+            .into_iter()
+            .take(limit)
+            .map(|s| {
+                Ok((
+                    PasteId::parse(s.id).map_err(RepositoryError::ReadFailure)?,
+                    s.size,
+                ))
+            })
+            .collect()
+    }
+
+    /// Create the paste unless a live one already exists under the same id. An
+    /// expired paste that has not been purged yet is replaced.
+    fn insert(&self, entity: &NewPaste) -> Result<Inserted, RepositoryError> {
+        let hash = self.acquire_blob(entity.content.as_ref())?;
+
+        let inserted = self.insert_header(entity, &hash);
+        if inserted.is_err() {
+            self.release_blob(&hash)?;
+        }
+
+        inserted
+    }
+
+    fn insert_header(
+        &self,
+        entity: &NewPaste,
+        hash: &ContentHash,
+    ) -> Result<Inserted, RepositoryError> {
+        let path = self.path_for(&entity.id);
+        let record = Record {
+            header: Header::from_new_paste(entity, hash),
+            content: Vec::new(),
+            encoded: None,
+            modified: None,
+        };
+        let bytes = record.encode()?;
+
+        let _guard = self.lock(&path);
+
+        match write_atomic(&path, &bytes, false) {
+            Ok(()) => return remove_views(&path).map(|()| Inserted::Created),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(RepositoryError::WriteFailure(e.to_string())),
+        }
+
+        let expired = read_header(&path)?;
+        if !expired.is_expired(Utc::now()) {
+            return Err(RepositoryError::Conflict(entity.id.as_ref().to_owned()));
+        }
+
+        write_atomic(&path, &bytes, true)
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+        remove_views(&path)?;
+        self.release_header_blob(&expired)?;
+
+        Ok(Inserted::ReplacedExpired)
+    }
+
+    fn update(
+        &self,
+        id: &PasteId,
+        content: &PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let path = self.path_for(id);
+        let _guard = self.lock(&path);
+
+        let mut record = Record::read(&path, id)?;
+        let replaced = record.header.content_hash()?;
+
+        let hash = self.acquire_blob(content.as_ref())?;
+        record.header.content_hash = Some(hash.as_ref().to_owned());
+        record.header.size = Some(content.len() as u64);
+        record.header.content_type = content_type;
+        record.header.updated_at = Some(Utc::now());
+        if record.header.created_at.is_none() {
+            record.header.created_at = record.modified;
+        }
+
+        if let Err(e) = write_atomic(&path, &record.encode()?, true) {
+            self.release_blob(&hash)?;
+            return Err(RepositoryError::WriteFailure(e.to_string()));
+        }
+
+        match replaced {
+            Some(replaced) => self.release_blob(&replaced),
+            None => Ok(()),
+        }
+    }
+
+    fn record_view(&self, id: &PasteId) -> Result<(), RepositoryError> {
+        let path = self.path_for(id);
+        let _guard = self.lock(&path);
+
+        if !path.exists() {
+            return Err(RepositoryError::NotFound(id.as_ref().to_owned()));
+        }
+
+        let views = read_views(&path)? + 1;
+        write_atomic(&views_path(&path), views.to_string().as_bytes(), true)
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))
+    }
+
+    /// Claim the paste by renaming it to a unique temporary name, then read
+    /// and delete it. Renames are atomic, so only one concurrent caller can
+    /// claim a given file; the rest see it as missing. A claim left behind by
+    /// a crash is swept up by [`Store::purge_taken`].
+    fn take(&self, id: &PasteId) -> Result<Paste, RepositoryError> {
+        let path = self.path_for(id);
+        let _guard = self.lock(&path);
+
+        let claimed = taken_path(&path);
+
+        match fs::rename(&path, &claimed) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(RepositoryError::NotFound(id.as_ref().to_owned()))
+            }
+            Err(e) => return Err(RepositoryError::WriteFailure(e.to_string())),
+        }
+
+        let views = read_views(&path)?;
+        let last_read_at = modified_at(&views_path(&path));
+        let record = self.read_record(&claimed, id);
+        let _ = fs::remove_file(&claimed);
+        remove_views(&path)?;
+
+        let record = record?;
+        self.release_header_blob(&record.header)?;
+
+        record.into_paste(views, last_read_at)
+    }
+
+    fn delete(&self, id: &PasteId) -> Result<(), RepositoryError> {
+        let path = self.path_for(id);
+        let _guard = self.lock(&path);
+
+        let header = match read_header(&path) {
+            Ok(h) => h,
+            Err(RepositoryError::NotFound(_)) => {
+                return Err(RepositoryError::NotFound(id.as_ref().to_owned()))
+            }
+            Err(e) => return Err(e),
+        };
+
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                remove_views(&path)?;
+                self.release_header_blob(&header)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(RepositoryError::NotFound(id.as_ref().to_owned()))
+            }
+            Err(e) => Err(RepositoryError::WriteFailure(e.to_string())),
+        }
+    }
+
+    fn reencrypt(&self) -> Result<usize, RepositoryError> {
+        let mut rewritten = 0;
+
+        let files =
+            paste_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        for file in files {
+            if self.move_content(&file)? {
+                rewritten += 1;
+            }
+        }
+
+        let hashes = blob_hashes(&self.root.join(BLOBS_DIR))
+            .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let current = self.keyring.as_deref().and_then(Keyring::current);
+
+        for hash in hashes {
+            let path = self.blob_path(&hash);
+            let write_err = |e: io::Error| {
+                RepositoryError::WriteFailure(format!("blob {}: {}", hash.as_ref(), e))
+            };
+            let _guard = stripe(&self.blob_locks, &path);
+
+            let (_, key_id, compressed) = match self.read_blob(&path, &hash) {
+                Ok(blob) => blob,
+                // Released since the blobs were listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(write_err(e)),
+            };
+            if key_id.as_deref() != current {
+                self.write_blob(&path, &hash, compressed)
+                    .map_err(write_err)?;
+                rewritten += 1;
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Move the content of a paste onto the blob it is now indexed by, if it
+    /// is held anywhere else: inline, for pastes written before content was
+    /// deduplicated, or in a blob stored under another hash. Returns whether
+    /// there was any to move.
+    fn move_content(&self, path: &Path) -> Result<bool, RepositoryError> {
+        let _guard = self.lock(path);
+
+        let header = match read_header(path) {
+            Ok(h) => h,
+            Err(RepositoryError::NotFound(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let id = PasteId::parse(header.id).map_err(RepositoryError::ReadFailure)?;
+        let mut record = self.read_record(path, &id)?;
+        let old = record.header.content_hash()?;
+
+        let indexed = keyring::index(self.keyring.as_deref(), ContentHash::of(&record.content));
+        if old.as_ref() == Some(&indexed) {
+            return Ok(false);
+        }
+
+        let hash = self.acquire_blob(&record.content)?;
+        record.header.content_hash = Some(hash.as_ref().to_owned());
+        // Rewriting the file moves its modification time on
+        if record.header.created_at.is_none() {
+            record.header.created_at = record.modified;
+        }
+
+        if let Err(e) = write_atomic(path, &record.encode()?, true) {
+            self.release_blob(&hash)?;
+            return Err(RepositoryError::WriteFailure(e.to_string()));
+        }
+
+        match old {
+            Some(old) => self.release_blob(&old).map(|()| true),
+            None => Ok(true),
+        }
+    }
+
+    fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError> {
+        let files =
+            paste_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let mut purged = self.purge_taken()?;
+
+        for file in files {
+            let _guard = self.lock(&file);
+
+            let header = match read_header(&file) {
+                Ok(h) => h,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable paste file {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+
+            if header.is_expired(now) {
+                match fs::remove_file(&file) {
+                    Ok(()) => {
+                        purged += 1;
+                        self.release_header_blob(&header)?;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(RepositoryError::WriteFailure(e.to_string())),
+                }
+                remove_views(&file)?;
+            }
+        }
+
+        Ok(purged)
+    }
+
+    /// Remove the pastes claimed by a burn-after-reading read that never
+    /// finished, releasing their blobs, and return how many there were.
+    fn purge_taken(&self) -> Result<usize, RepositoryError> {
+        let files =
+            sharded_files(&self.root).map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+        let mut purged = 0;
+
+        for claimed in files {
+            let Some(path) = claimed_paste(&claimed) else {
+                continue;
+            };
+            // A read still in flight holds this lock until its claim is gone
+            let _guard = self.lock(&path);
+
+            let header = match read_header(&claimed) {
+                Ok(h) => h,
+                Err(RepositoryError::NotFound(_)) => continue,
+                Err(e) => {
+                    tracing::warn!(
+                        "Skipping unreadable paste file {}: {}",
+                        claimed.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            match fs::remove_file(&claimed) {
+                Ok(()) => {
+                    purged += 1;
+                    self.release_header_blob(&header)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(RepositoryError::WriteFailure(e.to_string())),
+            }
+            if !path.exists() {
+                remove_views(&path)?;
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+/// Write `bytes` to a temporary sibling of `path` and move it into place, so
+/// readers never observe a partially written file.
+///
+/// Without `overwrite` the file is linked rather than renamed into place,
+/// which fails with `AlreadyExists` instead of clobbering an existing paste.
+fn write_atomic(path: &Path, bytes: &[u8], overwrite: bool) -> io::Result<()> {
+    let dir = path.parent().expect("paste paths always have a parent");
+    fs::create_dir_all(dir)?;
+
+    let tmp = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+
+        if overwrite {
+            fs::rename(&tmp, path)
+        } else {
+            fs::hard_link(&tmp, path)
+        }
+    })();
+
+    if result.is_err() || !overwrite {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Lock the stripe of `locks` that `path` hashes onto.
+fn stripe<'a>(locks: &'a [Mutex<()>], path: &Path) -> MutexGuard<'a, ()> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let stripe = hasher.finish() as usize % locks.len();
+
+    locks[stripe]
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn parse_header(line: &[u8]) -> Result<Header, RepositoryError> {
+    serde_json::from_slice(line).map_err(|e| RepositoryError::ReadFailure(e.to_string()))
+}
+
+fn read_header(path: &Path) -> Result<Header, RepositoryError> {
+    let file = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(RepositoryError::NotFound(path.display().to_string()))
+        }
+        Err(e) => return Err(RepositoryError::ReadFailure(e.to_string())),
+    };
+    let mut line = Vec::new();
+    BufReader::new(file)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+
+    parse_header(&line)
+}
+
+/// When the file at `path` was last modified, if it exists.
+fn modified_at(path: &Path) -> Option<DateTime<Utc>> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from)
+}
+
+fn views_path(path: &Path) -> PathBuf {
+    path.with_extension("views")
+}
+
+/// A unique name to claim the paste at `path` under, from which
+/// [`claimed_paste`] recovers `path`.
+fn taken_path(path: &Path) -> PathBuf {
+    let name = path.file_name().expect("paste paths always have a name");
+
+    path.with_file_name(format!(
+        ".{}.{}.taken",
+        name.to_string_lossy(),
+        Uuid::new_v4()
+    ))
+}
+
+/// The paste a file made by [`taken_path`] was claimed from, or `None` for
+/// any other file.
+fn claimed_paste(claimed: &Path) -> Option<PathBuf> {
+    let name = claimed.file_name()?.to_str()?;
+    let (paste, _) = name
+        .strip_prefix('.')?
+        .strip_suffix(".taken")?
+        .split_once('.')?;
+
+    Some(claimed.with_file_name(paste))
+}
+
+fn refs_path(path: &Path) -> PathBuf {
+    path.with_extension("refs")
+}
+
+fn encoding_path(path: &Path) -> PathBuf {
+    path.with_extension("encoding")
+}
+
+fn sealed_path(path: &Path) -> PathBuf {
+    path.with_extension("sealed")
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn read_views(path: &Path) -> Result<u64, RepositoryError> {
+    read_count(&views_path(path))
+}
+
+/// Read a counter sidecar, which counts from zero when missing.
+fn read_count(path: &Path) -> Result<u64, RepositoryError> {
+    match fs::read_to_string(path) {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map_err(|_| RepositoryError::ReadFailure(format!("invalid count {}", v))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(RepositoryError::ReadFailure(e.to_string())),
+    }
+}
+
+fn remove_views(path: &Path) -> Result<(), RepositoryError> {
+    match fs::remove_file(views_path(path)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(RepositoryError::WriteFailure(e.to_string())),
+    }
+}
+
+/// Every paste file beneath `root`, skipping blobs, in-flight temporary files
+/// and sidecars.
+fn paste_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let files = sharded_files(root)?
+        .into_iter()
+        .filter(|file| {
+            file.file_name()
+                .is_some_and(|n| !n.to_string_lossy().contains('.'))
+        })
+        .collect();
+
+    Ok(files)
+}
+
+/// The hash of every blob beneath `dir`, whether stored in the clear or
+/// sealed.
+fn blob_hashes(dir: &Path) -> io::Result<Vec<ContentHash>> {
+    let files = match sharded_files(dir) {
+        Ok(files) => files,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut hashes: Vec<ContentHash> = files
+        .iter()
+        .filter(|file| file.extension().is_none_or(|e| e == "sealed"))
+        .filter_map(|file| file.file_stem()?.to_str())
+        .filter_map(|stem| ContentHash::parse(stem.to_owned()).ok())
+        .collect();
+    hashes.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    hashes.dedup();
+
+    Ok(hashes)
+}
+
+/// Every file two levels of shards beneath `root`, skipping the blobs and
+/// keys directories.
+fn sharded_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for outer in fs::read_dir(root)? {
+        let outer = outer?.path();
+        if !outer.is_dir()
+            || outer
+                .file_name()
+                .is_some_and(|n| n == BLOBS_DIR || n == KEYS_DIR)
+        {
+            continue;
+        }
+
+        for inner in fs::read_dir(outer)? {
+            let inner = inner?.path();
+            if !inner.is_dir() {
+                continue;
+            }
+
+            for file in fs::read_dir(inner)? {
+                let file = file?.path();
+                if file.is_file() {
+                    files.push(file);
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[async_trait]
+impl Repository for FileSystemRepository {
+    async fn find_one(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, move |store| {
+            store.find_one(&id)
+        })
+        .await
+    }
+
+    async fn find_by_hash(&self, hash: ContentHash) -> Result<PasteId, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, move |store| {
+            store.find_by_hash(&hash)
+        })
+        .await
+    }
+
+    async fn find_by_owner(&self, owner: String) -> Result<Vec<Paste>, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, move |store| {
+            store.find_by_owner(&owner)
+        })
+        .await
+    }
+
+    async fn insert(&self, entity: NewPaste) -> Result<Inserted, RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.insert(&entity)
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        id: PasteId,
+        content: PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.update(&id, &content, content_type)
+        })
+        .await
+    }
+
+    async fn record_view(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.record_view(&id)
+        })
+        .await
+    }
+
+    async fn take(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| store.take(&id))
+            .await
+    }
+
+    async fn delete(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.delete(&id)
+        })
+        .await
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.purge_expired(now)
+        })
+        .await
+    }
+
+    async fn usage(&self) -> Result<Usage, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, |store| store.usage())
+            .await
+    }
+
+    async fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, move |store| {
+            store.eviction_candidates(order, limit)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl KeyStore for FileSystemRepository {
+    async fn insert_key(&self, key: StoredApiKey) -> Result<(), RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.insert_key(&key)
+        })
+        .await
+    }
+
+    async fn find_key(&self, hash: ApiKeyHash) -> Result<StoredApiKey, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, move |store| {
+            store
+                .list_keys()?
+                .into_iter()
+                .find(|k| k.hash == hash)
+                .ok_or_else(|| RepositoryError::NotFound("API key".to_string()))
+        })
+        .await
+    }
+
+    async fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError> {
+        self.with_store(RepositoryError::ReadFailure, |store| store.list_keys())
+            .await
+    }
+
+    async fn delete_key(&self, name: String) -> Result<(), RepositoryError> {
+        self.with_store(RepositoryError::WriteFailure, move |store| {
+            store.delete_key(&name)
+        })
+        .await
+    }
+}
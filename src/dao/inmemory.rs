@@ -1,28 +1,235 @@
-use std::collections::HashMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
 
-use super::{repository::RepositoryError, Repository};
-use crate::domain::{PasteContent, PasteId};
+use super::{
+    repository::{EvictionOrder, Inserted, RepositoryError, Usage},
+    KeyStore, Repository,
+};
+use crate::domain::{
+    ApiKeyHash, ContentHash, NewPaste, Paste, PasteContent, PasteId, StoredApiKey, Visibility,
+};
 
+/// Keeps pastes in process memory. Nothing is ever at rest, so content is
+/// neither compressed nor encrypted.
+#[derive(Default)]
 pub struct InMemoryRepository {
-    data: HashMap<PasteId, PasteContent>,
+    data: DashMap<PasteId, Paste>,
+    /// Each distinct content, shared by the pastes holding it
+    blobs: DashMap<ContentHash, Blob>,
+    /// API keys by name
+    keys: DashMap<String, StoredApiKey>,
 }
 
-impl Repository for InMemoryRepository {
-    fn new() -> Self {
-        Self {
-            data: HashMap::new(),
+struct Blob {
+    content: PasteContent,
+    refs: usize,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a new reference to `content`, returning the shared copy to hold
+    /// in its place.
+    ///
+    /// Callers may hold a lock on `data`, so `blobs` is always locked second.
+    fn acquire(&self, content: PasteContent) -> PasteContent {
+        let mut blob = self
+            .blobs
+            .entry(content.hash())
+            .or_insert(Blob { content, refs: 0 });
+        blob.refs += 1;
+        blob.content.clone()
+    }
+
+    /// Drop a reference to `content`, forgetting it once nothing refers to it.
+    fn release(&self, content: &PasteContent) {
+        if let Entry::Occupied(mut e) = self.blobs.entry(content.hash()) {
+            e.get_mut().refs -= 1;
+            if e.get().refs == 0 {
+                e.remove();
+            }
         }
     }
+}
 
-    fn find_one(&self, id: PasteId) -> Result<PasteContent, super::repository::RepositoryError> {
+#[async_trait]
+impl Repository for InMemoryRepository {
+    async fn find_one(&self, id: PasteId) -> Result<Paste, RepositoryError> {
         match self.data.get(&id) {
             Some(d) => Ok(d.to_owned()),
             None => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
         }
     }
 
-    fn insert(&mut self, entity: crate::domain::NewPaste) -> Result<(), RepositoryError> {
-        self.data.insert(entity.id, entity.content);
-        Ok(())
+    async fn find_by_hash(&self, hash: ContentHash) -> Result<PasteId, RepositoryError> {
+        let content = match self.blobs.get(&hash) {
+            Some(b) => b.content.clone(),
+            None => return Err(RepositoryError::NotFound(hash.as_ref().to_owned())),
+        };
+        let now = Utc::now();
+
+        self.data
+            .iter()
+            .find(|p| {
+                p.visibility == Visibility::Public
+                    && !p.burn_after_reading
+                    && p.password_hash.is_none()
+                    && !p.is_expired(now)
+                    && p.content == content
+            })
+            .map(|p| p.id.clone())
+            .ok_or_else(|| RepositoryError::NotFound(hash.as_ref().to_owned()))
+    }
+
+    async fn find_by_owner(&self, owner: String) -> Result<Vec<Paste>, RepositoryError> {
+        Ok(self
+            .data
+            .iter()
+            .filter(|p| p.owner.as_ref() == Some(&owner))
+            .map(|p| p.to_owned())
+            .collect())
+    }
+
+    async fn insert(&self, entity: NewPaste) -> Result<Inserted, RepositoryError> {
+        let mut paste = Paste::from(entity);
+
+        match self.data.entry(paste.id.clone()) {
+            Entry::Occupied(mut e) if e.get().is_expired(Utc::now()) => {
+                paste.content = self.acquire(paste.content);
+                let expired = e.insert(paste);
+                self.release(&expired.content);
+                Ok(Inserted::ReplacedExpired)
+            }
+            Entry::Occupied(e) => Err(RepositoryError::Conflict(e.key().as_ref().to_owned())),
+            Entry::Vacant(e) => {
+                paste.content = self.acquire(paste.content);
+                e.insert(paste);
+                Ok(Inserted::Created)
+            }
+        }
+    }
+
+    async fn update(
+        &self,
+        id: PasteId,
+        content: PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        match self.data.get_mut(&id) {
+            Some(mut d) => {
+                d.size = content.len() as u64;
+                d.content_type = content_type;
+                let replaced = std::mem::replace(&mut d.content, self.acquire(content));
+                self.release(&replaced);
+                d.updated_at = Some(Utc::now());
+                Ok(())
+            }
+            None => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+        }
+    }
+
+    async fn record_view(&self, id: PasteId) -> Result<(), RepositoryError> {
+        match self.data.get_mut(&id) {
+            Some(mut d) => {
+                d.views += 1;
+                d.last_read_at = Some(Utc::now());
+                Ok(())
+            }
+            None => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+        }
+    }
+
+    async fn take(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        match self.data.remove(&id) {
+            Some((_, d)) => {
+                self.release(&d.content);
+                Ok(d)
+            }
+            None => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+        }
+    }
+
+    async fn delete(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.take(id).await.map(|_| ())
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError> {
+        let mut purged = 0;
+        self.data.retain(|_, paste| {
+            let expired = paste.is_expired(now);
+            if expired {
+                self.release(&paste.content);
+            }
+            purged += usize::from(expired);
+            !expired
+        });
+        Ok(purged)
+    }
+
+    async fn usage(&self) -> Result<Usage, RepositoryError> {
+        Ok(self.data.iter().fold(Usage::default(), |usage, p| Usage {
+            pastes: usage.pastes + 1,
+            bytes: usage.bytes + p.size,
+        }))
+    }
+
+    async fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError> {
+        let mut pastes: Vec<_> = self
+            .data
+            .iter()
+            .map(|p| {
+                let since = match order {
+                    EvictionOrder::LeastRecentlyRead => p.last_read_at.unwrap_or(p.created_at),
+                    EvictionOrder::Oldest => p.created_at,
+                };
+                (since, p.id.clone(), p.size)
+            })
+            .collect();
+        pastes.sort_by(|a, b| (a.0, a.1.as_ref()).cmp(&(b.0, b.1.as_ref())));
+
+        Ok(pastes
+            .into_iter()
+            .take(limit)
+            .map(|(_, id, size)| (id, size))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl KeyStore for InMemoryRepository {
+    async fn insert_key(&self, key: StoredApiKey) -> Result<(), RepositoryError> {
+        match self.keys.entry(key.name.clone()) {
+            Entry::Occupied(e) => Err(RepositoryError::Conflict(e.key().to_owned())),
+            Entry::Vacant(e) => {
+                e.insert(key);
+                Ok(())
+            }
+        }
+    }
+
+    async fn find_key(&self, hash: ApiKeyHash) -> Result<StoredApiKey, RepositoryError> {
+        self.keys
+            .iter()
+            .find(|k| k.hash == hash)
+            .map(|k| k.to_owned())
+            .ok_or_else(|| RepositoryError::NotFound("API key".to_string()))
+    }
+
+    async fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError> {
+        Ok(self.keys.iter().map(|k| k.to_owned()).collect())
+    }
+
+    async fn delete_key(&self, name: String) -> Result<(), RepositoryError> {
+        match self.keys.remove(&name) {
+            Some(_) => Ok(()),
+            None => Err(RepositoryError::NotFound(name)),
+        }
     }
 }
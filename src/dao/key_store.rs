@@ -0,0 +1,17 @@
+use async_trait::async_trait;
+
+use super::RepositoryError;
+use crate::domain::{ApiKeyHash, StoredApiKey};
+
+/// A store of API keys, kept by each backend alongside its pastes.
+#[async_trait]
+pub trait KeyStore: Sync + Send + 'static {
+    /// Store a new key, failing with `Conflict` if its name is taken.
+    async fn insert_key(&self, key: StoredApiKey) -> Result<(), RepositoryError>;
+    /// Find the key with the given hash.
+    async fn find_key(&self, hash: ApiKeyHash) -> Result<StoredApiKey, RepositoryError>;
+    /// Every key, in no particular order.
+    async fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError>;
+    /// Revoke the key with the given name. Pastes it owns are kept.
+    async fn delete_key(&self, name: String) -> Result<(), RepositoryError>;
+}
@@ -1,5 +1,13 @@
+mod filesystem;
 mod inmemory;
+mod key_store;
+mod quota;
 mod repository;
+mod sqlite;
 
+pub use filesystem::FileSystemRepository;
 pub use inmemory::InMemoryRepository;
+pub use key_store::KeyStore;
+pub use quota::QuotaRepository;
 pub use repository::*;
+pub use sqlite::SqliteRepository;
@@ -0,0 +1,355 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use super::{
+    repository::{EvictionOrder, Inserted, RepositoryError, Usage},
+    KeyStore, Repository,
+};
+use crate::{
+    config::QuotaSettings,
+    domain::{ApiKeyHash, ContentHash, NewPaste, Paste, PasteContent, PasteId, StoredApiKey},
+};
+
+/// Pastes fetched per round of eviction.
+const EVICTION_BATCH: usize = 32;
+
+/// Holds another repository to a quota on how many pastes it stores and how
+/// many bytes they take, whatever the backend.
+///
+/// Going over quota purges expired pastes first. If that is not enough, the
+/// policy decides whether writes fail with `InsufficientStorage` before they
+/// are made, or other pastes are evicted to make room once they succeed, so a
+/// write that fails costs no one their pastes. A paste too large for the
+/// quota on its own is always turned away.
+pub struct QuotaRepository<R> {
+    inner: R,
+    quota: QuotaSettings,
+    /// Usage as of the last write, counting the room claimed by writes still
+    /// in flight, or `None` when it has to be counted afresh. Room is claimed
+    /// under the lock, so concurrent writes cannot both count on it, but the
+    /// lock is not held while they write.
+    usage: Mutex<Option<Usage>>,
+}
+
+impl<R: Repository> QuotaRepository<R> {
+    pub fn new(inner: R, quota: QuotaSettings) -> Self {
+        Self {
+            inner,
+            quota,
+            usage: Mutex::new(None),
+        }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.quota.max_pastes.is_some() || self.quota.max_bytes.is_some()
+    }
+
+    /// Whether `usage` stays within quota after adding `pastes` pastes and
+    /// `bytes` bytes.
+    fn fits(&self, usage: Usage, pastes: u64, bytes: u64) -> bool {
+        self.quota
+            .max_pastes
+            .is_none_or(|max| usage.pastes + pastes <= max)
+            && self
+                .quota
+                .max_bytes
+                .is_none_or(|max| usage.bytes + bytes <= max)
+    }
+
+    /// Bring `usage` low enough to take `pastes` more pastes and `bytes` more
+    /// bytes, never evicting `keep`.
+    async fn make_room(
+        &self,
+        usage: &mut Usage,
+        pastes: u64,
+        bytes: u64,
+        keep: Option<&PasteId>,
+    ) -> Result<(), RepositoryError> {
+        if self.fits(*usage, pastes, bytes) {
+            return Ok(());
+        }
+
+        // Expired pastes are the first to go, whatever the policy
+        if self.inner.purge_expired(Utc::now()).await? > 0 {
+            *usage = self.inner.usage().await?;
+        }
+
+        while !self.fits(*usage, pastes, bytes) {
+            let Some(order) = self.quota.policy.eviction_order() else {
+                tracing::warn!(
+                    "Rejecting a paste: {} pastes of {} bytes are stored",
+                    usage.pastes,
+                    usage.bytes
+                );
+                return Err(RepositoryError::InsufficientStorage(
+                    "the storage quota is used up".to_string(),
+                ));
+            };
+
+            self.evict(usage, order, pastes, bytes, keep).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Evict one batch of candidates, stopping as soon as there is room.
+    async fn evict(
+        &self,
+        usage: &mut Usage,
+        order: EvictionOrder,
+        pastes: u64,
+        bytes: u64,
+        keep: Option<&PasteId>,
+    ) -> Result<(), RepositoryError> {
+        // One more than a batch, so skipping `keep` still leaves a full one
+        let candidates = self
+            .inner
+            .eviction_candidates(order, EVICTION_BATCH + 1)
+            .await?;
+        let mut evicted = 0;
+
+        for (id, size) in candidates.into_iter().filter(|(id, _)| Some(id) != keep) {
+            if self.fits(*usage, pastes, bytes) || evicted == EVICTION_BATCH {
+                break;
+            }
+
+            match self.inner.delete(id.clone()).await {
+                Ok(()) => {
+                    tracing::warn!("Evicted paste {} to stay within quota", id.as_ref());
+                    usage.pastes = usage.pastes.saturating_sub(1);
+                    usage.bytes = usage.bytes.saturating_sub(size);
+                }
+                // Deleted since it was listed, which makes room all the same
+                Err(RepositoryError::NotFound(_)) => {
+                    *usage = self.inner.usage().await?;
+                }
+                Err(e) => return Err(e),
+            }
+            evicted += 1;
+        }
+
+        if evicted == 0 {
+            return Err(RepositoryError::InsufficientStorage(
+                "nothing is left to evict".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fail if a paste of `size` bytes could never fit, however much was
+    /// evicted.
+    fn check_size(&self, size: u64) -> Result<(), RepositoryError> {
+        match self.fits(Usage::default(), 1, size) {
+            true => Ok(()),
+            false => Err(RepositoryError::InsufficientStorage(format!(
+                "a paste of {} bytes is larger than the storage quota",
+                size
+            ))),
+        }
+    }
+
+    /// Run a write of the paste `keep` that can grow usage by `pastes` pastes
+    /// and `bytes` bytes. Under the reject policy the room has to be there
+    /// beforehand; otherwise other pastes are evicted once the write is made.
+    /// Usage is counted afresh after writes that `recount` says may have
+    /// changed it by other than the room claimed.
+    async fn within_quota<T>(
+        &self,
+        pastes: u64,
+        bytes: u64,
+        keep: &PasteId,
+        write: impl std::future::Future<Output = Result<T, RepositoryError>>,
+        recount: impl FnOnce(&T) -> bool,
+    ) -> Result<T, RepositoryError> {
+        let evicting = self.quota.policy.eviction_order().is_some();
+        self.claim(pastes, bytes, !evicting).await?;
+
+        let written = match write.await {
+            Ok(written) => written,
+            Err(e) => {
+                // Hand back the room claimed
+                if let Some(usage) = self.usage.lock().await.as_mut() {
+                    usage.pastes = usage.pastes.saturating_sub(pastes);
+                    usage.bytes = usage.bytes.saturating_sub(bytes);
+                }
+                return Err(e);
+            }
+        };
+        if recount(&written) {
+            self.recount().await;
+        }
+
+        if evicting {
+            // The write stands, even if storage stays over quota for now
+            if let Err(e) = self.evict_to_quota(keep).await {
+                tracing::error!("Failed to evict pastes to get back within quota: {}", e);
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Count `pastes` pastes and `bytes` bytes as used, failing unless there
+    /// is room for them when it `must_fit`.
+    async fn claim(&self, pastes: u64, bytes: u64, must_fit: bool) -> Result<(), RepositoryError> {
+        let mut cached = self.usage.lock().await;
+        let mut usage = match *cached {
+            Some(usage) => usage,
+            None => self.inner.usage().await?,
+        };
+
+        // Count afresh next time should anything fail part way
+        *cached = None;
+        if must_fit {
+            self.make_room(&mut usage, pastes, bytes, None).await?;
+        }
+
+        usage.pastes += pastes;
+        usage.bytes += bytes;
+        *cached = Some(usage);
+
+        Ok(())
+    }
+
+    /// Evict pastes other than `keep` until usage is back within quota.
+    async fn evict_to_quota(&self, keep: &PasteId) -> Result<(), RepositoryError> {
+        let mut cached = self.usage.lock().await;
+        let mut usage = match *cached {
+            Some(usage) => usage,
+            None => self.inner.usage().await?,
+        };
+
+        *cached = None;
+        self.make_room(&mut usage, 0, 0, Some(keep)).await?;
+        *cached = Some(usage);
+
+        Ok(())
+    }
+
+    /// Forget the cached usage, after a write that shrank it by an unknown
+    /// amount.
+    async fn recount(&self) {
+        *self.usage.lock().await = None;
+    }
+}
+
+#[async_trait]
+impl<R: Repository> Repository for QuotaRepository<R> {
+    async fn find_one(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        self.inner.find_one(id).await
+    }
+
+    async fn find_by_hash(&self, hash: ContentHash) -> Result<PasteId, RepositoryError> {
+        self.inner.find_by_hash(hash).await
+    }
+
+    async fn find_by_owner(&self, owner: String) -> Result<Vec<Paste>, RepositoryError> {
+        self.inner.find_by_owner(owner).await
+    }
+
+    async fn insert(&self, entity: NewPaste) -> Result<Inserted, RepositoryError> {
+        if !self.is_limited() {
+            return self.inner.insert(entity).await;
+        }
+
+        let size = entity.size();
+        self.check_size(size)?;
+        let keep = entity.id.clone();
+        // The expired paste replaced was counted too, but is gone now
+        self.within_quota(1, size, &keep, self.inner.insert(entity), |inserted| {
+            *inserted == Inserted::ReplacedExpired
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        id: PasteId,
+        content: PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        if !self.is_limited() {
+            return self.inner.update(id, content, content_type).await;
+        }
+
+        // A concurrent update can replace the content this size is read from
+        // before this one does, so usage is counted afresh after every update
+        // rather than moved by the difference
+        let old = self.inner.find_one(id.clone()).await?.size;
+        let new = content.len() as u64;
+        if new <= old {
+            let updated = self.inner.update(id, content, content_type).await;
+            self.recount().await;
+            return updated;
+        }
+
+        // Only the growth needs room, but the whole paste has to fit
+        self.check_size(new)?;
+        let keep = id.clone();
+        self.within_quota(
+            0,
+            new - old,
+            &keep,
+            self.inner.update(id, content, content_type),
+            |_| true,
+        )
+        .await
+    }
+
+    async fn record_view(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.inner.record_view(id).await
+    }
+
+    async fn take(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        let paste = self.inner.take(id).await?;
+        self.recount().await;
+        Ok(paste)
+    }
+
+    async fn delete(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.inner.delete(id).await?;
+        self.recount().await;
+        Ok(())
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError> {
+        let purged = self.inner.purge_expired(now).await?;
+        if purged > 0 {
+            self.recount().await;
+        }
+        Ok(purged)
+    }
+
+    async fn usage(&self) -> Result<Usage, RepositoryError> {
+        self.inner.usage().await
+    }
+
+    async fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError> {
+        self.inner.eviction_candidates(order, limit).await
+    }
+}
+
+#[async_trait]
+impl<R: KeyStore> KeyStore for QuotaRepository<R> {
+    async fn insert_key(&self, key: StoredApiKey) -> Result<(), RepositoryError> {
+        self.inner.insert_key(key).await
+    }
+
+    async fn find_key(&self, hash: ApiKeyHash) -> Result<StoredApiKey, RepositoryError> {
+        self.inner.find_key(hash).await
+    }
+
+    async fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError> {
+        self.inner.list_keys().await
+    }
+
+    async fn delete_key(&self, name: String) -> Result<(), RepositoryError> {
+        self.inner.delete_key(name).await
+    }
+}
@@ -1,20 +1,29 @@
 use core::fmt;
 
 use actix_web::{HttpResponse, ResponseError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
-use crate::domain::{NewPaste, PasteContent, PasteId};
+use crate::domain::{ContentHash, NewPaste, Paste, PasteContent, PasteId};
 
 #[derive(Debug)]
 pub enum RepositoryError {
     NotFound(String),
+    Conflict(String),
+    ReadFailure(String),
     WriteFailure(String),
+    /// Storing the paste would exceed the storage quota
+    InsufficientStorage(String),
 }
 
 impl fmt::Display for RepositoryError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::NotFound(msg) => write!(f, "Not found: {}", msg),
+            Self::Conflict(msg) => write!(f, "Already exists: {}", msg),
+            Self::ReadFailure(msg) => write!(f, "Failed to read: {}", msg),
             Self::WriteFailure(msg) => write!(f, "Failed to write: {}", msg),
+            Self::InsufficientStorage(msg) => write!(f, "Out of storage: {}", msg),
         }
     }
 }
@@ -23,13 +32,105 @@ impl ResponseError for RepositoryError {
     fn error_response(&self) -> HttpResponse {
         match self {
             RepositoryError::NotFound(_) => HttpResponse::NotFound().body(self.to_string()),
+            RepositoryError::Conflict(_) => HttpResponse::Conflict().body(self.to_string()),
+            RepositoryError::ReadFailure(_) => {
+                HttpResponse::InternalServerError().body(self.to_string())
+            }
             RepositoryError::WriteFailure(_) => HttpResponse::BadRequest().body(self.to_string()),
+            RepositoryError::InsufficientStorage(_) => {
+                HttpResponse::InsufficientStorage().body(self.to_string())
+            }
         }
     }
 }
 
+/// How many pastes a repository holds, and their total size in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub pastes: u64,
+    pub bytes: u64,
+}
+
+/// What an insert did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inserted {
+    /// Created a paste under a free id
+    Created,
+    /// Took the place of an expired paste that had not been purged yet
+    ReplacedExpired,
+}
+
+/// The order pastes are evicted in to make room for new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionOrder {
+    /// Pastes read longest ago first, counting pastes never read from their
+    /// creation
+    LeastRecentlyRead,
+    /// Pastes created longest ago first
+    Oldest,
+}
+
+/// A paste store. Implementations are shared across workers without any outer
+/// lock, so each backend is responsible for its own synchronisation.
+///
+/// Content is stored once per distinct `ContentHash` and shared by every paste
+/// holding it; it is reclaimed when the last of those pastes is deleted,
+/// taken, replaced or purged.
+#[async_trait]
 pub trait Repository: Sync + Send + 'static {
-    fn new() -> Self;
-    fn find_one(&self, id: PasteId) -> Result<PasteContent, RepositoryError>;
-    fn insert(&mut self, entity: NewPaste) -> Result<(), RepositoryError>;
+    async fn find_one(&self, id: PasteId) -> Result<Paste, RepositoryError>;
+    /// Find a live, public paste whose content has the given hash. Pastes
+    /// that burn after reading are never returned, as a lookup should not
+    /// lead anyone into spending them.
+    async fn find_by_hash(&self, hash: ContentHash) -> Result<PasteId, RepositoryError>;
+    /// Every paste owned by the API key named `owner`, in no particular
+    /// order. Expired pastes are included until they are purged.
+    async fn find_by_owner(&self, owner: String) -> Result<Vec<Paste>, RepositoryError>;
+    /// Create a paste, failing with `Conflict` if a live paste already has
+    /// the same id. An expired paste that has not been purged yet is replaced.
+    async fn insert(&self, entity: NewPaste) -> Result<Inserted, RepositoryError>;
+    /// Replace the content of an existing paste and the type it was given
+    /// as, refreshing its size and `updated_at` and leaving its other
+    /// properties as they are.
+    async fn update(
+        &self,
+        id: PasteId,
+        content: PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError>;
+    /// Atomically increment the view count of a paste, and note when it was
+    /// read.
+    async fn record_view(&self, id: PasteId) -> Result<(), RepositoryError>;
+    /// Atomically remove and return a paste. Of several concurrent calls for
+    /// the same id, at most one succeeds.
+    async fn take(&self, id: PasteId) -> Result<Paste, RepositoryError>;
+    async fn delete(&self, id: PasteId) -> Result<(), RepositoryError>;
+    /// Remove every paste that expired at or before `now`, returning how many
+    /// were removed.
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError>;
+    /// Count the stored pastes and their sizes. Expired pastes count until
+    /// they are purged.
+    async fn usage(&self) -> Result<Usage, RepositoryError>;
+    /// Up to `limit` pastes, with their sizes, in the order they would be
+    /// evicted in.
+    async fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError>;
+}
+
+/// Run blocking storage work on tokio's blocking thread pool, reporting a
+/// failure to join the task via `err`.
+pub(crate) async fn blocking<T, F>(
+    err: fn(String) -> RepositoryError,
+    f: F,
+) -> Result<T, RepositoryError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, RepositoryError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| err(e.to_string()))?
 }
@@ -0,0 +1,781 @@
+use std::{path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{
+    functions::FunctionFlags, params, Connection, OptionalExtension, Row, TransactionBehavior,
+};
+
+use super::{
+    repository::{blocking, EvictionOrder, Inserted, RepositoryError, Usage},
+    KeyStore, Repository,
+};
+use crate::{
+    compression::{self, Codec},
+    domain::{
+        ApiKeyHash, ContentHash, Encryption, NewPaste, OwnerTokenHash, Paste, PasteContent,
+        PasteId, PastePasswordHash, StoredApiKey, Visibility,
+    },
+    keyring::{self, Keyring},
+};
+
+/// Schema migrations, applied in order. The index of the last applied
+/// migration is tracked via SQLite's `user_version` pragma.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_create_pastes.sql"),
+    include_str!("../../migrations/0002_add_paste_expiry.sql"),
+    include_str!("../../migrations/0003_add_burn_after_reading.sql"),
+    include_str!("../../migrations/0004_add_owner_token_hash.sql"),
+    include_str!("../../migrations/0005_add_paste_metadata.sql"),
+    include_str!("../../migrations/0006_add_paste_language_and_visibility.sql"),
+    include_str!("../../migrations/0007_deduplicate_paste_content.sql"),
+    include_str!("../../migrations/0008_add_blob_encoding.sql"),
+    include_str!("../../migrations/0009_add_paste_encryption.sql"),
+    include_str!("../../migrations/0010_add_blob_key_id.sql"),
+    include_str!("../../migrations/0011_add_paste_last_read_at.sql"),
+    include_str!("../../migrations/0012_add_api_keys.sql"),
+    include_str!("../../migrations/0013_add_paste_password_hash.sql"),
+];
+
+/// The columns read back into a `Paste`, in the order `StoredPaste::from_row`
+/// expects. Queries select them from `PASTES_WITH_CONTENT`.
+const PASTE_COLUMNS: &str = "id, content, created_at, updated_at, expires_at, size, content_type, \
+     views, burn_after_reading, owner_token_hash, language, visibility, encoding, \
+     encryption, content_hash, key_id, last_read_at, owner, password_hash";
+
+/// Pastes joined to the blobs holding their content.
+const PASTES_WITH_CONTENT: &str = "pastes JOIN blobs ON blobs.hash = pastes.content_hash";
+
+/// Store a paste's content unless it already is. Its reference count is kept
+/// by triggers on `pastes`.
+const INSERT_BLOB: &str = "INSERT INTO blobs (hash, content, encoding, key_id)
+     VALUES (?1, ?2, ?3, ?4) ON CONFLICT (hash) DO NOTHING";
+
+/// Stores pastes in a SQLite database.
+///
+/// Queries run on the blocking thread pool against a pool of connections, and
+/// the database runs in WAL mode so readers do not wait on writers.
+pub struct SqliteRepository {
+    pool: Pool<SqliteConnectionManager>,
+    codec: Option<Arc<dyn Codec>>,
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl SqliteRepository {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RepositoryError> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.pragma_update(None, "journal_mode", "WAL")?;
+            conn.busy_timeout(std::time::Duration::from_secs(5))
+        });
+        let pool = Pool::new(manager).map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+        let mut conn = pool
+            .get()
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+        migrate(&mut conn).map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+        Ok(Self {
+            pool,
+            codec: None,
+            keyring: None,
+        })
+    }
+
+    /// Compress new content with `codec`. Content stored before, whatever it
+    /// was compressed with, stays readable.
+    pub fn with_compression(self, codec: Option<Arc<dyn Codec>>) -> Self {
+        Self { codec, ..self }
+    }
+
+    /// Encrypt new content with the current key of `keyring`, and read back
+    /// content encrypted with any of its keys.
+    pub fn with_encryption(self, keyring: Option<Arc<Keyring>>) -> Self {
+        Self { keyring, ..self }
+    }
+
+    /// `content` as it is stored: compressed, if that makes it smaller, then
+    /// encrypted, if there is a current key.
+    fn encode(
+        codec: Option<&dyn Codec>,
+        keyring: Option<&Keyring>,
+        hash: &ContentHash,
+        content: &PasteContent,
+    ) -> Result<Blob, RepositoryError> {
+        let write_err = |e: std::io::Error| RepositoryError::WriteFailure(e.to_string());
+
+        let (encoding, compressed) =
+            compression::compress(codec, content.as_ref()).map_err(write_err)?;
+        let (key_id, content) = keyring::seal(keyring, hash, compressed).map_err(write_err)?;
+
+        Ok(Blob {
+            encoding,
+            key_id,
+            content,
+        })
+    }
+
+    /// Re-encrypt every blob not stored under the current key with it, or
+    /// store it in the clear if there is none, and move every blob not stored
+    /// under the hash it is now indexed by onto it, returning how many were
+    /// rewritten. Every blob is read to check its hash. Each is rewritten in
+    /// a transaction of its own, so the database stays in use meanwhile.
+    pub async fn reencrypt(&self) -> Result<usize, RepositoryError> {
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::WriteFailure, move |mut conn| {
+            let write_err = |e: rusqlite::Error| RepositoryError::WriteFailure(e.to_string());
+            let io_err = |e: std::io::Error| RepositoryError::WriteFailure(e.to_string());
+            let current = keyring.as_deref().and_then(Keyring::current);
+
+            let hashes = conn
+                .prepare("SELECT hash FROM blobs")
+                .and_then(|mut stmt| {
+                    stmt.query_map([], |row| row.get(0))?
+                        .collect::<rusqlite::Result<Vec<String>>>()
+                })
+                .map_err(write_err)?;
+
+            let mut rewritten = 0;
+            for hash in hashes {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(write_err)?;
+
+                // The blob may have been released since
+                let blob: Option<(Vec<u8>, Option<String>, Option<String>)> = tx
+                    .query_row(
+                        "SELECT content, encoding, key_id FROM blobs WHERE hash = ?1",
+                        params![hash],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )
+                    .optional()
+                    .map_err(write_err)?;
+                let Some((stored, encoding, key_id)) = blob else {
+                    continue;
+                };
+
+                let hash = ContentHash::parse(hash).map_err(RepositoryError::ReadFailure)?;
+                let compressed = keyring::open(keyring.as_deref(), key_id.clone(), &hash, stored)
+                    .map_err(io_err)?;
+                let (content, _) = compression::decompress(encoding.clone(), compressed.clone())
+                    .map_err(io_err)?;
+                let indexed = keyring::index(keyring.as_deref(), ContentHash::of(&content));
+                if indexed == hash && key_id.as_deref() == current {
+                    continue;
+                }
+
+                let (key_id, stored) =
+                    keyring::seal(keyring.as_deref(), &indexed, compressed).map_err(io_err)?;
+                if indexed == hash {
+                    tx.execute(
+                        "UPDATE blobs SET content = ?2, key_id = ?3 WHERE hash = ?1",
+                        params![hash.as_ref(), stored, key_id],
+                    )
+                    .map_err(write_err)?;
+                } else {
+                    // The reference count triggers move the pastes' references
+                    // over and remove the old blob
+                    tx.execute(
+                        INSERT_BLOB,
+                        params![indexed.as_ref(), stored, encoding, key_id],
+                    )
+                    .map_err(write_err)?;
+                    tx.execute(
+                        "UPDATE pastes SET content_hash = ?2 WHERE content_hash = ?1",
+                        params![hash.as_ref(), indexed.as_ref()],
+                    )
+                    .map_err(write_err)?;
+                }
+                tx.commit().map_err(write_err)?;
+                rewritten += 1;
+            }
+
+            Ok(rewritten)
+        })
+        .await
+    }
+
+    /// Run `f` with a pooled connection on the blocking thread pool. A failure
+    /// to obtain a connection is reported via `err`.
+    async fn with_conn<T, F>(
+        &self,
+        err: fn(String) -> RepositoryError,
+        f: F,
+    ) -> Result<T, RepositoryError>
+    where
+        T: Send + 'static,
+        F: FnOnce(PooledConnection<SqliteConnectionManager>) -> Result<T, RepositoryError>
+            + Send
+            + 'static,
+    {
+        let pool = self.pool.clone();
+
+        blocking(err, move || {
+            f(pool.get().map_err(|e| err(e.to_string()))?)
+        })
+        .await
+    }
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    // Moving existing content into `blobs` needs it hashed the way new
+    // content is
+    conn.create_scalar_function(
+        "sha256",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            Ok(ContentHash::of(ctx.get_raw(0).as_bytes()?)
+                .as_ref()
+                .to_owned())
+        },
+    )?;
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", idx + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn from_timestamp(secs: i64) -> Result<DateTime<Utc>, RepositoryError> {
+    DateTime::from_timestamp(secs, 0)
+        .ok_or_else(|| RepositoryError::ReadFailure(format!("invalid timestamp {}", secs)))
+}
+
+/// A `blobs` row about to be stored.
+struct Blob {
+    encoding: Option<&'static str>,
+    key_id: Option<String>,
+    content: Vec<u8>,
+}
+
+/// A `pastes` row as stored, before validation.
+struct StoredPaste {
+    id: String,
+    content: Vec<u8>,
+    created_at: i64,
+    updated_at: Option<i64>,
+    expires_at: Option<i64>,
+    size: i64,
+    content_type: Option<String>,
+    views: i64,
+    burn_after_reading: bool,
+    owner_token_hash: Option<String>,
+    language: Option<String>,
+    visibility: String,
+    encoding: Option<String>,
+    encryption: Option<String>,
+    content_hash: String,
+    key_id: Option<String>,
+    last_read_at: Option<i64>,
+    owner: Option<String>,
+    password_hash: Option<String>,
+}
+
+impl StoredPaste {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            content: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            expires_at: row.get(4)?,
+            size: row.get(5)?,
+            content_type: row.get(6)?,
+            views: row.get(7)?,
+            burn_after_reading: row.get(8)?,
+            owner_token_hash: row.get(9)?,
+            language: row.get(10)?,
+            visibility: row.get(11)?,
+            encoding: row.get(12)?,
+            encryption: row.get(13)?,
+            content_hash: row.get(14)?,
+            key_id: row.get(15)?,
+            last_read_at: row.get(16)?,
+            owner: row.get(17)?,
+            password_hash: row.get(18)?,
+        })
+    }
+
+    fn into_paste(self, keyring: Option<&Keyring>) -> Result<Paste, RepositoryError> {
+        let read_err = |e: std::io::Error| RepositoryError::ReadFailure(e.to_string());
+        let hash = ContentHash::parse(self.content_hash).map_err(RepositoryError::ReadFailure)?;
+
+        let compressed =
+            keyring::open(keyring, self.key_id, &hash, self.content).map_err(read_err)?;
+        let (content, encoded) =
+            compression::decompress(self.encoding, compressed).map_err(read_err)?;
+
+        Ok(Paste {
+            id: PasteId::parse(self.id).map_err(RepositoryError::ReadFailure)?,
+            // The size limit was enforced on the way in; it may have changed since.
+            content: PasteContent::parse_bytes(content.into(), usize::MAX)
+                .map_err(RepositoryError::ReadFailure)?,
+            encoded,
+            created_at: from_timestamp(self.created_at)?,
+            updated_at: self.updated_at.map(from_timestamp).transpose()?,
+            expires_at: self.expires_at.map(from_timestamp).transpose()?,
+            size: self.size.try_into().unwrap_or_default(),
+            content_type: self.content_type,
+            language: self.language,
+            visibility: Visibility::parse(self.visibility).map_err(RepositoryError::ReadFailure)?,
+            encryption: self
+                .encryption
+                .map(Encryption::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+            views: self.views.try_into().unwrap_or_default(),
+            last_read_at: self.last_read_at.map(from_timestamp).transpose()?,
+            burn_after_reading: self.burn_after_reading,
+            owner_token_hash: self
+                .owner_token_hash
+                .map(OwnerTokenHash::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+            owner: self.owner,
+            password_hash: self
+                .password_hash
+                .map(PastePasswordHash::parse)
+                .transpose()
+                .map_err(RepositoryError::ReadFailure)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn find_one(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            conn.query_row(
+                &format!(
+                    "SELECT {} FROM {} WHERE id = ?1",
+                    PASTE_COLUMNS, PASTES_WITH_CONTENT
+                ),
+                params![id.as_ref()],
+                StoredPaste::from_row,
+            )
+            .optional()
+            .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?
+            .ok_or_else(|| RepositoryError::NotFound(id.as_ref().to_owned()))?
+            .into_paste(keyring.as_deref())
+        })
+        .await
+    }
+
+    async fn find_by_hash(&self, hash: ContentHash) -> Result<PasteId, RepositoryError> {
+        let key = keyring::index(self.keyring.as_deref(), hash.clone());
+
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            let id: String = conn
+                .query_row(
+                    "SELECT id FROM pastes
+                     WHERE content_hash = ?1
+                       AND visibility = ?2
+                       AND NOT burn_after_reading
+                       AND password_hash IS NULL
+                       AND (expires_at IS NULL OR expires_at > ?3)
+                     LIMIT 1",
+                    params![
+                        key.as_ref(),
+                        Visibility::Public.as_ref(),
+                        Utc::now().timestamp()
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?
+                .ok_or_else(|| RepositoryError::NotFound(hash.as_ref().to_owned()))?;
+
+            PasteId::parse(id).map_err(RepositoryError::ReadFailure)
+        })
+        .await
+    }
+
+    async fn find_by_owner(&self, owner: String) -> Result<Vec<Paste>, RepositoryError> {
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            let read_err = |e: rusqlite::Error| RepositoryError::ReadFailure(e.to_string());
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {} FROM {} WHERE owner = ?1",
+                    PASTE_COLUMNS, PASTES_WITH_CONTENT
+                ))
+                .map_err(read_err)?;
+
+            let rows = stmt
+                .query_map(params![owner], StoredPaste::from_row)
+                .map_err(read_err)?;
+
+            rows.map(|row| row.map_err(read_err)?.into_paste(keyring.as_deref()))
+                .collect()
+        })
+        .await
+    }
+
+    async fn insert(&self, entity: NewPaste) -> Result<Inserted, RepositoryError> {
+        let codec = self.codec.clone();
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::WriteFailure, move |mut conn| {
+            let write_err = |e: rusqlite::Error| RepositoryError::WriteFailure(e.to_string());
+            let hash = keyring::index(keyring.as_deref(), entity.content.hash());
+            let blob = Self::encode(codec.as_deref(), keyring.as_deref(), &hash, &entity.content)?;
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(write_err)?;
+
+            tx.execute(
+                INSERT_BLOB,
+                params![hash.as_ref(), blob.content, blob.encoding, blob.key_id],
+            )
+            .map_err(write_err)?;
+
+            let existed = tx
+                .query_row(
+                    "SELECT 1 FROM pastes WHERE id = ?1",
+                    params![entity.id.as_ref()],
+                    |_| Ok(()),
+                )
+                .optional()
+                .map_err(write_err)?
+                .is_some();
+
+            // Replace the existing row only when it has expired; otherwise the
+            // insert is a no-op, and rolling back leaves nothing changed.
+            let inserted = tx
+                .execute(
+                    "INSERT INTO pastes
+                     (id, content_hash, created_at, expires_at, size, content_type,
+                      burn_after_reading, owner_token_hash, language, visibility, encryption,
+                      owner, password_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?10, ?11, ?12, ?13, ?14)
+                     ON CONFLICT (id) DO UPDATE SET
+                         content_hash = excluded.content_hash,
+                         created_at = excluded.created_at,
+                         updated_at = NULL,
+                         expires_at = excluded.expires_at,
+                         size = excluded.size,
+                         content_type = excluded.content_type,
+                         views = 0,
+                         last_read_at = NULL,
+                         burn_after_reading = excluded.burn_after_reading,
+                         owner_token_hash = excluded.owner_token_hash,
+                         language = excluded.language,
+                         visibility = excluded.visibility,
+                         encryption = excluded.encryption,
+                         owner = excluded.owner,
+                         password_hash = excluded.password_hash
+                     WHERE pastes.expires_at IS NOT NULL AND pastes.expires_at <= ?9",
+                    params![
+                        entity.id.as_ref(),
+                        hash.as_ref(),
+                        entity.created_at.timestamp(),
+                        entity.expires_at.map(|at| at.timestamp()),
+                        entity.size() as i64,
+                        entity.content_type,
+                        entity.burn_after_reading,
+                        entity.owner_token_hash.as_ref(),
+                        Utc::now().timestamp(),
+                        entity.language,
+                        entity.visibility.as_ref(),
+                        entity.encryption.as_ref().map(|e| e.as_ref()),
+                        entity.owner,
+                        entity.password_hash.as_ref().map(|h| h.as_ref()),
+                    ],
+                )
+                .map_err(write_err)?;
+
+            match inserted {
+                0 => Err(RepositoryError::Conflict(entity.id.as_ref().to_owned())),
+                _ => {
+                    tx.commit().map_err(write_err)?;
+                    Ok(match existed {
+                        true => Inserted::ReplacedExpired,
+                        false => Inserted::Created,
+                    })
+                }
+            }
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        id: PasteId,
+        content: PasteContent,
+        content_type: Option<String>,
+    ) -> Result<(), RepositoryError> {
+        let codec = self.codec.clone();
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::WriteFailure, move |mut conn| {
+            let write_err = |e: rusqlite::Error| RepositoryError::WriteFailure(e.to_string());
+            let hash = keyring::index(keyring.as_deref(), content.hash());
+            let blob = Self::encode(codec.as_deref(), keyring.as_deref(), &hash, &content)?;
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(write_err)?;
+
+            tx.execute(
+                INSERT_BLOB,
+                params![hash.as_ref(), blob.content, blob.encoding, blob.key_id],
+            )
+            .map_err(write_err)?;
+
+            let updated = tx
+                .execute(
+                    "UPDATE pastes
+                     SET content_hash = ?2, size = ?3, updated_at = ?4, content_type = ?5
+                     WHERE id = ?1",
+                    params![
+                        id.as_ref(),
+                        hash.as_ref(),
+                        content.len() as i64,
+                        Utc::now().timestamp(),
+                        content_type
+                    ],
+                )
+                .map_err(write_err)?;
+
+            match updated {
+                0 => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+                _ => tx.commit().map_err(write_err),
+            }
+        })
+        .await
+    }
+
+    async fn record_view(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.with_conn(RepositoryError::WriteFailure, move |conn| {
+            let updated = conn
+                .execute(
+                    "UPDATE pastes SET views = views + 1, last_read_at = ?2 WHERE id = ?1",
+                    params![id.as_ref(), Utc::now().timestamp()],
+                )
+                .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+            match updated {
+                0 => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+
+    async fn take(&self, id: PasteId) -> Result<Paste, RepositoryError> {
+        let keyring = self.keyring.clone();
+
+        self.with_conn(RepositoryError::WriteFailure, move |mut conn| {
+            let write_err = |e: rusqlite::Error| RepositoryError::WriteFailure(e.to_string());
+            // The content is read before the delete can release its blob; the
+            // write lock taken up front keeps concurrent takes out between the two.
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(write_err)?;
+
+            let paste = tx
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM {} WHERE id = ?1",
+                        PASTE_COLUMNS, PASTES_WITH_CONTENT
+                    ),
+                    params![id.as_ref()],
+                    StoredPaste::from_row,
+                )
+                .optional()
+                .map_err(write_err)?
+                .ok_or_else(|| RepositoryError::NotFound(id.as_ref().to_owned()))?;
+
+            tx.execute("DELETE FROM pastes WHERE id = ?1", params![id.as_ref()])
+                .map_err(write_err)?;
+            tx.commit().map_err(write_err)?;
+
+            paste.into_paste(keyring.as_deref())
+        })
+        .await
+    }
+
+    async fn delete(&self, id: PasteId) -> Result<(), RepositoryError> {
+        self.with_conn(RepositoryError::WriteFailure, move |conn| {
+            let deleted = conn
+                .execute("DELETE FROM pastes WHERE id = ?1", params![id.as_ref()])
+                .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+            match deleted {
+                0 => Err(RepositoryError::NotFound(id.as_ref().to_owned())),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, RepositoryError> {
+        self.with_conn(RepositoryError::WriteFailure, move |conn| {
+            conn.execute(
+                "DELETE FROM pastes WHERE expires_at IS NOT NULL AND expires_at <= ?1",
+                params![now.timestamp()],
+            )
+            .map_err(|e| RepositoryError::WriteFailure(e.to_string()))
+        })
+        .await
+    }
+
+    async fn usage(&self) -> Result<Usage, RepositoryError> {
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            let (pastes, bytes): (i64, i64) = conn
+                .query_row(
+                    "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM pastes",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?;
+
+            Ok(Usage {
+                pastes: pastes.try_into().unwrap_or_default(),
+                bytes: bytes.try_into().unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    async fn eviction_candidates(
+        &self,
+        order: EvictionOrder,
+        limit: usize,
+    ) -> Result<Vec<(PasteId, u64)>, RepositoryError> {
+        let order_by = match order {
+            EvictionOrder::LeastRecentlyRead => "COALESCE(last_read_at, created_at), id",
+            EvictionOrder::Oldest => "created_at, id",
+        };
+
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            let read_err = |e: rusqlite::Error| RepositoryError::ReadFailure(e.to_string());
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT id, size FROM pastes ORDER BY {} LIMIT ?1",
+                    order_by
+                ))
+                .map_err(read_err)?;
+
+            let rows = stmt
+                .query_map(params![limit as i64], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })
+                .map_err(read_err)?;
+
+            rows.map(|row| {
+                let (id, size) = row.map_err(read_err)?;
+                let id = PasteId::parse(id).map_err(RepositoryError::ReadFailure)?;
+                Ok((id, size.try_into().unwrap_or_default()))
+            })
+            .collect()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl KeyStore for SqliteRepository {
+    async fn insert_key(&self, key: StoredApiKey) -> Result<(), RepositoryError> {
+        self.with_conn(RepositoryError::WriteFailure, move |conn| {
+            let inserted = conn
+                .execute(
+                    "INSERT INTO api_keys (name, key_hash, created_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT DO NOTHING",
+                    params![key.name, key.hash.as_ref(), key.created_at.timestamp()],
+                )
+                .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+            match inserted {
+                0 => Err(RepositoryError::Conflict(key.name)),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+
+    async fn find_key(&self, hash: ApiKeyHash) -> Result<StoredApiKey, RepositoryError> {
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            conn.query_row(
+                &format!(
+                    "SELECT {} FROM api_keys WHERE key_hash = ?1",
+                    StoredKey::COLUMNS
+                ),
+                params![hash.as_ref()],
+                StoredKey::from_row,
+            )
+            .optional()
+            .map_err(|e| RepositoryError::ReadFailure(e.to_string()))?
+            .ok_or_else(|| RepositoryError::NotFound("API key".to_string()))?
+            .into_key()
+        })
+        .await
+    }
+
+    async fn list_keys(&self) -> Result<Vec<StoredApiKey>, RepositoryError> {
+        self.with_conn(RepositoryError::ReadFailure, move |conn| {
+            let read_err = |e: rusqlite::Error| RepositoryError::ReadFailure(e.to_string());
+            let mut stmt = conn
+                .prepare(&format!("SELECT {} FROM api_keys", StoredKey::COLUMNS))
+                .map_err(read_err)?;
+
+            let rows = stmt.query_map([], StoredKey::from_row).map_err(read_err)?;
+
+            rows.map(|row| row.map_err(read_err)?.into_key()).collect()
+        })
+        .await
+    }
+
+    async fn delete_key(&self, name: String) -> Result<(), RepositoryError> {
+        self.with_conn(RepositoryError::WriteFailure, move |conn| {
+            let deleted = conn
+                .execute("DELETE FROM api_keys WHERE name = ?1", params![name])
+                .map_err(|e| RepositoryError::WriteFailure(e.to_string()))?;
+
+            match deleted {
+                0 => Err(RepositoryError::NotFound(name)),
+                _ => Ok(()),
+            }
+        })
+        .await
+    }
+}
+
+/// An `api_keys` row as stored, before validation.
+struct StoredKey {
+    name: String,
+    key_hash: String,
+    created_at: i64,
+}
+
+impl StoredKey {
+    const COLUMNS: &'static str = "name, key_hash, created_at";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            name: row.get(0)?,
+            key_hash: row.get(1)?,
+            created_at: row.get(2)?,
+        })
+    }
+
+    fn into_key(self) -> Result<StoredApiKey, RepositoryError> {
+        Ok(StoredApiKey {
+            name: self.name,
+            hash: ApiKeyHash::parse(self.key_hash).map_err(RepositoryError::ReadFailure)?,
+            created_at: from_timestamp(self.created_at)?,
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+const KEY_BYTES: usize = 32;
+
+/// Prefix of generated keys, so leaked ones are easy to recognise.
+const KEY_PREFIX: &str = "whtpst_";
+
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Secret a client authenticates to the API with, sent as
+/// `Authorization: Bearer <key>`. Only its hash is ever stored.
+#[derive(Debug)]
+pub struct ApiKey(Secret<String>);
+
+impl ApiKey {
+    pub fn generate() -> ApiKey {
+        let mut bytes = [0u8; KEY_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(Secret::new(format!("{}{}", KEY_PREFIX, hex::encode(bytes))))
+    }
+
+    pub fn parse(s: String) -> Result<ApiKey, String> {
+        if s.trim().is_empty() {
+            return Err("not a valid API key - empty string".to_string());
+        }
+
+        Ok(Self(Secret::new(s)))
+    }
+
+    pub fn hash(&self) -> ApiKeyHash {
+        ApiKeyHash(hex::encode(Sha256::digest(
+            self.0.expose_secret().as_bytes(),
+        )))
+    }
+}
+
+impl ExposeSecret<String> for ApiKey {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+/// Hex-encoded SHA-256 of an `ApiKey`, which keys are stored and looked up by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyHash(String);
+
+impl ApiKeyHash {
+    pub fn parse(s: String) -> Result<ApiKeyHash, String> {
+        match hex::decode(&s) {
+            Ok(bytes) if bytes.len() == Sha256::output_size() => Ok(Self(s)),
+            _ => Err("not a valid API key hash".to_string()),
+        }
+    }
+}
+
+impl AsRef<str> for ApiKeyHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// An API key as kept in a `KeyStore`. Pastes created with the key are owned
+/// by its name, so a key issued again under the same name takes them over.
+#[derive(Debug, Clone)]
+pub struct StoredApiKey {
+    pub name: String,
+    pub hash: ApiKeyHash,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoredApiKey {
+    pub fn new(name: String, key: &ApiKey) -> Result<StoredApiKey, String> {
+        Ok(Self {
+            name: parse_key_name(name)?,
+            hash: key.hash(),
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// Names are kept to characters that are safe in file names and URLs.
+pub fn parse_key_name(name: String) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match valid {
+        true => Ok(name),
+        false => Err(format!(
+            "{:?} is not a valid key name - use up to {} letters, digits, - or _",
+            name, MAX_NAME_LENGTH
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::ExposeSecret;
+
+    use super::{ApiKey, ApiKeyHash, StoredApiKey};
+
+    #[test]
+    fn generated_keys_are_unique_and_prefixed() {
+        let a = ApiKey::generate();
+        let b = ApiKey::generate();
+        assert_ne!(a.expose_secret(), b.expose_secret());
+        assert!(a.expose_secret().starts_with("whtpst_"));
+    }
+
+    #[test]
+    fn a_key_hashes_the_same_after_parsing() {
+        let key = ApiKey::generate();
+        let parsed = ApiKey::parse(key.expose_secret().clone()).unwrap();
+        assert_eq!(key.hash(), parsed.hash());
+        assert_ok!(ApiKeyHash::parse(key.hash().as_ref().to_owned()));
+    }
+
+    #[test]
+    fn invalid_keys_and_names_are_rejected() {
+        let key = ApiKey::generate();
+        assert_err!(ApiKey::parse(" ".to_string()));
+        assert_err!(StoredApiKey::new("".to_string(), &key));
+        assert_err!(StoredApiKey::new("../etc".to_string(), &key));
+        assert_err!(StoredApiKey::new("a".repeat(65), &key));
+        assert_ok!(StoredApiKey::new("ops-team_2".to_string(), &key));
+    }
+}
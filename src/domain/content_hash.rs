@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of a paste's content, under which identical content is
+/// stored once.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentHash(String);
+
+impl ContentHash {
+    pub fn of(content: &[u8]) -> ContentHash {
+        Self(hex::encode(Sha256::digest(content)))
+    }
+
+    /// Accept a SHA-256 digest in hex, in either case.
+    pub fn parse(s: String) -> Result<ContentHash, String> {
+        match hex::decode(&s) {
+            Ok(bytes) if bytes.len() == Sha256::output_size() => Ok(Self(s.to_lowercase())),
+            _ => Err(format!("{} is not a valid SHA-256 hash", s)),
+        }
+    }
+}
+
+impl AsRef<str> for ContentHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ContentHash;
+    use claims::{assert_err, assert_ok_eq};
+
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn content_is_hashed_with_sha256() {
+        assert_eq!(HELLO, ContentHash::of(b"hello").as_ref());
+    }
+
+    #[test]
+    fn uppercase_hashes_are_normalised() {
+        assert_ok_eq!(
+            ContentHash::parse(HELLO.to_uppercase()),
+            ContentHash::of(b"hello")
+        );
+    }
+
+    #[test]
+    fn malformed_hashes_are_rejected() {
+        for hash in ["", "abc", &HELLO[1..], "zz"] {
+            assert_err!(ContentHash::parse(hash.to_string()));
+        }
+    }
+}
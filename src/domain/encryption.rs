@@ -0,0 +1,51 @@
+/// How a client encrypted a paste before submitting it. The key never reaches
+/// the server, which stores and serves the ciphertext untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// AES-256-GCM, with the 12-byte nonce ahead of the ciphertext and tag
+    Aes256Gcm,
+}
+
+impl Encryption {
+    pub fn parse(s: String) -> Result<Encryption, String> {
+        match s.to_lowercase().as_str() {
+            "aes-256-gcm" => Ok(Self::Aes256Gcm),
+            _ => Err(format!("{} is not a supported encryption scheme", s)),
+        }
+    }
+}
+
+impl AsRef<str> for Encryption {
+    fn as_ref(&self) -> &str {
+        match self {
+            Encryption::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Encryption;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn schemes_round_trip_through_parse() {
+        assert_ok_eq!(
+            Encryption::parse(Encryption::Aes256Gcm.as_ref().to_owned()),
+            Encryption::Aes256Gcm
+        );
+    }
+
+    #[test]
+    fn parse_ignores_case() {
+        assert_ok_eq!(
+            Encryption::parse("AES-256-GCM".to_string()),
+            Encryption::Aes256Gcm
+        );
+    }
+
+    #[test]
+    fn unknown_schemes_are_rejected() {
+        assert_err!(Encryption::parse("rot13".to_string()));
+    }
+}
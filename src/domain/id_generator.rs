@@ -0,0 +1,191 @@
+use rand::{seq::SliceRandom, Rng};
+use uuid::Uuid;
+
+use super::PasteId;
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const ADJECTIVES: &[&str] = &[
+    "agile",
+    "amber",
+    "ancient",
+    "bold",
+    "brave",
+    "bright",
+    "brisk",
+    "calm",
+    "clever",
+    "cosmic",
+    "crisp",
+    "curious",
+    "daring",
+    "dusty",
+    "eager",
+    "early",
+    "fancy",
+    "fierce",
+    "fluffy",
+    "gentle",
+    "giant",
+    "glad",
+    "golden",
+    "grand",
+    "happy",
+    "hidden",
+    "humble",
+    "icy",
+    "jolly",
+    "keen",
+    "kind",
+    "lively",
+    "lucky",
+    "merry",
+    "mighty",
+    "misty",
+    "modest",
+    "noble",
+    "odd",
+    "patient",
+    "plucky",
+    "polite",
+    "proud",
+    "quick",
+    "quiet",
+    "rapid",
+    "rusty",
+    "shiny",
+    "silent",
+    "silver",
+    "sleepy",
+    "snowy",
+    "solid",
+    "sunny",
+    "swift",
+    "tidy",
+    "tiny",
+    "vivid",
+    "wandering",
+    "warm",
+    "wild",
+    "wise",
+    "witty",
+    "zesty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "bat", "bear", "beaver", "bison", "camel", "cat", "cobra", "crane", "crow", "deer",
+    "dingo", "dolphin", "dove", "eagle", "eel", "elk", "falcon", "ferret", "finch", "fox", "frog",
+    "gecko", "goat", "goose", "hare", "hawk", "hedgehog", "heron", "ibis", "jackal", "koala",
+    "lark", "lemur", "lion", "llama", "lynx", "mole", "moose", "newt", "otter", "owl", "panda",
+    "parrot", "pike", "puffin", "quail", "rabbit", "raven", "seal", "shark", "sloth", "snail",
+    "sparrow", "squid", "stork", "swan", "tapir", "tiger", "toad", "trout", "walrus", "wolf",
+    "yak",
+];
+
+/// Source of ids for pastes created without one.
+///
+/// Generated ids need only be unlikely to collide: callers retry with a fresh
+/// id when one is already taken.
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self) -> PasteId;
+}
+
+/// Random UUIDv4s, e.g. `c739f1a2-c5a0-4f13-8df1-d181e402eba7`.
+#[derive(Debug)]
+pub struct UuidGenerator;
+
+impl IdGenerator for UuidGenerator {
+    fn generate(&self) -> PasteId {
+        PasteId::parse(Uuid::new_v4().to_string()).expect("a uuid is a valid paste id")
+    }
+}
+
+/// Random alphanumeric strings of a fixed length, e.g. `aZ3kP9qX`.
+#[derive(Debug)]
+pub struct Base62Generator {
+    length: usize,
+}
+
+impl Base62Generator {
+    pub fn new(length: usize) -> Result<Self, String> {
+        if length == 0 {
+            return Err("base62 ids need a length of at least 1".to_string());
+        }
+
+        Ok(Self { length })
+    }
+}
+
+impl IdGenerator for Base62Generator {
+    fn generate(&self) -> PasteId {
+        let mut rng = rand::thread_rng();
+        let id = (0..self.length)
+            .map(|_| char::from(*BASE62.choose(&mut rng).expect("alphabet is not empty")))
+            .collect();
+
+        PasteId::parse(id).expect("a base62 string is a valid paste id")
+    }
+}
+
+/// Two adjectives, an animal and a four-digit number, e.g.
+/// `brave-quiet-otter-4821`.
+///
+/// Made to be read out and typed, not to be secret: with some 2.7 billion of
+/// them (about 31 bits), ids can be guessed far more easily than `uuid` or
+/// long `base62` ones, so they do not keep unlisted pastes out of reach.
+#[derive(Debug)]
+pub struct WordsGenerator;
+
+impl IdGenerator for WordsGenerator {
+    fn generate(&self) -> PasteId {
+        let mut rng = rand::thread_rng();
+        let mut adjective = || *ADJECTIVES.choose(&mut rng).expect("word list is not empty");
+        let (first, second) = (adjective(), adjective());
+        let id = format!(
+            "{}-{}-{}-{:04}",
+            first,
+            second,
+            ANIMALS.choose(&mut rng).expect("word list is not empty"),
+            rng.gen_range(0..10_000)
+        );
+
+        PasteId::parse(id).expect("generated words are a valid paste id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Base62Generator, IdGenerator, UuidGenerator, WordsGenerator};
+    use claims::assert_err;
+
+    #[test]
+    fn uuid_ids_are_uuids() {
+        let id = UuidGenerator.generate();
+        assert_eq!(36, id.as_ref().len());
+    }
+
+    #[test]
+    fn base62_ids_have_the_configured_length() {
+        let id = Base62Generator::new(8).unwrap().generate();
+        assert_eq!(8, id.as_ref().len());
+        assert!(id.as_ref().chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn base62_ids_cannot_be_empty() {
+        assert_err!(Base62Generator::new(0));
+    }
+
+    #[test]
+    fn word_ids_are_three_words_and_a_number() {
+        let id = WordsGenerator.generate();
+        let parts: Vec<&str> = id.as_ref().split('-').collect();
+
+        assert_eq!(4, parts.len());
+        for word in &parts[..3] {
+            assert!(word.chars().all(|c| c.is_ascii_lowercase()));
+        }
+        assert_eq!(4, parts[3].len());
+        assert!(parts[3].parse::<u16>().unwrap() < 10_000);
+    }
+}
@@ -1,7 +1,25 @@
+mod api_key;
+mod content_hash;
+mod encryption;
+mod id_generator;
 mod new_paste;
+mod owner_token;
+mod paste;
 mod paste_content;
 mod paste_id;
+mod paste_password;
+mod paste_ttl;
+mod visibility;
 
+pub use api_key::{parse_key_name, ApiKey, ApiKeyHash, StoredApiKey};
+pub use content_hash::ContentHash;
+pub use encryption::Encryption;
+pub use id_generator::{Base62Generator, IdGenerator, UuidGenerator, WordsGenerator};
 pub use new_paste::NewPaste;
+pub use owner_token::{OwnerToken, OwnerTokenHash};
+pub use paste::{EncodedContent, Paste};
 pub use paste_content::PasteContent;
 pub use paste_id::PasteId;
+pub use paste_password::{PastePassword, PastePasswordHash};
+pub use paste_ttl::PasteTtl;
+pub use visibility::Visibility;
@@ -1,6 +1,29 @@
-use super::{PasteContent, PasteId};
+use chrono::{DateTime, Utc};
 
+use super::{Encryption, OwnerTokenHash, PasteContent, PasteId, PastePasswordHash, Visibility};
+
+#[derive(Clone)]
 pub struct NewPaste {
     pub id: PasteId,
     pub content: PasteContent,
+    pub content_type: Option<String>,
+    pub language: Option<String>,
+    pub visibility: Visibility,
+    /// Set when the content is ciphertext the client encrypted
+    pub encryption: Option<Encryption>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    pub owner_token_hash: OwnerTokenHash,
+    /// Name of the API key the paste was created with, if any
+    pub owner: Option<String>,
+    /// Set when reading the paste takes a password
+    pub password_hash: Option<PastePasswordHash>,
+}
+
+impl NewPaste {
+    /// Length of the content in bytes
+    pub fn size(&self) -> u64 {
+        self.content.len() as u64
+    }
 }
@@ -0,0 +1,106 @@
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+const TOKEN_BYTES: usize = 32;
+
+/// Secret handed to a paste's creator, proving ownership of the paste (e.g. to
+/// delete it). Only its hash is ever stored.
+#[derive(Debug)]
+pub struct OwnerToken(Secret<String>);
+
+impl OwnerToken {
+    pub fn generate() -> OwnerToken {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(Secret::new(hex::encode(bytes)))
+    }
+
+    pub fn parse(s: String) -> Result<OwnerToken, String> {
+        if s.trim().is_empty() {
+            return Err("not a valid paste token - empty string".to_string());
+        }
+
+        Ok(Self(Secret::new(s)))
+    }
+
+    pub fn hash(&self) -> OwnerTokenHash {
+        OwnerTokenHash(hex::encode(Sha256::digest(
+            self.0.expose_secret().as_bytes(),
+        )))
+    }
+}
+
+impl ExposeSecret<String> for OwnerToken {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+/// Hex-encoded SHA-256 of an `OwnerToken`, as persisted alongside a paste.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerTokenHash(String);
+
+impl OwnerTokenHash {
+    pub fn parse(s: String) -> Result<OwnerTokenHash, String> {
+        match hex::decode(&s) {
+            Ok(bytes) if bytes.len() == Sha256::output_size() => Ok(Self(s)),
+            _ => Err("not a valid paste token hash".to_string()),
+        }
+    }
+
+    /// Compare in constant time, so response timings reveal nothing about
+    /// how much of a guessed token was right.
+    pub fn verify(&self, token: &OwnerToken) -> bool {
+        self.0.as_bytes().ct_eq(token.hash().0.as_bytes()).into()
+    }
+}
+
+impl AsRef<str> for OwnerTokenHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{OwnerToken, OwnerTokenHash};
+    use claims::{assert_err, assert_ok};
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn generated_tokens_are_unique() {
+        let a = OwnerToken::generate();
+        let b = OwnerToken::generate();
+        assert_ne!(a.expose_secret(), b.expose_secret());
+    }
+
+    #[test]
+    fn a_token_verifies_against_its_own_hash() {
+        let token = OwnerToken::generate();
+        assert!(token.hash().verify(&token));
+    }
+
+    #[test]
+    fn a_different_token_is_rejected() {
+        let token = OwnerToken::generate();
+        assert!(!token.hash().verify(&OwnerToken::generate()));
+    }
+
+    #[test]
+    fn empty_token_is_rejected() {
+        assert_err!(OwnerToken::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_hash_round_trips_through_parse() {
+        let hash = OwnerToken::generate().hash();
+        assert_ok!(OwnerTokenHash::parse(hash.as_ref().to_owned()));
+    }
+
+    #[test]
+    fn a_malformed_hash_is_rejected() {
+        assert_err!(OwnerTokenHash::parse("not hex".to_string()));
+    }
+}
@@ -0,0 +1,83 @@
+use actix_web::web::Bytes;
+use chrono::{DateTime, Utc};
+
+use super::{
+    Encryption, NewPaste, OwnerTokenHash, PasteContent, PasteId, PastePasswordHash, Visibility,
+};
+
+/// A stored paste and its metadata, as read back from a repository.
+#[derive(Debug, Clone)]
+pub struct Paste {
+    pub id: PasteId,
+    pub content: PasteContent,
+    /// The content as stored, when it is stored compressed
+    pub encoded: Option<EncodedContent>,
+    pub created_at: DateTime<Utc>,
+    /// Set once the content has been replaced
+    pub updated_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Length of the content in bytes
+    pub size: u64,
+    /// The `Content-Type` the paste was submitted with, if any
+    pub content_type: Option<String>,
+    /// Name of the language to highlight the paste as, if one was given
+    pub language: Option<String>,
+    pub visibility: Visibility,
+    /// Set when the content is ciphertext the client encrypted
+    pub encryption: Option<Encryption>,
+    pub views: u64,
+    /// When a view was last recorded, if ever
+    pub last_read_at: Option<DateTime<Utc>>,
+    /// Deleted by the first successful read
+    pub burn_after_reading: bool,
+    /// Absent for pastes stored before tokens were issued
+    pub owner_token_hash: Option<OwnerTokenHash>,
+    /// Name of the API key the paste was created with, if any
+    pub owner: Option<String>,
+    /// Set when reading the paste takes a password
+    pub password_hash: Option<PastePasswordHash>,
+}
+
+/// A paste's content compressed with an HTTP content coding such as `zstd`.
+#[derive(Debug, Clone)]
+pub struct EncodedContent {
+    pub encoding: String,
+    pub bytes: Bytes,
+}
+
+impl Paste {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// Whether a reader holding the API key named `owner`, if any, may read
+    /// the paste.
+    pub fn is_readable_by(&self, owner: Option<&str>) -> bool {
+        self.visibility != Visibility::Private
+            || owner.is_some_and(|owner| self.owner.as_deref() == Some(owner))
+    }
+}
+
+impl From<NewPaste> for Paste {
+    fn from(entity: NewPaste) -> Self {
+        Self {
+            size: entity.size(),
+            id: entity.id,
+            content: entity.content,
+            encoded: None,
+            created_at: entity.created_at,
+            updated_at: None,
+            expires_at: entity.expires_at,
+            content_type: entity.content_type,
+            language: entity.language,
+            visibility: entity.visibility,
+            encryption: entity.encryption,
+            views: 0,
+            last_read_at: None,
+            burn_after_reading: entity.burn_after_reading,
+            owner_token_hash: Some(entity.owner_token_hash),
+            owner: entity.owner,
+            password_hash: entity.password_hash,
+        }
+    }
+}
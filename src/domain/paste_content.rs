@@ -1,28 +1,82 @@
 use actix_web::web::Bytes;
-use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct PasteContent(String);
+use super::ContentHash;
+
+/// The body of a paste: text or arbitrary binary data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasteContent(Bytes);
 
 impl PasteContent {
-    pub fn parse(s: String) -> Result<PasteContent, String> {
+    /// Accept non-blank text of at most `max_bytes` bytes.
+    pub fn parse(s: String, max_bytes: usize) -> Result<PasteContent, String> {
         if s.trim().is_empty() {
             return Err("not valid paste content - empty string".to_string());
         }
 
-        Ok(Self(s))
+        check_size(s.len(), max_bytes)?;
+
+        Ok(Self(Bytes::from(s)))
+    }
+
+    /// Accept any non-empty body of at most `max_bytes` bytes. Bodies that
+    /// happen to be valid UTF-8 are held to the same rules as text.
+    pub fn parse_bytes(bytes: Bytes, max_bytes: usize) -> Result<PasteContent, String> {
+        match std::str::from_utf8(&bytes) {
+            Ok(s) if s.trim().is_empty() => {
+                Err("not valid paste content - empty string".to_string())
+            }
+            _ => {
+                check_size(bytes.len(), max_bytes)?;
+                Ok(Self(bytes))
+            }
+        }
     }
 
-    pub fn parse_bytes(bytes: Bytes) -> Result<PasteContent, String> {
+    /// Accept only valid, non-blank UTF-8, for clients that declared text.
+    pub fn parse_text(bytes: Bytes, max_bytes: usize) -> Result<PasteContent, String> {
         match String::from_utf8(bytes.to_vec()) {
-            Ok(p) => PasteContent::parse(p),
-            Err(e) => Err(e.to_owned().to_string()),
+            Ok(p) => PasteContent::parse(p, max_bytes),
+            Err(e) => Err(e.to_string()),
         }
     }
+
+    /// The content as text, if it is valid UTF-8.
+    pub fn as_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    /// Length of the content in bytes
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Identifies the content, so identical pastes can share storage.
+    pub fn hash(&self) -> ContentHash {
+        ContentHash::of(&self.0)
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+fn check_size(len: usize, max_bytes: usize) -> Result<(), String> {
+    if len > max_bytes {
+        return Err(format!(
+            "not valid paste content - larger than {} bytes",
+            max_bytes
+        ));
+    }
+
+    Ok(())
 }
 
-impl AsRef<str> for PasteContent {
-    fn as_ref(&self) -> &str {
+impl AsRef<[u8]> for PasteContent {
+    fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
@@ -30,29 +84,76 @@ impl AsRef<str> for PasteContent {
 #[cfg(test)]
 mod tests {
     use crate::domain::PasteContent;
+    use actix_web::web::Bytes;
     use claims::{assert_err, assert_ok};
 
+    const MAX: usize = 1024;
+
     #[test]
     fn normal_string_is_valid() {
         let name = "a".repeat(256);
-        assert_ok!(PasteContent::parse(name));
+        assert_ok!(PasteContent::parse(name, MAX));
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(PasteContent::parse(name));
+        assert_err!(PasteContent::parse(name, MAX));
     }
 
     #[test]
     fn empty_string_is_rejected() {
         let name = "".to_string();
-        assert_err!(PasteContent::parse(name));
+        assert_err!(PasteContent::parse(name, MAX));
     }
 
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Some name".to_string();
-        assert_ok!(PasteContent::parse(name));
+        assert_ok!(PasteContent::parse(name, MAX));
+    }
+
+    #[test]
+    fn binary_bytes_are_accepted() {
+        let bytes = Bytes::from_static(&[0x89, b'P', b'N', b'G', 0xff, 0x00]);
+        assert_ok!(PasteContent::parse_bytes(bytes, MAX));
+    }
+
+    #[test]
+    fn empty_bytes_are_rejected() {
+        assert_err!(PasteContent::parse_bytes(Bytes::new(), MAX));
+    }
+
+    #[test]
+    fn whitespace_only_bytes_are_rejected() {
+        assert_err!(PasteContent::parse_bytes(Bytes::from_static(b" \n"), MAX));
+    }
+
+    #[test]
+    fn binary_bytes_are_rejected_as_text() {
+        let bytes = Bytes::from_static(&[0xff, 0xfe, 0x00]);
+        assert_err!(PasteContent::parse_text(bytes, MAX));
+    }
+
+    #[test]
+    fn utf8_bytes_are_accepted_as_text() {
+        assert_ok!(PasteContent::parse_text(
+            Bytes::from_static("héllo".as_bytes()),
+            MAX
+        ));
+    }
+
+    #[test]
+    fn content_at_the_limit_is_accepted() {
+        assert_ok!(PasteContent::parse("a".repeat(MAX), MAX));
+    }
+
+    #[test]
+    fn content_over_the_limit_is_rejected() {
+        assert_err!(PasteContent::parse("a".repeat(MAX + 1), MAX));
+        assert_err!(PasteContent::parse_bytes(
+            Bytes::from(vec![0xff; MAX + 1]),
+            MAX
+        ));
     }
 }
@@ -1,6 +1,5 @@
 use serde::Deserialize;
 use unicode_segmentation::UnicodeSegmentation;
-use uuid::Uuid;
 
 #[derive(Debug, Hash, PartialEq, Eq, Deserialize, Clone)]
 pub struct PasteId(String);
@@ -23,11 +22,6 @@ impl PasteId {
 
         Ok(Self(s))
     }
-
-    // TODO: test to ensure parse works
-    pub fn random() -> PasteId {
-        PasteId(Uuid::new_v4().to_string())
-    }
 }
 
 impl AsRef<str> for PasteId {
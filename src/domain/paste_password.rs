@@ -0,0 +1,120 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+
+const SALT_BYTES: usize = 16;
+
+/// Longer passwords only make hashing slower, not guessing harder.
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+/// Password a reader has to give to read a paste. Only its hash is ever
+/// stored.
+#[derive(Debug)]
+pub struct PastePassword(Secret<String>);
+
+impl PastePassword {
+    pub fn parse(s: String) -> Result<PastePassword, String> {
+        if s.is_empty() {
+            return Err("not a valid paste password - empty string".to_string());
+        }
+        if s.len() > MAX_PASSWORD_LENGTH {
+            return Err(format!(
+                "not a valid paste password - longer than {} bytes",
+                MAX_PASSWORD_LENGTH
+            ));
+        }
+
+        Ok(Self(Secret::new(s)))
+    }
+
+    /// Hash with Argon2id under a fresh salt. Memory-hard and deliberately
+    /// slow, so callers on an async runtime should hash on a blocking thread.
+    pub fn hash(&self) -> Result<PastePasswordHash, String> {
+        let mut bytes = [0u8; SALT_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let salt = SaltString::encode_b64(&bytes).map_err(|e| e.to_string())?;
+
+        Argon2::default()
+            .hash_password(self.0.expose_secret().as_bytes(), &salt)
+            .map(|hash| PastePasswordHash(hash.to_string()))
+            .map_err(|e| format!("failed to hash the paste password: {}", e))
+    }
+}
+
+impl ExposeSecret<String> for PastePassword {
+    fn expose_secret(&self) -> &String {
+        self.0.expose_secret()
+    }
+}
+
+/// A `PastePassword` hashed in the PHC string format, which records the
+/// algorithm, parameters and salt alongside the hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PastePasswordHash(String);
+
+impl PastePasswordHash {
+    pub fn parse(s: String) -> Result<PastePasswordHash, String> {
+        match PasswordHash::new(&s) {
+            Ok(_) => Ok(Self(s)),
+            Err(_) => Err("not a valid paste password hash".to_string()),
+        }
+    }
+
+    /// As slow as hashing, so the same goes for calling it.
+    pub fn verify(&self, password: &PastePassword) -> bool {
+        PasswordHash::new(&self.0).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.expose_secret().as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
+impl AsRef<str> for PastePasswordHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{PastePassword, PastePasswordHash};
+
+    fn password(s: &str) -> PastePassword {
+        PastePassword::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn a_password_verifies_against_its_own_hash_only() {
+        let hash = password("hunter2").hash().unwrap();
+        assert!(hash.verify(&password("hunter2")));
+        assert!(!hash.verify(&password("hunter3")));
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        assert_ne!(
+            password("hunter2").hash().unwrap(),
+            password("hunter2").hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn a_hash_round_trips_through_parse() {
+        let hash = password("hunter2").hash().unwrap();
+        assert_ok!(PastePasswordHash::parse(hash.as_ref().to_owned()));
+        assert_err!(PastePasswordHash::parse("hunter2".to_string()));
+    }
+
+    #[test]
+    fn empty_and_overlong_passwords_are_rejected() {
+        assert_err!(PastePassword::parse("".to_string()));
+        assert_err!(PastePassword::parse("a".repeat(1025)));
+        assert_ok!(PastePassword::parse(" ".to_string()));
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::Duration;
+
+/// How long a paste lives before it expires, e.g. `90s`, `30m`, `1h`, `7d` or
+/// `2w`. A bare number is taken as seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasteTtl(Duration);
+
+impl PasteTtl {
+    pub fn parse(s: String) -> Result<PasteTtl, String> {
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(trimmed.len());
+        let (amount, unit) = trimmed.split_at(split);
+
+        let amount: i64 = match amount.parse() {
+            Ok(a) if a > 0 => a,
+            _ => {
+                return Err(format!(
+                    "{} is not a valid ttl - expected e.g. 30m or 1h",
+                    s
+                ))
+            }
+        };
+
+        let seconds_per_unit = match unit {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            _ => return Err(format!("{} is not a valid ttl - unknown unit", s)),
+        };
+
+        amount
+            .checked_mul(seconds_per_unit)
+            .and_then(Duration::try_seconds)
+            .map(Self)
+            .ok_or_else(|| format!("{} is not a valid ttl - too long", s))
+    }
+}
+
+impl AsRef<Duration> for PasteTtl {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::PasteTtl;
+    use chrono::Duration;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn a_bare_number_is_seconds() {
+        assert_ok_eq!(
+            PasteTtl::parse("90".to_string()).map(|t| *t.as_ref()),
+            Duration::seconds(90)
+        );
+    }
+
+    #[test]
+    fn units_are_applied() {
+        for (raw, expected) in [
+            ("45s", Duration::seconds(45)),
+            ("30m", Duration::minutes(30)),
+            ("1h", Duration::hours(1)),
+            ("7d", Duration::days(7)),
+            ("2w", Duration::weeks(2)),
+        ] {
+            assert_ok_eq!(
+                PasteTtl::parse(raw.to_string()).map(|t| *t.as_ref()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn zero_is_rejected() {
+        assert_err!(PasteTtl::parse("0h".to_string()));
+    }
+
+    #[test]
+    fn unknown_units_are_rejected() {
+        assert_err!(PasteTtl::parse("3y".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(PasteTtl::parse("".to_string()));
+    }
+
+    #[test]
+    fn overflowing_values_are_rejected() {
+        assert_err!(PasteTtl::parse(format!("{}w", i64::MAX)));
+    }
+}
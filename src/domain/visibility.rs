@@ -0,0 +1,62 @@
+/// Who may come across a paste.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Visibility {
+    /// Reachable by id and included in listings
+    #[default]
+    Public,
+    /// Reachable only by those who know its id
+    Unlisted,
+    /// Readable only with the API key that owns it
+    Private,
+}
+
+impl Visibility {
+    pub fn parse(s: String) -> Result<Visibility, String> {
+        match s.to_lowercase().as_str() {
+            "public" => Ok(Self::Public),
+            "unlisted" => Ok(Self::Unlisted),
+            "private" => Ok(Self::Private),
+            _ => Err(format!("{} is not a valid visibility", s)),
+        }
+    }
+}
+
+impl AsRef<str> for Visibility {
+    fn as_ref(&self) -> &str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Visibility;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn visibilities_round_trip_through_parse() {
+        for v in [
+            Visibility::Public,
+            Visibility::Unlisted,
+            Visibility::Private,
+        ] {
+            assert_ok_eq!(Visibility::parse(v.as_ref().to_owned()), v);
+        }
+    }
+
+    #[test]
+    fn parse_ignores_case() {
+        assert_ok_eq!(
+            Visibility::parse("Unlisted".to_string()),
+            Visibility::Unlisted
+        );
+    }
+
+    #[test]
+    fn unknown_visibility_is_rejected() {
+        assert_err!(Visibility::parse("secret".to_string()));
+    }
+}
@@ -0,0 +1,507 @@
+/// Just enough of a programming language's syntax to pick out keywords,
+/// strings, comments and numbers.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Language {
+    pub name: &'static str,
+    pub(super) extensions: &'static [&'static str],
+    pub(super) keywords: &'static [&'static str],
+    /// Whether keywords match regardless of case, as in SQL
+    pub(super) case_insensitive: bool,
+    pub(super) line_comments: &'static [&'static str],
+    pub(super) block_comment: Option<(&'static str, &'static str)>,
+    pub(super) quotes: &'static [char],
+}
+
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register", "return",
+    "short", "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned",
+    "void", "volatile", "while", "NULL", "true", "false", "bool",
+];
+
+const CPP_KEYWORDS: &[&str] = &[
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "constexpr",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "explicit",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "namespace",
+    "new",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "while",
+];
+
+const JS_KEYWORDS: &[&str] = &[
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+
+const TS_KEYWORDS: &[&str] = &[
+    "abstract",
+    "any",
+    "as",
+    "async",
+    "await",
+    "boolean",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "declare",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "keyof",
+    "let",
+    "never",
+    "new",
+    "null",
+    "number",
+    "of",
+    "private",
+    "protected",
+    "public",
+    "readonly",
+    "return",
+    "static",
+    "string",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "type",
+    "typeof",
+    "undefined",
+    "unknown",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+
+static LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        // Single quotes also mark lifetimes, so only double quotes delimit strings
+        quotes: &['"'],
+    },
+    Language {
+        name: "python",
+        extensions: &["py", "pyw"],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "try", "while", "with", "yield",
+        ],
+        case_insensitive: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        keywords: JS_KEYWORDS,
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        name: "typescript",
+        extensions: &["ts", "tsx", "mts", "cts"],
+        keywords: TS_KEYWORDS,
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        name: "go",
+        extensions: &["go"],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "fallthrough",
+            "false",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        keywords: C_KEYWORDS,
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "cpp",
+        extensions: &["cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+        keywords: CPP_KEYWORDS,
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "java",
+        extensions: &["java"],
+        keywords: &[
+            "abstract",
+            "boolean",
+            "break",
+            "byte",
+            "case",
+            "catch",
+            "char",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extends",
+            "false",
+            "final",
+            "finally",
+            "float",
+            "for",
+            "if",
+            "implements",
+            "import",
+            "instanceof",
+            "int",
+            "interface",
+            "long",
+            "new",
+            "null",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "short",
+            "static",
+            "super",
+            "switch",
+            "synchronized",
+            "this",
+            "throw",
+            "throws",
+            "true",
+            "try",
+            "var",
+            "void",
+            "volatile",
+            "while",
+        ],
+        case_insensitive: false,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "ruby",
+        extensions: &["rb"],
+        keywords: &[
+            "begin", "break", "case", "class", "def", "do", "else", "elsif", "end", "ensure",
+            "false", "for", "if", "in", "module", "next", "nil", "not", "or", "and", "redo",
+            "rescue", "retry", "return", "self", "super", "then", "true", "unless", "until",
+            "when", "while", "yield",
+        ],
+        case_insensitive: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "until", "while",
+        ],
+        case_insensitive: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        keywords: &[
+            "add",
+            "all",
+            "alter",
+            "and",
+            "as",
+            "asc",
+            "by",
+            "case",
+            "create",
+            "delete",
+            "desc",
+            "distinct",
+            "drop",
+            "else",
+            "end",
+            "exists",
+            "from",
+            "group",
+            "having",
+            "in",
+            "index",
+            "insert",
+            "into",
+            "is",
+            "join",
+            "key",
+            "left",
+            "limit",
+            "not",
+            "null",
+            "on",
+            "or",
+            "order",
+            "primary",
+            "references",
+            "select",
+            "set",
+            "table",
+            "then",
+            "union",
+            "update",
+            "values",
+            "when",
+            "where",
+        ],
+        case_insensitive: true,
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+    },
+    Language {
+        name: "json",
+        extensions: &["json"],
+        keywords: &["true", "false", "null"],
+        case_insensitive: false,
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        keywords: &["true", "false"],
+        case_insensitive: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Language {
+        name: "yaml",
+        extensions: &["yaml", "yml"],
+        keywords: &["true", "false", "null", "yes", "no"],
+        case_insensitive: false,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+];
+
+impl Language {
+    /// Look a language up by name or file extension, e.g. `rust` or `rs`.
+    pub fn find(name: &str) -> Option<&'static Language> {
+        let name = name.to_lowercase();
+
+        LANGUAGES
+            .iter()
+            .find(|l| l.name == name || l.extensions.contains(&name.as_str()))
+    }
+
+    /// Infer a language from the extension of a file name such as `foo.rs`.
+    pub fn infer(file_name: &str) -> Option<&'static Language> {
+        match file_name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => Language::find(ext),
+            _ => None,
+        }
+    }
+
+    pub(super) fn is_keyword(&self, word: &str) -> bool {
+        if self.case_insensitive {
+            self.keywords.iter().any(|k| k.eq_ignore_ascii_case(word))
+        } else {
+            self.keywords.contains(&word)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Language;
+    use claims::{assert_none, assert_some_eq};
+
+    #[test]
+    fn languages_are_found_by_name_or_extension() {
+        assert_some_eq!(Language::find("rust").map(|l| l.name), "rust");
+        assert_some_eq!(Language::find("RS").map(|l| l.name), "rust");
+        assert_some_eq!(Language::find("yml").map(|l| l.name), "yaml");
+    }
+
+    #[test]
+    fn language_is_inferred_from_extension() {
+        assert_some_eq!(Language::infer("foo.rs").map(|l| l.name), "rust");
+        assert_some_eq!(Language::infer("a.b.py").map(|l| l.name), "python");
+    }
+
+    #[test]
+    fn unknown_or_missing_extensions_infer_nothing() {
+        assert_none!(Language::infer("foo"));
+        assert_none!(Language::infer("foo.unknown"));
+        assert_none!(Language::infer(".rs"));
+    }
+}
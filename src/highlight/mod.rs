@@ -0,0 +1,251 @@
+//! A small, dependency-free syntax highlighter for the HTML view of a paste.
+//!
+//! It only tells keywords, strings, comments and numbers apart, which is as
+//! much as a paste viewer needs.
+
+mod language;
+
+pub use language::Language;
+
+/// The kinds of token that get their own CSS class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+impl Class {
+    fn css(self) -> &'static str {
+        match self {
+            Class::Keyword => "kw",
+            Class::String => "str",
+            Class::Comment => "com",
+            Class::Number => "num",
+        }
+    }
+}
+
+/// Highlight `text`, returning one HTML fragment per line. Tokens spanning
+/// several lines, such as block comments, are closed and reopened at each
+/// line break so every line stands alone. Without a language, the text is
+/// only escaped.
+pub fn highlight(text: &str, language: Option<&Language>) -> Vec<String> {
+    let tokens = match language {
+        Some(language) => tokenize(text, language),
+        None => vec![(None, text)],
+    };
+
+    let mut lines = vec![String::new()];
+
+    for (class, token) in tokens {
+        for (i, part) in token.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            if part.is_empty() {
+                continue;
+            }
+
+            let line = lines.last_mut().expect("there is always a current line");
+            match class {
+                Some(class) => {
+                    line.push_str(&format!(
+                        "<span class=\"{}\">{}</span>",
+                        class.css(),
+                        escape_html(part)
+                    ));
+                }
+                None => line.push_str(&escape_html(part)),
+            }
+        }
+    }
+
+    // A trailing newline ends the last line rather than starting a new one
+    if text.ends_with('\n') {
+        lines.pop();
+    }
+
+    lines
+}
+
+/// Escape text for inclusion in HTML content or a quoted attribute.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Split `text` into tokens, merging runs of unclassified text.
+fn tokenize<'a>(text: &'a str, language: &Language) -> Vec<(Option<Class>, &'a str)> {
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().expect("i is within text");
+
+        let (class, len) = if let Some(len) = comment_len(rest, language) {
+            (Some(Class::Comment), len)
+        } else if language.quotes.contains(&c) {
+            (Some(Class::String), string_len(rest, c))
+        } else if c.is_ascii_digit() {
+            (Some(Class::Number), word_len(rest))
+        } else if c.is_alphabetic() || c == '_' {
+            // Consume whole words, so keywords inside identifiers are not matched
+            let len = word_len(rest);
+            let class = language.is_keyword(&rest[..len]).then_some(Class::Keyword);
+            (class, len)
+        } else {
+            (None, c.len_utf8())
+        };
+
+        if let Some(class) = class {
+            if plain_start < i {
+                tokens.push((None, &text[plain_start..i]));
+            }
+            tokens.push((Some(class), &text[i..i + len]));
+            plain_start = i + len;
+        }
+
+        i += len;
+    }
+
+    if plain_start < text.len() {
+        tokens.push((None, &text[plain_start..]));
+    }
+
+    tokens
+}
+
+/// Length of the comment starting `s`, if it starts with one. An unterminated
+/// block comment runs to the end of the text.
+fn comment_len(s: &str, language: &Language) -> Option<usize> {
+    if let Some((open, close)) = language.block_comment {
+        if let Some(body) = s.strip_prefix(open) {
+            return Some(match body.find(close) {
+                Some(end) => open.len() + end + close.len(),
+                None => s.len(),
+            });
+        }
+    }
+
+    language
+        .line_comments
+        .iter()
+        .any(|prefix| s.starts_with(prefix))
+        .then(|| s.find('\n').unwrap_or(s.len()))
+}
+
+/// Length of the string starting `s` with `quote`, honouring backslash
+/// escapes. Only backtick strings may span lines; other unterminated strings
+/// end with the line.
+fn string_len(s: &str, quote: char) -> usize {
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+
+    s.len()
+}
+
+/// Length of the identifier or number starting `s`.
+fn word_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_html, highlight, Language};
+
+    fn rust() -> Option<&'static Language> {
+        Language::find("rust")
+    }
+
+    #[test]
+    fn keywords_strings_and_numbers_are_classified() {
+        let lines = highlight("let s = \"hi\"; 42", rust());
+        assert_eq!(
+            vec![
+                "<span class=\"kw\">let</span> s = <span class=\"str\">&quot;hi&quot;</span>; \
+                 <span class=\"num\">42</span>"
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn keywords_inside_identifiers_are_not_highlighted() {
+        assert_eq!(vec!["letter"], highlight("letter", rust()));
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_a_string() {
+        let lines = highlight(r#""a\"b" c"#, rust());
+        assert_eq!(
+            vec!["<span class=\"str\">&quot;a\\&quot;b&quot;</span> c"],
+            lines
+        );
+    }
+
+    #[test]
+    fn block_comments_are_split_per_line() {
+        let lines = highlight("/* a\nb */ fn", rust());
+        assert_eq!(
+            vec![
+                "<span class=\"com\">/* a</span>",
+                "<span class=\"com\">b */</span> <span class=\"kw\">fn</span>",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn line_comments_end_at_the_newline() {
+        let lines = highlight("// fn\nfn", rust());
+        assert_eq!(
+            vec![
+                "<span class=\"com\">// fn</span>",
+                "<span class=\"kw\">fn</span>"
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn text_without_a_language_is_only_escaped() {
+        assert_eq!(
+            vec!["&lt;script&gt;", "fn"],
+            highlight("<script>\nfn\n", None)
+        );
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;",
+            escape_html("<a href=\"x\">&'")
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Keys that repository backends encrypt paste content with at rest.
+//!
+//! Content is sealed with AES-256-GCM under the keyring's current key, and
+//! the id of that key is stored alongside it. Older keys stay in the keyring
+//! so content sealed before a rotation can still be read until it has been
+//! re-encrypted.
+//!
+//! Content is deduplicated under its hash, which is stored in the clear so it
+//! can be looked up. With an index key that is a keyed hash of the content,
+//! so whoever can read the storage cannot confirm guesses at what it holds.
+
+use std::{collections::HashMap, fmt, io};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::domain::ContentHash;
+
+pub struct Keyring {
+    /// The key new content is sealed with. Without one, new content is
+    /// stored in the clear.
+    current: Option<String>,
+    keys: HashMap<String, LessSafeKey>,
+    /// The key content hashes are keyed with, see `index`
+    index_key: Option<hmac::Key>,
+    rng: SystemRandom,
+}
+
+impl Keyring {
+    /// `keys` maps key ids to base64-encoded 256-bit keys, and `current`
+    /// names the one to seal new content with.
+    pub fn new(
+        current: Option<String>,
+        keys: &HashMap<String, Secret<String>>,
+    ) -> Result<Self, String> {
+        let keys = keys
+            .iter()
+            .map(|(id, key)| {
+                if id.is_empty() || id.contains(char::is_whitespace) {
+                    return Err(format!("{:?} is not a valid key id", id));
+                }
+
+                let bytes = BASE64
+                    .decode(key.expose_secret().trim())
+                    .map_err(|_| format!("key {} is not valid base64", id))?;
+                let key = UnboundKey::new(&AES_256_GCM, &bytes)
+                    .map_err(|_| format!("key {} is not 256 bits long", id))?;
+
+                Ok((id.clone(), LessSafeKey::new(key)))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        if let Some(current) = current.as_ref().filter(|c| !keys.contains_key(*c)) {
+            return Err(format!("the current key {} is not among the keys", current));
+        }
+
+        Ok(Self {
+            current,
+            keys,
+            index_key: None,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Index content under hashes keyed with `key`, a base64-encoded 256-bit
+    /// key. Unlike the encryption keys it cannot be rotated: content indexed
+    /// under one key is not found under another.
+    pub fn with_index_key(self, key: &Secret<String>) -> Result<Self, String> {
+        let bytes = BASE64
+            .decode(key.expose_secret().trim())
+            .map_err(|_| "the index key is not valid base64".to_string())?;
+        if bytes.len() != 32 {
+            return Err("the index key is not 256 bits long".to_string());
+        }
+
+        Ok(Self {
+            index_key: Some(hmac::Key::new(hmac::HMAC_SHA256, &bytes)),
+            ..self
+        })
+    }
+
+    /// The id of the key new content is sealed with, if any
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    fn key(&self, id: &str) -> io::Result<&LessSafeKey> {
+        self.keys
+            .get(id)
+            .ok_or_else(|| invalid_data(format!("unknown encryption key {}", id)))
+    }
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("current", &self.current)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .field("index_key", &self.index_key.is_some())
+            .finish()
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The hash content with SHA-256 `hash` is stored and deduplicated under:
+/// its HMAC-SHA256 under the index key if there is one, `hash` itself if not.
+pub fn index(keyring: Option<&Keyring>, hash: ContentHash) -> ContentHash {
+    match keyring.and_then(|k| k.index_key.as_ref()) {
+        Some(key) => {
+            let tag = hmac::sign(key, hash.as_ref().as_bytes());
+            ContentHash::parse(hex::encode(tag.as_ref()))
+                .expect("HMAC-SHA256 tags are as long as SHA-256 digests")
+        }
+        None => hash,
+    }
+}
+
+/// Seal `data` for storage as the content with `hash`, returning the id of
+/// the key used. Data is stored as is when there is no current key.
+///
+/// The sealed form is the nonce, the ciphertext and the tag, in that order.
+/// The hash is bound in as associated data, so sealed content cannot be
+/// passed off as some other content.
+pub fn seal(
+    keyring: Option<&Keyring>,
+    hash: &ContentHash,
+    mut data: Vec<u8>,
+) -> io::Result<(Option<String>, Vec<u8>)> {
+    let Some((keyring, id)) = keyring.and_then(|k| Some((k, k.current()?))) else {
+        return Ok((None, data));
+    };
+
+    let mut nonce = [0; NONCE_LEN];
+    keyring
+        .rng
+        .fill(&mut nonce)
+        .map_err(|_| io::Error::other("failed to generate a nonce"))?;
+
+    keyring
+        .key(id)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(hash.as_ref()),
+            &mut data,
+        )
+        .map_err(|_| io::Error::other("failed to encrypt content"))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.append(&mut data);
+
+    Ok((Some(id.to_owned()), sealed))
+}
+
+/// Recover content stored by `seal` under the key `key_id`.
+pub fn open(
+    keyring: Option<&Keyring>,
+    key_id: Option<String>,
+    hash: &ContentHash,
+    stored: Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    let Some(key_id) = key_id else {
+        return Ok(stored);
+    };
+    let keyring = keyring.ok_or_else(|| {
+        invalid_data(format!(
+            "content is encrypted with key {}, but no keys are configured",
+            key_id
+        ))
+    })?;
+
+    if stored.len() < NONCE_LEN {
+        return Err(invalid_data("encrypted content is truncated".to_string()));
+    }
+    let (nonce, sealed) = stored.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| invalid_data("invalid nonce".to_string()))?;
+
+    let mut data = sealed.to_vec();
+    let len = keyring
+        .key(&key_id)?
+        .open_in_place(nonce, Aad::from(hash.as_ref()), &mut data)
+        .map_err(|_| invalid_data(format!("content failed to decrypt with key {}", key_id)))?
+        .len();
+    data.truncate(len);
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{index, open, seal, Keyring};
+    use crate::domain::ContentHash;
+
+    const KEY_1: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+    const KEY_2: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+    fn keyring(current: Option<&str>, keys: &[(&str, &str)]) -> Result<Keyring, String> {
+        let keys: HashMap<_, _> = keys
+            .iter()
+            .map(|(id, key)| (id.to_string(), Secret::new(key.to_string())))
+            .collect();
+        Keyring::new(current.map(str::to_owned), &keys)
+    }
+
+    #[test]
+    fn sealed_content_round_trips() {
+        let keyring = keyring(Some("k1"), &[("k1", KEY_1)]).unwrap();
+        let hash = ContentHash::of(b"secret");
+
+        let (key_id, stored) = seal(Some(&keyring), &hash, b"secret".to_vec()).unwrap();
+        assert_eq!(Some("k1".to_string()), key_id);
+        assert!(!stored.windows(6).any(|w| w == b"secret"));
+
+        let content = open(Some(&keyring), key_id, &hash, stored).unwrap();
+        assert_eq!(b"secret".to_vec(), content);
+    }
+
+    #[test]
+    fn content_is_stored_as_is_without_a_current_key() {
+        let keyring = keyring(None, &[("k1", KEY_1)]).unwrap();
+        let hash = ContentHash::of(b"plain");
+
+        let (key_id, stored) = seal(Some(&keyring), &hash, b"plain".to_vec()).unwrap();
+        assert_eq!(None, key_id);
+        assert_eq!(b"plain".to_vec(), stored);
+    }
+
+    #[test]
+    fn retired_keys_still_open_content() {
+        let old = keyring(Some("k1"), &[("k1", KEY_1)]).unwrap();
+        let new = keyring(Some("k2"), &[("k1", KEY_1), ("k2", KEY_2)]).unwrap();
+        let hash = ContentHash::of(b"secret");
+
+        let (key_id, stored) = seal(Some(&old), &hash, b"secret".to_vec()).unwrap();
+        assert_ok!(open(Some(&new), key_id, &hash, stored));
+    }
+
+    #[test]
+    fn content_sealed_for_another_hash_fails_to_open() {
+        let keyring = keyring(Some("k1"), &[("k1", KEY_1)]).unwrap();
+
+        let (key_id, stored) = seal(Some(&keyring), &ContentHash::of(b"a"), b"a".to_vec()).unwrap();
+        assert_err!(open(Some(&keyring), key_id, &ContentHash::of(b"b"), stored));
+    }
+
+    #[test]
+    fn unknown_and_missing_keys_fail_to_open() {
+        let keyring = keyring(Some("k1"), &[("k1", KEY_1)]).unwrap();
+        let hash = ContentHash::of(b"secret");
+        let (_, stored) = seal(Some(&keyring), &hash, b"secret".to_vec()).unwrap();
+
+        assert_err!(open(
+            Some(&keyring),
+            Some("k9".to_string()),
+            &hash,
+            stored.clone()
+        ));
+        assert_err!(open(None, Some("k1".to_string()), &hash, stored));
+    }
+
+    #[test]
+    fn content_is_indexed_under_a_keyed_hash_with_an_index_key() {
+        let hash = ContentHash::of(b"secret");
+        let plain = keyring(Some("k1"), &[("k1", KEY_1)]).unwrap();
+        let indexed = |key: &str| {
+            let keyring = keyring(Some("k1"), &[("k1", KEY_1)])
+                .unwrap()
+                .with_index_key(&Secret::new(key.to_string()))
+                .unwrap();
+            index(Some(&keyring), hash.clone())
+        };
+
+        assert_eq!(hash, index(None, hash.clone()));
+        assert_eq!(hash, index(Some(&plain), hash.clone()));
+        assert_ne!(hash, indexed(KEY_1));
+        assert_eq!(indexed(KEY_1), indexed(KEY_1));
+        assert_ne!(indexed(KEY_1), indexed(KEY_2));
+    }
+
+    #[test]
+    fn invalid_index_keys_are_rejected() {
+        let keyring = || keyring(None, &[]).unwrap();
+
+        assert_err!(keyring().with_index_key(&Secret::new("c2hvcnQ=".to_string())));
+        assert_err!(keyring().with_index_key(&Secret::new("not base64".to_string())));
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        assert_err!(keyring(Some("k1"), &[("k1", "c2hvcnQ=")]));
+        assert_err!(keyring(Some("k1"), &[("k1", "not base64")]));
+        assert_err!(keyring(Some("k1"), &[("k 1", KEY_1)]));
+        assert_err!(keyring(Some("k2"), &[("k1", KEY_1)]));
+    }
+}
@@ -1,6 +1,10 @@
+pub mod compression;
 pub mod config;
 pub mod dao;
 pub mod domain;
+pub mod highlight;
+pub mod keyring;
+pub mod rate_limit;
 pub mod routes;
 pub mod startup;
 pub mod telemetry;
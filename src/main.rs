@@ -1,22 +1,160 @@
+use secrecy::ExposeSecret;
 use std::net::TcpListener;
 use whtpst::{
-    config::get_config,
-    dao::{InMemoryRepository, Repository},
+    config::{get_config, StorageBackend},
+    dao::{FileSystemRepository, InMemoryRepository, KeyStore, SqliteRepository},
+    domain::{ApiKey, StoredApiKey},
     startup::run,
     telemetry::{get_subscriber, init_subscriber},
 };
 
+/// What the binary was asked to do
+enum Command {
+    /// Serve pastes; the default
+    Serve,
+    /// Re-encrypt stored content with the current key, then exit
+    Reencrypt,
+    /// Manage API keys, then exit
+    Keys(KeyCommand),
+}
+
+enum KeyCommand {
+    /// Issue a new key under a name, printing it
+    Create(String),
+    List,
+    /// Revoke the key with a name
+    Revoke(String),
+}
+
+fn invalid_input(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let subscriber = get_subscriber("whtpst".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] | ["serve"] => Command::Serve,
+        ["reencrypt"] => Command::Reencrypt,
+        ["create-key", name] => Command::Keys(KeyCommand::Create(name.to_owned())),
+        ["list-keys"] => Command::Keys(KeyCommand::List),
+        ["revoke-key", name] => Command::Keys(KeyCommand::Revoke(name.to_owned())),
+        _ => {
+            return Err(invalid_input(format!(
+                "unknown command {} - use serve, reencrypt, create-key NAME, list-keys or \
+                 revoke-key NAME",
+                args.join(" ")
+            )))
+        }
+    };
+
     let config = get_config().expect("Failed to read config file");
-    let repo = InMemoryRepository::new();
 
-    let address = format!("{}:{}", config.application.host, config.application.port);
-    let listener = TcpListener::bind(address)?;
+    let codec = config
+        .storage
+        .compression
+        .codec()
+        .expect("Invalid compression settings");
+    let keyring = config
+        .storage
+        .encryption
+        .keyring()
+        .expect("Invalid encryption settings");
+
+    let rewritten = match (config.storage.backend, command) {
+        (StorageBackend::InMemory, Command::Keys(_)) => {
+            return Err(invalid_input(
+                "the inmemory backend keeps no keys between runs".to_string(),
+            ));
+        }
+        (StorageBackend::Sqlite, Command::Keys(command)) => {
+            let store = SqliteRepository::open(&config.storage.database_path)
+                .expect("Failed to open the sqlite database");
+            return manage_keys(store, command).await;
+        }
+        (StorageBackend::FileSystem, Command::Keys(command)) => {
+            let store = FileSystemRepository::open(&config.storage.directory)
+                .expect("Failed to open the storage directory");
+            return manage_keys(store, command).await;
+        }
+        (StorageBackend::InMemory, Command::Reencrypt) => {
+            tracing::info!("The inmemory backend keeps nothing at rest to re-encrypt");
+            return Ok(());
+        }
+        (StorageBackend::Sqlite, Command::Reencrypt) => {
+            SqliteRepository::open(&config.storage.database_path)
+                .expect("Failed to open the sqlite database")
+                .with_encryption(keyring)
+                .reencrypt()
+                .await
+        }
+        (StorageBackend::FileSystem, Command::Reencrypt) => {
+            FileSystemRepository::open(&config.storage.directory)
+                .expect("Failed to open the storage directory")
+                .with_compression(codec)
+                .with_encryption(keyring)
+                .reencrypt()
+                .await
+        }
+        (backend, Command::Serve) => {
+            let address = format!("{}:{}", config.application.host, config.application.port);
+            let listener = TcpListener::bind(address)?;
+
+            match backend {
+                StorageBackend::InMemory => {
+                    run(listener, InMemoryRepository::new(), config)?.await?
+                }
+                StorageBackend::Sqlite => {
+                    let repo = SqliteRepository::open(&config.storage.database_path)
+                        .expect("Failed to open the sqlite database")
+                        .with_compression(codec)
+                        .with_encryption(keyring);
+                    run(listener, repo, config)?.await?
+                }
+                StorageBackend::FileSystem => {
+                    let repo = FileSystemRepository::open(&config.storage.directory)
+                        .expect("Failed to open the storage directory")
+                        .with_compression(codec)
+                        .with_encryption(keyring);
+                    run(listener, repo, config)?.await?
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let rewritten = rewritten.map_err(|e| std::io::Error::other(e.to_string()))?;
+    tracing::info!("Re-encrypted {} stored records", rewritten);
+
+    Ok(())
+}
+
+async fn manage_keys<K: KeyStore>(store: K, command: KeyCommand) -> std::io::Result<()> {
+    let to_io = |e: whtpst::dao::RepositoryError| std::io::Error::other(e.to_string());
+
+    match command {
+        KeyCommand::Create(name) => {
+            let key = ApiKey::generate();
+            let stored = StoredApiKey::new(name, &key).map_err(invalid_input)?;
+            store.insert_key(stored).await.map_err(to_io)?;
+            // The key is shown once; only its hash is kept
+            println!("{}", key.expose_secret());
+        }
+        KeyCommand::List => {
+            let mut keys = store.list_keys().await.map_err(to_io)?;
+            keys.sort_by(|a, b| a.name.cmp(&b.name));
+            for key in keys {
+                println!("{}\t{}", key.name, key.created_at.to_rfc3339());
+            }
+        }
+        KeyCommand::Revoke(name) => {
+            store.delete_key(name.clone()).await.map_err(to_io)?;
+            tracing::info!("Revoked API key {}", name);
+        }
+    }
 
-    run(listener, repo)?.await?;
     Ok(())
 }
@@ -0,0 +1,432 @@
+//! Per-client rate limiting.
+//!
+//! Each client gets a token bucket per kind of request: every request takes a
+//! token, and tokens refill at a steady rate up to the bucket's size. Clients
+//! are told apart by IP address, read from `X-Forwarded-For` only when the
+//! request comes through a trusted proxy.
+//!
+//! Wrong guesses at a paste's password are limited the same way, with a
+//! bucket per paste rather than per client, so spreading guesses over many
+//! addresses does not help.
+
+use std::{
+    borrow::Borrow,
+    future::{ready, Future, Ready},
+    hash::Hash,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header::RETRY_AFTER, Method},
+    Error, HttpResponse,
+};
+use dashmap::DashMap;
+use ipnet::IpNet;
+
+use crate::{
+    config::{BucketSettings, RateLimitSettings},
+    domain::PasteId,
+};
+
+/// Paths that are never limited, so health checks keep working under load.
+const EXEMPT_PATHS: &[&str] = &["/health"];
+
+pub struct RateLimiter {
+    trusted_proxies: Vec<IpNet>,
+    create: Option<Buckets<IpAddr>>,
+    read: Option<Buckets<IpAddr>>,
+    passwords: Option<Buckets<String>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Result<Self, String> {
+        let trusted_proxies = settings
+            .trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("{} is not an IP address or range", proxy))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            trusted_proxies,
+            create: settings.create.map(Buckets::new).transpose()?,
+            read: settings.read.map(Buckets::new).transpose()?,
+            passwords: settings.passwords.map(Buckets::new).transpose()?,
+        })
+    }
+
+    /// Take a token for the request, or say how long until one is available.
+    fn check(&self, req: &ServiceRequest, now: Instant) -> Result<(), Duration> {
+        if EXEMPT_PATHS.contains(&req.path()) {
+            return Ok(());
+        }
+
+        // Anything that is not a read writes pastes
+        let buckets = match *req.method() {
+            Method::GET | Method::HEAD | Method::OPTIONS => &self.read,
+            _ => &self.create,
+        };
+        let (Some(buckets), Some(peer)) = (buckets, req.peer_addr()) else {
+            return Ok(());
+        };
+
+        let forwarded_for = req
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|v| v.to_str().ok());
+
+        buckets.take(self.client_ip(peer.ip(), forwarded_for), now)
+    }
+
+    /// The address of the client behind the request. Addresses in
+    /// `X-Forwarded-For` are only believed as far back as they were added by
+    /// trusted proxies: the rightmost untrusted one is the client, as anything
+    /// before it may have been made up.
+    fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        let mut client = peer;
+
+        if let Some(forwarded_for) = forwarded_for {
+            for hop in forwarded_for.rsplit(',') {
+                if !self.is_trusted(client) {
+                    break;
+                }
+                match hop.trim().parse() {
+                    Ok(ip) => client = ip,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        client
+    }
+
+    /// Whether `ip` is one of the proxies whose forwarding headers are
+    /// believed.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether the password of `paste` may be guessed at, or how long until
+    /// it may be. Only wrong guesses use up tokens, so readers who know the
+    /// password are only held up while someone else is guessing.
+    pub fn check_password(&self, paste: &PasteId, now: Instant) -> Result<(), Duration> {
+        match &self.passwords {
+            Some(buckets) => buckets.peek(paste.as_ref(), now),
+            None => Ok(()),
+        }
+    }
+
+    /// Take a token for a wrong guess at the password of `paste`.
+    pub fn wrong_password(&self, paste: &PasteId, now: Instant) {
+        if let Some(buckets) = &self.passwords {
+            // Running out is only enforced on the next guess
+            let _ = buckets.take(paste.as_ref().to_owned(), now);
+        }
+    }
+
+    /// Forget clients and pastes whose buckets have refilled, as they are no
+    /// different from ones never seen.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        for buckets in [&self.create, &self.read].into_iter().flatten() {
+            buckets.sweep(now);
+        }
+        if let Some(buckets) = &self.passwords {
+            buckets.sweep(now);
+        }
+    }
+}
+
+/// The token buckets of every client or paste, for one kind of request.
+struct Buckets<K> {
+    /// Most tokens a bucket holds
+    capacity: f64,
+    /// Tokens added per second
+    rate: f64,
+    buckets: DashMap<K, Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    fn new(settings: BucketSettings) -> Result<Self, String> {
+        if settings.burst == 0 || settings.per_minute == 0 {
+            return Err("rate limits need a burst and rate of at least 1".to_string());
+        }
+
+        Ok(Self {
+            capacity: settings.burst.into(),
+            rate: f64::from(settings.per_minute) / 60.0,
+            buckets: DashMap::new(),
+        })
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.capacity);
+        bucket.updated = now;
+    }
+
+    fn take(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        self.refill(&mut bucket, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.wait(&bucket))
+        }
+    }
+
+    /// Like `take`, but leaving the token in the bucket.
+    fn peek<Q>(&self, key: &Q, now: Instant) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(mut bucket) = self.buckets.get_mut(key) else {
+            return Ok(());
+        };
+        self.refill(&mut bucket, now);
+
+        match bucket.tokens >= 1.0 {
+            true => Ok(()),
+            false => Err(self.wait(&bucket)),
+        }
+    }
+
+    /// How long until `bucket` holds a whole token.
+    fn wait(&self, bucket: &Bucket) -> Duration {
+        Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+    }
+
+    fn sweep(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            self.refill(bucket, now);
+            bucket.tokens < self.capacity
+        });
+    }
+}
+
+/// Middleware turning away clients that have run out of tokens with
+/// `429 Too Many Requests`.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            limiter: Arc::clone(&self.limiter),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Err(wait) = self.limiter.check(&req, Instant::now()) {
+            let response = too_many(wait, "Too many requests");
+            return Box::pin(ready(Ok(req.into_response(response).map_into_right_body())));
+        }
+
+        let response = self.service.call(req);
+        Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+    }
+}
+
+/// `429 Too Many Requests`, saying `what` there were too many of and how
+/// long to wait.
+pub fn too_many(wait: Duration, what: &str) -> HttpResponse {
+    // Round up, so a client that waits as told is let through
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, secs.to_string()))
+        .body(format!("{}: retry in {} seconds", what, secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::IpAddr,
+        time::{Duration, Instant},
+    };
+
+    use claims::{assert_err, assert_ok};
+
+    use super::{Buckets, RateLimiter};
+    use crate::{
+        config::{BucketSettings, RateLimitSettings},
+        domain::PasteId,
+    };
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn limiter(trusted_proxies: &[&str]) -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+            create: None,
+            read: None,
+            passwords: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn buckets_allow_a_burst_then_refill_steadily() {
+        let buckets = Buckets::new(BucketSettings {
+            burst: 2,
+            per_minute: 60,
+        })
+        .unwrap();
+        let client = ip("192.0.2.1");
+        let start = Instant::now();
+
+        assert_ok!(buckets.take(client, start));
+        assert_ok!(buckets.take(client, start));
+        let wait = buckets.take(client, start).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+
+        // Other clients have buckets of their own
+        assert_ok!(buckets.take(ip("192.0.2.2"), start));
+
+        assert_ok!(buckets.take(client, start + Duration::from_secs(1)));
+        assert_err!(buckets.take(client, start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn refilled_buckets_are_swept() {
+        let buckets = Buckets::new(BucketSettings {
+            burst: 1,
+            per_minute: 60,
+        })
+        .unwrap();
+        let start = Instant::now();
+
+        buckets.take(ip("192.0.2.1"), start).unwrap();
+        buckets.sweep(start);
+        assert_eq!(1, buckets.buckets.len());
+
+        buckets.sweep(start + Duration::from_secs(1));
+        assert_eq!(0, buckets.buckets.len());
+    }
+
+    #[test]
+    fn only_wrong_passwords_use_up_a_paste_s_tokens() {
+        let limiter = RateLimiter::new(&RateLimitSettings {
+            trusted_proxies: vec![],
+            create: None,
+            read: None,
+            passwords: Some(BucketSettings {
+                burst: 2,
+                per_minute: 60,
+            }),
+        })
+        .unwrap();
+        let paste = PasteId::parse("secret".to_string()).unwrap();
+        let start = Instant::now();
+
+        assert_ok!(limiter.check_password(&paste, start));
+        assert_ok!(limiter.check_password(&paste, start));
+        limiter.wrong_password(&paste, start);
+        assert_ok!(limiter.check_password(&paste, start));
+        limiter.wrong_password(&paste, start);
+        assert_err!(limiter.check_password(&paste, start));
+
+        // Other pastes have buckets of their own
+        let other = PasteId::parse("other".to_string()).unwrap();
+        assert_ok!(limiter.check_password(&other, start));
+
+        assert_ok!(limiter.check_password(&paste, start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let limiter = limiter(&["10.0.0.0/8"]);
+
+        assert_eq!(
+            ip("192.0.2.1"),
+            limiter.client_ip(ip("192.0.2.1"), Some("198.51.100.7"))
+        );
+    }
+
+    #[test]
+    fn forwarded_for_is_followed_back_through_trusted_proxies() {
+        let limiter = limiter(&["10.0.0.0/8", "127.0.0.1"]);
+
+        assert_eq!(
+            ip("198.51.100.7"),
+            limiter.client_ip(ip("127.0.0.1"), Some("203.0.113.9, 198.51.100.7, 10.1.2.3"))
+        );
+        assert_eq!(
+            ip("127.0.0.1"),
+            limiter.client_ip(ip("127.0.0.1"), Some("not an ip"))
+        );
+        assert_eq!(ip("127.0.0.1"), limiter.client_ip(ip("127.0.0.1"), None));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(RateLimiter::new(&RateLimitSettings {
+            trusted_proxies: vec!["localhost".to_string()],
+            create: None,
+            read: None,
+            passwords: None,
+        })
+        .is_err());
+        assert!(Buckets::<IpAddr>::new(BucketSettings {
+            burst: 0,
+            per_minute: 60,
+        })
+        .is_err());
+    }
+}
@@ -1,7 +1,9 @@
 mod health;
 mod index;
 mod paste;
+mod usage;
 
 pub use health::*;
 pub use index::*;
 pub use paste::*;
+pub use usage::*;
@@ -0,0 +1,153 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    error::InternalError,
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    web, Error, FromRequest, HttpRequest, HttpResponse,
+};
+
+use super::{options::header, TOKEN_HEADER};
+use crate::{
+    dao::{KeyStore, RepositoryError},
+    domain::{ApiKey, OwnerToken, Paste, StoredApiKey, Visibility},
+};
+
+/// Pass on a paste found for a reader holding `key`, if any, treating a
+/// private paste the key does not own as missing, so that its existence does
+/// not leak.
+pub(super) fn readable(
+    found: Result<Paste, RepositoryError>,
+    key: Option<&StoredApiKey>,
+) -> Result<Paste, RepositoryError> {
+    match found {
+        Ok(p) if !p.is_readable_by(key.map(|k| k.name.as_str())) => {
+            Err(RepositoryError::NotFound(p.id.as_ref().to_owned()))
+        }
+        found => found,
+    }
+}
+
+/// Check that the request carries the owner token of `paste`, or the API key
+/// that owns it, producing the response to send back if it does not. Those
+/// who cannot manage a private paste are told it does not exist.
+///
+/// The response is boxed, as an `HttpResponse` is too large to be passed
+/// back in every `Result`.
+pub(super) fn check_owner(
+    req: &HttpRequest,
+    paste: &Paste,
+    key: Option<&StoredApiKey>,
+) -> Result<(), Box<HttpResponse>> {
+    if key.is_some_and(|key| paste.owner.as_ref() == Some(&key.name)) {
+        return Ok(());
+    }
+
+    check_token(req, paste).map_err(|response| match paste.visibility {
+        Visibility::Private => Box::new(HttpResponse::from_error(RepositoryError::NotFound(
+            paste.id.as_ref().to_owned(),
+        ))),
+        _ => response,
+    })
+}
+
+fn check_token(req: &HttpRequest, paste: &Paste) -> Result<(), Box<HttpResponse>> {
+    let token = match header(req, TOKEN_HEADER) {
+        Ok(Some(t)) => t,
+        Ok(None) => {
+            return Err(Box::new(
+                HttpResponse::Unauthorized().body(format!("missing {} header", TOKEN_HEADER)),
+            ))
+        }
+        Err(e) => return Err(Box::new(HttpResponse::BadRequest().body(e))),
+    };
+
+    let token =
+        OwnerToken::parse(token).map_err(|e| Box::new(HttpResponse::BadRequest().body(e)))?;
+
+    match &paste.owner_token_hash {
+        Some(hash) if hash.verify(&token) => Ok(()),
+        _ => Err(Box::new(
+            HttpResponse::Forbidden().body("invalid paste token"),
+        )),
+    }
+}
+
+/// The API key a request carries as `Authorization: Bearer <key>`, if any. A
+/// key that is not recognised fails the request rather than passing for no
+/// key at all.
+pub struct MaybeAuthenticated(pub Option<StoredApiKey>);
+
+/// The API key a request carries, failing the request without one.
+pub struct Authenticated(pub StoredApiKey);
+
+impl FromRequest for MaybeAuthenticated {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let keys = req.app_data::<web::Data<dyn KeyStore>>().cloned();
+        let key = bearer_key(req);
+
+        Box::pin(async move {
+            let Some(key) = key? else {
+                return Ok(Self(None));
+            };
+            let keys = keys.ok_or_else(|| {
+                InternalError::from_response(
+                    "no key store",
+                    HttpResponse::InternalServerError().body("API keys are not available"),
+                )
+            })?;
+
+            match keys.find_key(key.hash()).await {
+                Ok(stored) => Ok(Self(Some(stored))),
+                Err(RepositoryError::NotFound(_)) => Err(unauthorized("invalid API key")),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+impl FromRequest for Authenticated {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let key = MaybeAuthenticated::from_request(req, payload);
+
+        Box::pin(async move {
+            match key.await? {
+                MaybeAuthenticated(Some(key)) => Ok(Self(key)),
+                MaybeAuthenticated(None) => Err(unauthorized("missing API key")),
+            }
+        })
+    }
+}
+
+/// The key in the request's `Authorization` header, when it uses the
+/// `Bearer` scheme. Other schemes are left to whatever else reads the header.
+fn bearer_key(req: &HttpRequest) -> Result<Option<ApiKey>, Error> {
+    let Some(key) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return Ok(None);
+    };
+
+    ApiKey::parse(key.trim().to_owned())
+        .map(Some)
+        .map_err(|e| unauthorized(&e))
+}
+
+fn unauthorized(msg: &str) -> Error {
+    InternalError::from_response(
+        msg.to_owned(),
+        HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+            .body(msg.to_owned()),
+    )
+    .into()
+}
@@ -0,0 +1,35 @@
+use actix_web::{
+    http::header::CONTENT_LENGTH,
+    web::{self, Bytes},
+    HttpRequest, HttpResponse,
+};
+
+/// Read a request body of at most `max_bytes` bytes. Oversized bodies are
+/// turned away with 413 as soon as that is known, without buffering the rest.
+/// The response is boxed, like those of the other request checks.
+pub(super) async fn read_body(
+    req: &HttpRequest,
+    payload: web::Payload,
+    max_bytes: usize,
+) -> Result<Bytes, Box<HttpResponse>> {
+    let declared = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+
+    if declared.is_some_and(|len| len > max_bytes) {
+        return Err(Box::new(too_large(max_bytes)));
+    }
+
+    match payload.to_bytes_limited(max_bytes).await {
+        Ok(Ok(bytes)) => Ok(bytes),
+        Ok(Err(e)) => Err(Box::new(HttpResponse::from_error(e))),
+        Err(_) => Err(Box::new(too_large(max_bytes))),
+    }
+}
+
+pub(super) fn too_large(max_bytes: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge()
+        .body(format!("Paste too large: the limit is {} bytes", max_bytes))
+}
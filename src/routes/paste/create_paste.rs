@@ -1,26 +1,38 @@
-use std::sync::Mutex;
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, Responder};
 
-use actix_web::{
-    web::{self, Bytes},
-    HttpResponse, Responder,
-};
+use chrono::Utc;
+use secrecy::ExposeSecret;
 
+use super::{
+    auth::MaybeAuthenticated,
+    body::read_body,
+    ids::NewId,
+    options::{check_visibility, parse_upload},
+    password::hash_password,
+    urls::paste_url,
+    CreatePasteOptions, CreatePasteQuery, TOKEN_HEADER,
+};
 use crate::{
+    config::LimitSettings,
     dao::Repository,
-    domain::{NewPaste, PasteContent, PasteId},
+    domain::{NewPaste, OwnerToken, PasteId},
 };
 
 #[tracing::instrument(
     name = "Adding a new paste",
-    skip(repo),
+    skip(repo, req, payload, limits, key),
     fields(
         raw_paste_id = %raw_paste_id,
     )
 )]
 pub async fn create_paste<R: Repository>(
     raw_paste_id: web::Path<String>,
-    bytes: Bytes,
-    repo: web::Data<Mutex<R>>,
+    query: web::Query<CreatePasteQuery>,
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: web::Data<R>,
+    limits: web::Data<LimitSettings>,
+    key: MaybeAuthenticated,
 ) -> impl Responder {
     let raw_paste_id = raw_paste_id.into_inner();
     let paste_id = match PasteId::parse(raw_paste_id) {
@@ -28,23 +40,85 @@ pub async fn create_paste<R: Repository>(
         Err(e) => return HttpResponse::BadRequest().body(e),
     };
 
-    let paste_content = match PasteContent::parse_bytes(bytes) {
-        Ok(p) => p,
+    create(
+        NewId::Chosen(paste_id),
+        query.into_inner(),
+        req,
+        payload,
+        repo.as_ref(),
+        &limits,
+        key,
+    )
+    .await
+}
+
+/// Create a paste from a raw or `multipart/form-data` upload under `new_id`,
+/// responding with its URL and owner token.
+pub(super) async fn create<R: Repository>(
+    new_id: NewId<'_>,
+    query: CreatePasteQuery,
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: &R,
+    limits: &LimitSettings,
+    key: MaybeAuthenticated,
+) -> HttpResponse {
+    let bytes = match read_body(&req, payload, limits.max_paste_bytes).await {
+        Ok(b) => b,
+        Err(response) => return *response,
+    };
+
+    let options = match CreatePasteOptions::parse(query, &req) {
+        Ok(o) => o,
         Err(e) => return HttpResponse::BadRequest().body(e),
     };
 
+    let upload = match parse_upload(
+        &req,
+        bytes,
+        limits.max_paste_bytes,
+        options.encryption.is_some(),
+    ) {
+        Ok(u) => u,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let owner = key.0.map(|k| k.name);
+    if let Err(e) = check_visibility(options.visibility, owner.as_deref()) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let password_hash = match hash_password(options.password).await {
+        Ok(h) => h,
+        Err(response) => return *response,
+    };
+
+    let owner_token = OwnerToken::generate();
+
     let new_paste = NewPaste {
-        id: paste_id.clone(),
-        content: paste_content,
+        id: new_id.first(),
+        content: upload.content,
+        content_type: upload.content_type,
+        language: upload.language,
+        visibility: options.visibility,
+        encryption: options.encryption,
+        created_at: Utc::now(),
+        expires_at: options.expires_at,
+        burn_after_reading: options.burn_after_reading,
+        owner_token_hash: owner_token.hash(),
+        owner,
+        password_hash,
     };
 
-    return match repo
-        .into_inner()
-        .lock()
-        .expect("failed to acquire mutex lock")
-        .insert(new_paste)
-    {
-        Ok(_) => HttpResponse::Ok().body(paste_id.as_ref().to_owned()),
-        Err(e) => HttpResponse::from_error(e),
+    let paste_id = match new_id.insert(repo, new_paste).await {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::from_error(e),
     };
+
+    let url = paste_url(&req, &paste_id);
+
+    HttpResponse::Created()
+        .insert_header((LOCATION, url.as_str()))
+        .insert_header((TOKEN_HEADER, owner_token.expose_secret().as_str()))
+        .body(format!("{}\n", url))
 }
@@ -0,0 +1,214 @@
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, Responder};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    auth::MaybeAuthenticated,
+    body::{read_body, too_large},
+    ids::NewId,
+    options::{check_visibility, expiry},
+    password::hash_password,
+    urls::paste_url,
+};
+use crate::{
+    config::LimitSettings,
+    dao::Repository,
+    domain::{
+        Encryption, IdGenerator, NewPaste, OwnerToken, OwnerTokenHash, PasteContent, PasteId,
+        PastePassword, Visibility,
+    },
+    highlight::Language,
+};
+
+/// Every byte of content may take up to six once escaped in JSON (`\u0000`),
+/// so the request body is allowed that much room.
+const JSON_ESCAPE_OVERHEAD: usize = 6;
+
+/// Room in the request body for everything besides the content.
+const JSON_FIELDS_ALLOWANCE: usize = 4096;
+
+/// Body of `POST /api/v1/pastes`.
+#[derive(Debug, Deserialize)]
+pub struct CreatePasteRequest {
+    /// Base64 encoded when `encryption` is set
+    pub content: String,
+    /// Generated when absent
+    pub id: Option<String>,
+    /// How long to keep the paste, e.g. `1h`
+    pub ttl: Option<String>,
+    /// Language to highlight the paste as, by name or file extension
+    pub language: Option<String>,
+    /// `public`, `unlisted` or `private`; private pastes need an API key
+    pub visibility: Option<String>,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+    /// Scheme the content was encrypted with by the client
+    pub encryption: Option<String>,
+    /// Password reading the paste takes
+    pub password: Option<String>,
+}
+
+impl CreatePasteRequest {
+    /// Size of the content once decoded, in bytes.
+    fn content_len(&self) -> usize {
+        match self.encryption {
+            Some(_) => {
+                let padding = self
+                    .content
+                    .bytes()
+                    .rev()
+                    .take_while(|b| *b == b'=')
+                    .count();
+                (self.content.len() / 4 * 3).saturating_sub(padding)
+            }
+            None => self.content.len(),
+        }
+    }
+}
+
+/// Response to `POST /api/v1/pastes`.
+#[derive(Debug, Serialize)]
+pub struct CreatePasteResponse {
+    pub id: String,
+    pub url: String,
+    pub raw_url: String,
+    /// Needed to update or delete the paste; it cannot be recovered later
+    pub token: String,
+}
+
+#[tracing::instrument(
+    name = "Adding a new paste from JSON",
+    skip(repo, req, payload, limits, ids, key)
+)]
+pub async fn create_paste_json<R: Repository>(
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: web::Data<R>,
+    limits: web::Data<LimitSettings>,
+    ids: web::Data<dyn IdGenerator>,
+    key: MaybeAuthenticated,
+) -> impl Responder {
+    let max_body = limits
+        .max_paste_bytes
+        .saturating_mul(JSON_ESCAPE_OVERHEAD)
+        .saturating_add(JSON_FIELDS_ALLOWANCE);
+
+    let bytes = match read_body(&req, payload, max_body).await {
+        Ok(b) => b,
+        Err(response) => return *response,
+    };
+
+    let mut request: CreatePasteRequest = match serde_json::from_slice(&bytes) {
+        Ok(r) => r,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+
+    if request.content_len() > limits.max_paste_bytes {
+        return too_large(limits.max_paste_bytes);
+    }
+
+    let password = match request
+        .password
+        .take()
+        .map(PastePassword::parse)
+        .transpose()
+    {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let new_id = match request.id.take().map(PasteId::parse).transpose() {
+        Ok(Some(id)) => NewId::Chosen(id),
+        Ok(None) => NewId::Generated(ids.as_ref()),
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let owner_token = OwnerToken::generate();
+
+    let mut new_paste = match parse_request(
+        request,
+        new_id.first(),
+        limits.max_paste_bytes,
+        owner_token.hash(),
+        key.0.map(|k| k.name),
+    ) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    // Only hashed once everything else is known to be valid, as it is slow
+    new_paste.password_hash = match hash_password(password).await {
+        Ok(h) => h,
+        Err(response) => return *response,
+    };
+
+    let paste_id = match new_id.insert(repo.as_ref(), new_paste).await {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    let url = paste_url(&req, &paste_id);
+
+    HttpResponse::Created()
+        .insert_header((LOCATION, url.as_str()))
+        .json(CreatePasteResponse {
+            id: paste_id.as_ref().to_owned(),
+            raw_url: format!("{}?view=raw", url),
+            url,
+            token: owner_token.expose_secret().to_owned(),
+        })
+}
+
+/// Validate a request with the same rules as the raw create routes.
+fn parse_request(
+    request: CreatePasteRequest,
+    id: PasteId,
+    max_bytes: usize,
+    owner_token_hash: OwnerTokenHash,
+    owner: Option<String>,
+) -> Result<NewPaste, String> {
+    let language = match request.language {
+        Some(lang) => Some(
+            Language::find(&lang)
+                .ok_or_else(|| format!("{} is not a supported language", lang))?
+                .name
+                .to_owned(),
+        ),
+        None => None,
+    };
+
+    let encryption = request.encryption.map(Encryption::parse).transpose()?;
+    let visibility = request
+        .visibility
+        .map(Visibility::parse)
+        .transpose()?
+        .unwrap_or_default();
+    check_visibility(visibility, owner.as_deref())?;
+
+    Ok(NewPaste {
+        id,
+        content: match encryption {
+            Some(_) => PasteContent::parse_bytes(
+                BASE64
+                    .decode(&request.content)
+                    .map_err(|e| format!("encrypted content is not valid base64: {}", e))?
+                    .into(),
+                max_bytes,
+            )?,
+            None => PasteContent::parse(request.content, max_bytes)?,
+        },
+        content_type: None,
+        language,
+        visibility,
+        encryption,
+        created_at: Utc::now(),
+        expires_at: request.ttl.map(expiry).transpose()?,
+        burn_after_reading: request.burn_after_reading,
+        owner_token_hash,
+        owner,
+        // Left to the caller, as hashing is slow
+        password_hash: None,
+    })
+}
@@ -1,39 +1,29 @@
-use std::sync::Mutex;
+use actix_web::{web, HttpRequest, Responder};
 
-use actix_web::{
-    web::{self, Bytes},
-    HttpResponse, Responder,
-};
+use super::{auth::MaybeAuthenticated, create_paste::create, ids::NewId, CreatePasteQuery};
+use crate::{config::LimitSettings, dao::Repository, domain::IdGenerator};
 
-use crate::{
-    dao::Repository,
-    domain::{NewPaste, PasteContent, PasteId},
-};
-
-#[tracing::instrument(name = "Adding a new paste with a generated id", skip(repo))]
+#[tracing::instrument(
+    name = "Adding a new paste with a generated id",
+    skip(repo, req, payload, limits, ids, key)
+)]
 pub async fn create_paste_sans_id<R: Repository>(
-    bytes: Bytes,
-    repo: web::Data<Mutex<R>>,
+    query: web::Query<CreatePasteQuery>,
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: web::Data<R>,
+    limits: web::Data<LimitSettings>,
+    ids: web::Data<dyn IdGenerator>,
+    key: MaybeAuthenticated,
 ) -> impl Responder {
-    let paste_id = PasteId::random();
-
-    let paste_content = match PasteContent::parse_bytes(bytes) {
-        Ok(p) => p,
-        Err(e) => return HttpResponse::BadRequest().body(e),
-    };
-
-    let new_paste = NewPaste {
-        id: paste_id.clone(),
-        content: paste_content,
-    };
-
-    return match repo
-        .into_inner()
-        .lock()
-        .expect("failed to acquire mutex lock")
-        .insert(new_paste)
-    {
-        Ok(_) => HttpResponse::Ok().body(paste_id.as_ref().to_owned()),
-        Err(e) => HttpResponse::from_error(e),
-    };
+    create(
+        NewId::Generated(ids.as_ref()),
+        query.into_inner(),
+        req,
+        payload,
+        repo.as_ref(),
+        &limits,
+        key,
+    )
+    .await
 }
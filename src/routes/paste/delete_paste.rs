@@ -0,0 +1,38 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+
+use super::auth::{check_owner, MaybeAuthenticated};
+use crate::{dao::Repository, domain::PasteId};
+
+#[tracing::instrument(
+    name = "Deleting a paste",
+    skip(repo, req, key),
+    fields(
+        raw_paste_id = %raw_paste_id,
+    )
+)]
+pub async fn delete_paste<R: Repository>(
+    raw_paste_id: web::Path<String>,
+    req: HttpRequest,
+    repo: web::Data<R>,
+    key: MaybeAuthenticated,
+) -> impl Responder {
+    let raw_paste_id = raw_paste_id.into_inner();
+    let paste_id = match PasteId::parse(raw_paste_id) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let paste = match repo.find_one(paste_id.clone()).await {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    if let Err(response) = check_owner(&req, &paste, key.0.as_ref()) {
+        return *response;
+    }
+
+    return match repo.delete(paste_id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    };
+}
@@ -1,33 +1,216 @@
-use std::sync::Mutex;
+use actix_web::{
+    http::header::{HeaderValue, CACHE_CONTROL, VARY},
+    web, HttpRequest, HttpResponse, Responder,
+};
+use chrono::Utc;
+use serde::Deserialize;
 
-use actix_web::{web, HttpResponse, Responder};
+use super::{
+    auth::{readable, MaybeAuthenticated},
+    password::{check_password, supplied_password, Prompt},
+    views::{self, View},
+};
+use crate::{
+    dao::{Repository, RepositoryError},
+    domain::{PasteId, StoredApiKey, Visibility},
+    highlight::Language,
+    rate_limit::RateLimiter,
+};
 
-use crate::{dao::Repository, domain::PasteId};
+/// Suffix that selects the HTML view, as in `/paste/foo.rs.html`.
+const HTML_SUFFIX: &str = ".html";
+
+#[derive(Deserialize, Debug)]
+pub struct GetPasteQuery {
+    view: Option<View>,
+    /// Language to highlight as, overriding the paste's own or one inferred
+    /// from its id
+    lang: Option<String>,
+}
+
+/// Body of `POST /paste/{id}/unlock`, as sent by the password prompt.
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    password: String,
+}
 
 #[tracing::instrument(
     name = "Retrieving a paste",
-    skip(repo),
+    skip(repo, req, limiter, key),
     fields(
         raw_paste_id = %raw_paste_id,
     )
 )]
 pub async fn get_paste<R: Repository>(
     raw_paste_id: web::Path<String>,
-    repo: web::Data<Mutex<R>>,
+    query: web::Query<GetPasteQuery>,
+    req: HttpRequest,
+    repo: web::Data<R>,
+    limiter: web::Data<RateLimiter>,
+    key: MaybeAuthenticated,
+) -> impl Responder {
+    let password = match supplied_password(&req) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let read = Read {
+        query: query.into_inner(),
+        key: key.0,
+        password,
+        view: None,
+    };
+    read_paste(
+        raw_paste_id.into_inner(),
+        read,
+        &req,
+        repo.as_ref(),
+        &limiter,
+    )
+    .await
+}
+
+/// Read a password-protected paste with the password from the prompt form,
+/// as HTML unless the query asks otherwise.
+#[tracing::instrument(
+    name = "Unlocking a paste",
+    skip(repo, req, form, limiter, key),
+    fields(
+        raw_paste_id = %raw_paste_id,
+    )
+)]
+pub async fn unlock_paste<R: Repository>(
+    raw_paste_id: web::Path<String>,
+    query: web::Query<GetPasteQuery>,
+    req: HttpRequest,
+    form: web::Form<UnlockForm>,
+    repo: web::Data<R>,
+    limiter: web::Data<RateLimiter>,
+    key: MaybeAuthenticated,
 ) -> impl Responder {
-    let raw_paste_id = raw_paste_id.into_inner();
+    let read = Read {
+        query: query.into_inner(),
+        key: key.0,
+        password: Some(form.into_inner().password),
+        view: Some(View::Html),
+    };
+    read_paste(
+        raw_paste_id.into_inner(),
+        read,
+        &req,
+        repo.as_ref(),
+        &limiter,
+    )
+    .await
+}
+
+/// What a reader asked for and brought along.
+struct Read {
+    query: GetPasteQuery,
+    key: Option<StoredApiKey>,
+    password: Option<String>,
+    /// The view to fall back on before negotiating one
+    view: Option<View>,
+}
+
+async fn read_paste<R: Repository>(
+    raw_paste_id: String,
+    read: Read,
+    req: &HttpRequest,
+    repo: &R,
+    limiter: &RateLimiter,
+) -> HttpResponse {
+    let Read {
+        query,
+        key,
+        password,
+        view,
+    } = read;
+
     let paste_id = match PasteId::parse(raw_paste_id) {
         Ok(p) => p,
         Err(e) => return HttpResponse::BadRequest().body(e),
     };
 
-    return match repo
-        .into_inner()
-        .lock()
-        .expect("failed to acquire mutex lock")
-        .find_one(paste_id)
-    {
-        Ok(p) => HttpResponse::Ok().body(p.as_ref().to_owned()),
-        Err(e) => HttpResponse::from_error(e),
+    // A paste whose id really ends in `.html` is served as is; otherwise the
+    // suffix asks for the HTML view of the paste without it. Failing both,
+    // the view is negotiated from the `Accept` header.
+    let found = readable(repo.find_one(paste_id.clone()).await, key.as_ref());
+    let (paste_id, paste, view) = match (found, html_stem(&paste_id)) {
+        (Ok(p), _) => (paste_id, p, query.view.or(view)),
+        (Err(RepositoryError::NotFound(_)), Some(stem)) => {
+            match readable(repo.find_one(stem.clone()).await, key.as_ref()) {
+                Ok(p) => (stem, p, query.view.or(Some(View::Html))),
+                Err(e) => return HttpResponse::from_error(e),
+            }
+        }
+        (Err(e), _) => return HttpResponse::from_error(e),
+    };
+
+    if paste.is_expired(Utc::now()) {
+        return HttpResponse::Gone().body(format!("Expired: {}", paste_id.as_ref()));
+    }
+
+    let view = match view.or_else(|| views::negotiate(req, &paste)) {
+        Some(v) => v,
+        None => {
+            return HttpResponse::NotAcceptable()
+                .body("Pastes are available as their own type, text/html or application/json")
+        }
+    };
+
+    let prompt = match view {
+        View::Html => Prompt::Form,
+        View::Raw | View::Json => Prompt::Basic,
+    };
+    if let Err(response) = check_password(req, &paste, password, limiter, prompt).await {
+        return *response;
+    }
+
+    // Only the reader that manages to take a burn-after-reading paste gets to
+    // see it; anyone racing them gets a 404.
+    let paste = if paste.burn_after_reading {
+        match repo.take(paste_id).await {
+            Ok(p) => p,
+            Err(e) => return HttpResponse::from_error(e),
+        }
+    } else {
+        // A lost view is not worth failing the read over
+        if let Err(e) = repo.record_view(paste_id).await {
+            tracing::warn!("Failed to record view: {}", e);
+        }
+        paste
+    };
+
+    // The raw view of compressed content also depends on `Accept-Encoding`
+    let vary = match (view, &paste.encoded) {
+        (View::Raw, Some(_)) => "accept, accept-encoding",
+        _ => "accept",
     };
+
+    let private = paste.visibility == Visibility::Private || paste.password_hash.is_some();
+    let mut response = match view {
+        View::Raw => views::raw(req, paste),
+        View::Html => views::html(paste, query.lang.as_deref().and_then(Language::find)),
+        View::Json => views::json(paste),
+    };
+
+    response
+        .headers_mut()
+        .insert(VARY, HeaderValue::from_static(vary));
+    // Keep private and password-protected pastes out of shared caches
+    if private {
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+    }
+    response
+}
+
+/// The id `paste_id` names the HTML view of, if it has the `.html` suffix.
+fn html_stem(paste_id: &PasteId) -> Option<PasteId> {
+    paste_id
+        .as_ref()
+        .strip_suffix(HTML_SUFFIX)
+        .and_then(|stem| PasteId::parse(stem.to_owned()).ok())
 }
@@ -0,0 +1,35 @@
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse, Responder};
+
+use super::urls::paste_url;
+use crate::{dao::Repository, domain::ContentHash};
+
+/// Point at a public paste holding content with the given SHA-256, so
+/// clients can check whether something was pasted before pasting it again.
+#[tracing::instrument(
+    name = "Looking up a paste by content hash",
+    skip(repo, req),
+    fields(
+        raw_hash = %raw_hash,
+    )
+)]
+pub async fn get_paste_by_hash<R: Repository>(
+    raw_hash: web::Path<String>,
+    req: HttpRequest,
+    repo: web::Data<R>,
+) -> impl Responder {
+    let hash = match ContentHash::parse(raw_hash.into_inner()) {
+        Ok(h) => h,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    match repo.find_by_hash(hash).await {
+        Ok(paste_id) => {
+            let url = paste_url(&req, &paste_id);
+
+            HttpResponse::SeeOther()
+                .insert_header((LOCATION, url.as_str()))
+                .body(format!("{}\n", url))
+        }
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
@@ -0,0 +1,98 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{
+    auth::{readable, MaybeAuthenticated},
+    password::{check_password, supplied_password, Prompt},
+};
+use crate::{
+    dao::Repository,
+    domain::{Paste, PasteId},
+    rate_limit::RateLimiter,
+};
+
+/// The public metadata of a paste.
+#[derive(Debug, Serialize)]
+pub struct PasteMeta {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub language: Option<String>,
+    pub visibility: String,
+    pub views: u64,
+    pub last_read_at: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    /// Whether reading the paste takes a password
+    pub password_protected: bool,
+    /// The scheme the content was encrypted with by its author, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<String>,
+    /// Hex-encoded SHA-256 of the content, as looked up by `/paste/by-hash`
+    pub sha256: String,
+}
+
+impl From<&Paste> for PasteMeta {
+    fn from(paste: &Paste) -> Self {
+        Self {
+            id: paste.id.as_ref().to_owned(),
+            created_at: paste.created_at,
+            updated_at: paste.updated_at,
+            expires_at: paste.expires_at,
+            size: paste.size,
+            content_type: paste.content_type.clone(),
+            language: paste.language.clone(),
+            visibility: paste.visibility.as_ref().to_owned(),
+            views: paste.views,
+            last_read_at: paste.last_read_at,
+            burn_after_reading: paste.burn_after_reading,
+            password_protected: paste.password_hash.is_some(),
+            encryption: paste.encryption.as_ref().map(|e| e.as_ref().to_owned()),
+            sha256: paste.content.hash().as_ref().to_owned(),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Retrieving paste metadata",
+    skip(repo, req, limiter, key),
+    fields(
+        raw_paste_id = %raw_paste_id,
+    )
+)]
+pub async fn get_paste_meta<R: Repository>(
+    raw_paste_id: web::Path<String>,
+    req: HttpRequest,
+    repo: web::Data<R>,
+    limiter: web::Data<RateLimiter>,
+    key: MaybeAuthenticated,
+) -> impl Responder {
+    let raw_paste_id = raw_paste_id.into_inner();
+    let paste_id = match PasteId::parse(raw_paste_id) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let paste = match readable(repo.find_one(paste_id.clone()).await, key.0.as_ref()) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    if paste.is_expired(Utc::now()) {
+        return HttpResponse::Gone().body(format!("Expired: {}", paste_id.as_ref()));
+    }
+
+    // The content hash alone would let guesses at the content be checked
+    let password = match supplied_password(&req) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    if let Err(response) = check_password(&req, &paste, password, &limiter, Prompt::Basic).await {
+        return *response;
+    }
+
+    HttpResponse::Ok().json(PasteMeta::from(&paste))
+}
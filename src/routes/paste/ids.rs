@@ -0,0 +1,159 @@
+use crate::{
+    dao::{Repository, RepositoryError},
+    domain::{IdGenerator, NewPaste, PasteId},
+};
+
+/// How many generated ids to try before giving up on a create.
+const MAX_ID_ATTEMPTS: usize = 5;
+
+/// Where the id of a paste being created comes from.
+pub(super) enum NewId<'a> {
+    /// Chosen by the client, who is told when it is taken
+    Chosen(PasteId),
+    /// Drawn from a generator, and drawn again when it is taken
+    Generated(&'a dyn IdGenerator),
+}
+
+impl NewId<'_> {
+    /// The id to try first.
+    pub(super) fn first(&self) -> PasteId {
+        match self {
+            Self::Chosen(id) => id.clone(),
+            Self::Generated(ids) => ids.generate(),
+        }
+    }
+
+    /// Insert `new_paste`, created under the `first` id, returning the id it
+    /// was stored under.
+    pub(super) async fn insert<R: Repository>(
+        &self,
+        repo: &R,
+        new_paste: NewPaste,
+    ) -> Result<PasteId, RepositoryError> {
+        match self {
+            Self::Chosen(id) => repo.insert(new_paste).await.map(|_| id.clone()),
+            Self::Generated(ids) => insert_generated(repo, *ids, new_paste).await,
+        }
+    }
+}
+
+/// Insert a paste whose id was generated, drawing a fresh id whenever the
+/// current one is already taken.
+async fn insert_generated<R: Repository>(
+    repo: &R,
+    ids: &dyn IdGenerator,
+    mut new_paste: NewPaste,
+) -> Result<PasteId, RepositoryError> {
+    let mut attempt = 1;
+
+    loop {
+        match repo.insert(new_paste.clone()).await {
+            Ok(_) => return Ok(new_paste.id),
+            Err(RepositoryError::Conflict(id)) if attempt < MAX_ID_ATTEMPTS => {
+                tracing::warn!("Generated paste id {} is taken, retrying", id);
+                new_paste.id = ids.generate();
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+
+    use super::{NewId, MAX_ID_ATTEMPTS};
+    use crate::{
+        dao::{InMemoryRepository, Repository, RepositoryError},
+        domain::{IdGenerator, NewPaste, OwnerToken, PasteContent, PasteId, Visibility},
+    };
+
+    /// Hands out `ids` in turn, then the last one forever, counting the ids
+    /// it generated.
+    struct Replay {
+        ids: Vec<&'static str>,
+        generated: Mutex<usize>,
+    }
+
+    impl Replay {
+        fn new(ids: &[&'static str]) -> Self {
+            Self {
+                ids: ids.to_vec(),
+                generated: Mutex::new(0),
+            }
+        }
+
+        fn generated(&self) -> usize {
+            *self.generated.lock().unwrap()
+        }
+    }
+
+    impl IdGenerator for Replay {
+        fn generate(&self) -> PasteId {
+            let mut generated = self.generated.lock().unwrap();
+            let id = self.ids[(*generated).min(self.ids.len() - 1)];
+            *generated += 1;
+
+            PasteId::parse(id.to_string()).unwrap()
+        }
+    }
+
+    fn paste(id: PasteId) -> NewPaste {
+        NewPaste {
+            id,
+            content: PasteContent::parse("somecontent".to_string(), usize::MAX).unwrap(),
+            content_type: None,
+            language: None,
+            visibility: Visibility::Public,
+            encryption: None,
+            created_at: Utc::now(),
+            expires_at: None,
+            burn_after_reading: false,
+            owner_token_hash: OwnerToken::generate().hash(),
+            owner: None,
+            password_hash: None,
+        }
+    }
+
+    async fn repo_holding(id: &str) -> InMemoryRepository {
+        let repo = InMemoryRepository::new();
+        repo.insert(paste(PasteId::parse(id.to_string()).unwrap()))
+            .await
+            .unwrap();
+        repo
+    }
+
+    #[tokio::test]
+    async fn taken_generated_ids_are_drawn_again() {
+        let repo = repo_holding("taken").await;
+        let ids = Replay::new(&["taken", "taken", "free"]);
+        let new_id = NewId::Generated(&ids);
+
+        let id = new_id.insert(&repo, paste(new_id.first())).await.unwrap();
+        assert_eq!("free", id.as_ref());
+        assert_eq!(3, ids.generated());
+    }
+
+    #[tokio::test]
+    async fn generated_ids_are_given_up_on_after_max_attempts() {
+        let repo = repo_holding("taken").await;
+        let ids = Replay::new(&["taken"]);
+        let new_id = NewId::Generated(&ids);
+
+        let result = new_id.insert(&repo, paste(new_id.first())).await;
+        assert!(matches!(result, Err(RepositoryError::Conflict(_))));
+        assert_eq!(MAX_ID_ATTEMPTS, ids.generated());
+    }
+
+    #[tokio::test]
+    async fn taken_chosen_ids_are_a_conflict() {
+        let repo = repo_holding("taken").await;
+        let new_id = NewId::Chosen(PasteId::parse("taken".to_string()).unwrap());
+
+        let result = new_id.insert(&repo, paste(new_id.first())).await;
+        assert!(matches!(result, Err(RepositoryError::Conflict(_))));
+    }
+}
@@ -1,7 +1,37 @@
+mod auth;
+mod body;
 mod create_paste;
+mod create_paste_json;
 mod create_paste_sans_id;
+mod delete_paste;
 mod get_paste;
+mod get_paste_by_hash;
+mod get_paste_meta;
+mod ids;
+mod multipart;
+mod options;
+mod owned_pastes;
+mod password;
+mod update_paste;
+mod urls;
+mod views;
 
+pub use auth::{Authenticated, MaybeAuthenticated};
 pub use create_paste::create_paste;
+pub use create_paste_json::{create_paste_json, CreatePasteRequest, CreatePasteResponse};
 pub use create_paste_sans_id::create_paste_sans_id;
-pub use get_paste::get_paste;
+pub use delete_paste::delete_paste;
+pub use get_paste::{get_paste, unlock_paste, GetPasteQuery, UnlockForm};
+pub use get_paste_by_hash::get_paste_by_hash;
+pub use get_paste_meta::{get_paste_meta, PasteMeta};
+pub use options::{
+    is_text, CreatePasteOptions, CreatePasteQuery, BURN_HEADER, ENCRYPTION_HEADER, TOKEN_HEADER,
+    TTL_HEADER, VISIBILITY_HEADER,
+};
+pub use owned_pastes::{
+    delete_owned_pastes, list_owned_pastes, BulkDeleteRequest, BulkDeleteResponse,
+    OwnedPastesResponse,
+};
+pub use password::PASSWORD_HEADER;
+pub use update_paste::update_paste;
+pub use views::{FileEnvelope, PasteEnvelope, View};
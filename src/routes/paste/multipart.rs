@@ -0,0 +1,304 @@
+//! Just enough of multipart bodies (RFC 2046, RFC 7578) to accept `curl -F`
+//! uploads, and to keep the files of a multi-file paste together as one
+//! `multipart/mixed` body.
+
+use actix_web::{
+    http::header::{ContentDisposition, DispositionType, HeaderValue},
+    web::Bytes,
+};
+use mime::{Mime, BOUNDARY, FORM_DATA, MULTIPART};
+use rand::RngCore;
+
+use super::views::filename_param;
+use crate::domain::Paste;
+
+/// Subtype under which the files of a multi-file paste are stored.
+const MIXED: &str = "mixed";
+
+/// One file of an upload or of a multi-file paste.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FilePart {
+    pub(super) filename: Option<String>,
+    pub(super) content_type: Option<String>,
+    pub(super) content: Bytes,
+}
+
+/// The boundary of a `multipart/form-data` body, if `content_type` is one.
+pub(super) fn form_data_boundary(content_type: &str) -> Option<String> {
+    boundary(content_type, FORM_DATA.as_str())
+}
+
+/// Whether `content_type` is `multipart/form-data`, with or without a
+/// boundary.
+pub(super) fn is_form_data(content_type: &str) -> bool {
+    content_type
+        .parse::<Mime>()
+        .is_ok_and(|mime| mime.type_() == MULTIPART && mime.subtype() == FORM_DATA)
+}
+
+/// The files of a multi-file paste, or `None` for an ordinary paste.
+pub(super) fn stored_files(paste: &Paste) -> Option<Vec<FilePart>> {
+    let boundary = boundary(paste.content_type.as_deref()?, MIXED)?;
+    parse(&Bytes::copy_from_slice(paste.content.as_ref()), &boundary).ok()
+}
+
+fn boundary(content_type: &str, subtype: &str) -> Option<String> {
+    let mime: Mime = content_type.parse().ok()?;
+
+    if mime.type_() != MULTIPART || mime.subtype() != subtype {
+        return None;
+    }
+
+    mime.get_param(BOUNDARY).map(|b| b.as_str().to_owned())
+}
+
+/// The files of a `multipart/form-data` upload: the parts with a filename.
+/// A form of a single field and no files, as sent by `curl -F 'f=<file'`,
+/// holds one file without a name. Any other field is turned away, as options
+/// go in the query string or headers rather than the form.
+pub(super) fn form_files(body: &Bytes, boundary: &str) -> Result<Vec<FilePart>, String> {
+    let (files, mut fields): (Vec<_>, Vec<_>) = parse_parts(body, boundary)?
+        .into_iter()
+        .partition(|(_, part)| part.filename.is_some());
+
+    if files.is_empty() && fields.len() == 1 {
+        return Ok(fields.pop().map(|(_, part)| part).into_iter().collect());
+    }
+
+    match fields.first() {
+        Some((name, _)) => Err(format!(
+            "form field {} is not a file - pass options in the query string or headers",
+            name.as_deref().unwrap_or("\"\"")
+        )),
+        None => Ok(files.into_iter().map(|(_, part)| part).collect()),
+    }
+}
+
+/// Split a multipart body into its parts. Preamble and epilogue are ignored.
+pub(super) fn parse(body: &Bytes, boundary: &str) -> Result<Vec<FilePart>, String> {
+    Ok(parse_parts(body, boundary)?
+        .into_iter()
+        .map(|(_, part)| part)
+        .collect())
+}
+
+/// Split a multipart body into its parts, along with the form field names
+/// they are given, if any.
+fn parse_parts(body: &Bytes, boundary: &str) -> Result<Vec<(Option<String>, FilePart)>, String> {
+    let malformed = || "not a valid multipart body".to_string();
+    let delimiter = [b"\r\n--", boundary.as_bytes()].concat();
+
+    // Where the current delimiter ends. The first need not follow a line break.
+    let mut pos = if body.starts_with(&delimiter[2..]) {
+        delimiter.len() - 2
+    } else {
+        find(body, &delimiter, 0).ok_or_else(malformed)? + delimiter.len()
+    };
+
+    let mut parts = vec![];
+
+    loop {
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        let start = find(body, b"\r\n", pos).ok_or_else(malformed)? + 2;
+        let end = find(body, &delimiter, start).ok_or_else(malformed)?;
+        parts.push(parse_part(body.slice(start..end))?);
+        pos = end + delimiter.len();
+    }
+}
+
+fn parse_part(part: Bytes) -> Result<(Option<String>, FilePart), String> {
+    let (headers, content) = if part.starts_with(b"\r\n") {
+        (Bytes::new(), part.slice(2..))
+    } else {
+        let split = find(&part, b"\r\n\r\n", 0)
+            .ok_or_else(|| "not a valid multipart body - unterminated headers".to_string())?;
+        (part.slice(..split), part.slice(split + 4..))
+    };
+
+    let mut field = None;
+    let mut file = FilePart {
+        filename: None,
+        content_type: None,
+        content,
+    };
+
+    for line in headers.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let Some(colon) = line.iter().position(|b| *b == b':') else {
+            continue;
+        };
+        let value =
+            HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).map_err(|e| e.to_string())?;
+
+        match std::str::from_utf8(&line[..colon]).map(str::to_lowercase) {
+            Ok(name) if name == "content-disposition" => {
+                let disposition =
+                    ContentDisposition::from_raw(&value).map_err(|e| e.to_string())?;
+                field = disposition.get_name().map(str::to_owned);
+                file.filename = match disposition.get_filename_ext() {
+                    Some(ext) => String::from_utf8(ext.value.clone()).ok(),
+                    None => disposition.get_filename().map(str::to_owned),
+                };
+            }
+            Ok(name) if name == "content-type" => {
+                file.content_type = value.to_str().ok().map(str::to_owned);
+            }
+            _ => {}
+        }
+    }
+
+    Ok((field, file))
+}
+
+/// Encode files as a `multipart/mixed` body, returning its content type too.
+pub(super) fn encode(files: &[FilePart]) -> (String, Bytes) {
+    let boundary = loop {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let boundary = format!("whtpst-{}", hex::encode(nonce));
+
+        // A random boundary is all but certain to be absent, but check anyway
+        if !files
+            .iter()
+            .any(|f| find(&f.content, boundary.as_bytes(), 0).is_some())
+        {
+            break boundary;
+        }
+    };
+
+    let mut body = vec![];
+
+    for file in files {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+
+        let disposition = ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: file.filename.iter().map(|f| filename_param(f)).collect(),
+        };
+        body.extend_from_slice(format!("Content-Disposition: {}\r\n", disposition).as_bytes());
+
+        if let Some(content_type) = &file.content_type {
+            body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(&file.content);
+        body.extend_from_slice(b"\r\n");
+    }
+
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    (
+        format!("multipart/{}; boundary={}", MIXED, boundary),
+        body.into(),
+    )
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, form_files, parse, FilePart};
+    use actix_web::web::Bytes;
+
+    fn file(name: &str, content: &'static str) -> FilePart {
+        FilePart {
+            filename: Some(name.to_owned()),
+            content_type: Some("text/plain".to_owned()),
+            content: Bytes::from_static(content.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn curl_form_upload_is_parsed() {
+        let body = Bytes::from_static(
+            b"--XyZ\r\n\
+              Content-Disposition: form-data; name=\"f\"; filename=\"main.rs\"\r\n\
+              Content-Type: application/octet-stream\r\n\
+              \r\n\
+              fn main() {}\n\r\n\
+              --XyZ--\r\n",
+        );
+
+        let parts = parse(&body, "XyZ").unwrap();
+        assert_eq!(1, parts.len());
+        assert_eq!(Some("main.rs".to_owned()), parts[0].filename);
+        assert_eq!(
+            Some("application/octet-stream".to_owned()),
+            parts[0].content_type
+        );
+        assert_eq!(&b"fn main() {}\n"[..], parts[0].content);
+    }
+
+    #[test]
+    fn preamble_and_headerless_parts_are_handled() {
+        let body = Bytes::from_static(b"preamble\r\n--b\r\n\r\nhello\r\n--b--");
+
+        let parts = parse(&body, "b").unwrap();
+        assert_eq!(1, parts.len());
+        assert_eq!(None, parts[0].filename);
+        assert_eq!(&b"hello"[..], parts[0].content);
+    }
+
+    #[test]
+    fn only_form_parts_with_a_filename_are_files() {
+        let body = Bytes::from_static(
+            b"--b\r\n\
+              Content-Disposition: form-data; name=\"f\"; filename=\"a.txt\"\r\n\
+              \r\n\
+              hello\r\n\
+              --b\r\n\
+              Content-Disposition: form-data; name=\"ttl\"\r\n\
+              \r\n\
+              1h\r\n\
+              --b--\r\n",
+        );
+
+        let err = form_files(&body, "b").unwrap_err();
+        assert!(err.contains("ttl"), "{}", err);
+    }
+
+    #[test]
+    fn a_lone_form_field_is_the_content() {
+        let body = Bytes::from_static(
+            b"--b\r\n\
+              Content-Disposition: form-data; name=\"f\"\r\n\
+              \r\n\
+              hello\r\n\
+              --b--\r\n",
+        );
+
+        let files = form_files(&body, "b").unwrap();
+        assert_eq!(1, files.len());
+        assert_eq!(None, files[0].filename);
+        assert_eq!(&b"hello"[..], files[0].content);
+    }
+
+    #[test]
+    fn unterminated_body_is_rejected() {
+        let body = Bytes::from_static(b"--b\r\n\r\nhello");
+        assert!(parse(&body, "b").is_err());
+    }
+
+    #[test]
+    fn files_round_trip_through_encode() {
+        let files = vec![
+            file("a.rs", "fn a() {}"),
+            file("b \"q\".py", "def b(): pass"),
+        ];
+
+        let (content_type, body) = encode(&files);
+        let boundary = content_type.split("boundary=").nth(1).unwrap();
+
+        assert_eq!(files, parse(&body, boundary).unwrap());
+    }
+}
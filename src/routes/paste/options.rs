@@ -0,0 +1,248 @@
+use actix_web::{http::header::CONTENT_TYPE, web::Bytes, HttpRequest};
+use chrono::{DateTime, Utc};
+use mime::{Mime, APPLICATION_OCTET_STREAM as OCTET_STREAM, JSON, TEXT, XML};
+use serde::Deserialize;
+
+use super::{multipart, password::PASSWORD_HEADER};
+use crate::{
+    domain::{Encryption, PasteContent, PastePassword, PasteTtl, Visibility},
+    highlight::Language,
+};
+
+/// Header alternative to the `ttl` query param.
+pub const TTL_HEADER: &str = "X-Paste-TTL";
+/// Header alternative to the `burn` query param.
+pub const BURN_HEADER: &str = "X-Paste-Burn";
+/// Header alternative to the `encryption` query param. Also set on the raw
+/// view of an encrypted paste.
+pub const ENCRYPTION_HEADER: &str = "X-Paste-Encryption";
+
+/// Header alternative to the `visibility` query param.
+pub const VISIBILITY_HEADER: &str = "X-Paste-Visibility";
+
+/// Carries the owner token: set on create responses, required to update or
+/// delete.
+pub const TOKEN_HEADER: &str = "X-Paste-Token";
+
+/// Query params accepted by the create routes.
+#[derive(Debug, Deserialize)]
+pub struct CreatePasteQuery {
+    pub ttl: Option<String>,
+    pub burn: Option<String>,
+    /// Scheme the body was encrypted with by the client
+    pub encryption: Option<String>,
+    /// `public`, `unlisted` or `private`
+    pub visibility: Option<String>,
+}
+
+/// Options for a new paste, gathered from the query string and headers.
+#[derive(Debug, Default)]
+pub struct CreatePasteOptions {
+    pub expires_at: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+    pub encryption: Option<Encryption>,
+    pub visibility: Visibility,
+    /// Only taken from its header, as query strings end up in logs
+    pub password: Option<PastePassword>,
+}
+
+impl CreatePasteOptions {
+    /// Query params take precedence over the equivalent headers.
+    pub fn parse(query: CreatePasteQuery, req: &HttpRequest) -> Result<Self, String> {
+        let ttl = match query.ttl {
+            Some(ttl) => Some(ttl),
+            None => header(req, TTL_HEADER)?,
+        };
+
+        let expires_at = ttl.map(expiry).transpose()?;
+
+        let burn_after_reading = match query.burn {
+            Some(burn) => parse_flag("burn", &burn)?,
+            None => match header(req, BURN_HEADER)? {
+                Some(burn) => parse_flag(BURN_HEADER, &burn)?,
+                None => false,
+            },
+        };
+
+        let encryption = match query.encryption {
+            Some(scheme) => Some(scheme),
+            None => header(req, ENCRYPTION_HEADER)?,
+        };
+
+        let visibility = match query.visibility {
+            Some(visibility) => Some(visibility),
+            None => header(req, VISIBILITY_HEADER)?,
+        };
+
+        Ok(Self {
+            expires_at,
+            burn_after_reading,
+            encryption: encryption.map(Encryption::parse).transpose()?,
+            visibility: visibility
+                .map(Visibility::parse)
+                .transpose()?
+                .unwrap_or_default(),
+            password: header(req, PASSWORD_HEADER)?
+                .map(PastePassword::parse)
+                .transpose()?,
+        })
+    }
+}
+
+/// Private pastes are only readable with the key that owns them, so they
+/// cannot be created without one.
+pub(super) fn check_visibility(visibility: Visibility, owner: Option<&str>) -> Result<(), String> {
+    match (visibility, owner) {
+        (Visibility::Private, None) => {
+            Err("a private paste needs an API key to own it".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A bare flag such as `?burn` counts as set.
+fn parse_flag(name: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "" | "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" => Ok(false),
+        _ => Err(format!("{} is not a valid value for {}", value, name)),
+    }
+}
+
+pub(super) fn header(req: &HttpRequest, name: &str) -> Result<Option<String>, String> {
+    req.headers()
+        .get(name)
+        .map(|v| {
+            v.to_str()
+                .map(str::to_owned)
+                .map_err(|_| format!("{} header is not valid ascii", name))
+        })
+        .transpose()
+}
+
+/// When a paste created now with the given ttl expires.
+pub(super) fn expiry(ttl: String) -> Result<DateTime<Utc>, String> {
+    let ttl = PasteTtl::parse(ttl)?;
+
+    Utc::now()
+        .checked_add_signed(*ttl.as_ref())
+        .ok_or_else(|| "not a valid ttl - too long".to_string())
+}
+
+/// The declared `Content-Type` of the request, if any. `curl -d` labels
+/// every body as a form submission, so that type is treated as undeclared.
+pub(super) fn content_type(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|ct| !ct.starts_with("application/x-www-form-urlencoded"))
+        .map(str::to_owned)
+}
+
+/// Parse a request body as paste content, holding it to the text rules when
+/// the request declared a textual type.
+pub(super) fn parse_content(
+    req: &HttpRequest,
+    bytes: Bytes,
+    max_bytes: usize,
+) -> Result<PasteContent, String> {
+    parse_typed(content_type(req).as_deref(), bytes, max_bytes)
+}
+
+fn parse_typed(
+    content_type: Option<&str>,
+    bytes: Bytes,
+    max_bytes: usize,
+) -> Result<PasteContent, String> {
+    match content_type {
+        Some(ct) if is_text(ct) => PasteContent::parse_text(bytes, max_bytes),
+        _ => PasteContent::parse_bytes(bytes, max_bytes),
+    }
+}
+
+/// The body of a create request, and what it says about itself.
+pub(super) struct Upload {
+    pub content: PasteContent,
+    pub content_type: Option<String>,
+    /// Inferred from the name of an uploaded file
+    pub language: Option<String>,
+}
+
+/// Parse the body of a create request. A `multipart/form-data` body, as sent
+/// by `curl -F`, is unpacked: a single file becomes the paste's content, while
+/// several are kept together as a multi-file paste. See
+/// `multipart::form_files` for which parts count as files. An `encrypted` upload is
+/// ciphertext whatever type it declares, and holds a single file.
+pub(super) fn parse_upload(
+    req: &HttpRequest,
+    bytes: Bytes,
+    max_bytes: usize,
+    encrypted: bool,
+) -> Result<Upload, String> {
+    let declared = content_type(req);
+    let parse = |content_type: Option<&str>, bytes| match encrypted {
+        true => PasteContent::parse_bytes(bytes, max_bytes),
+        false => parse_typed(content_type, bytes, max_bytes),
+    };
+
+    let Some(boundary) = declared.as_deref().and_then(multipart::form_data_boundary) else {
+        return Ok(Upload {
+            content: parse(declared.as_deref(), bytes)?,
+            content_type: declared,
+            language: None,
+        });
+    };
+
+    let mut files = multipart::form_files(&bytes, &boundary)?;
+    if encrypted && files.len() > 1 {
+        return Err("An encrypted paste holds a single file".to_string());
+    }
+
+    for file in files.iter_mut() {
+        // Each file is held to the rules its own type calls for
+        parse(file.content_type.as_deref(), file.content.clone())?;
+
+        // curl labels files it has no type for as generic binary data
+        if file.content_type.as_deref() == Some(OCTET_STREAM.as_ref())
+            && std::str::from_utf8(&file.content).is_ok()
+        {
+            file.content_type = None;
+        }
+    }
+
+    match files.as_slice() {
+        [] => Err("not valid paste content - no files".to_string()),
+        [file] => Ok(Upload {
+            content: PasteContent::parse_bytes(file.content.clone(), max_bytes)?,
+            content_type: file.content_type.clone(),
+            language: file
+                .filename
+                .as_deref()
+                .and_then(Language::infer)
+                .map(|l| l.name.to_owned()),
+        }),
+        files => {
+            let (content_type, body) = multipart::encode(files);
+            Ok(Upload {
+                content: PasteContent::parse_bytes(body, max_bytes)?,
+                content_type: Some(content_type),
+                language: None,
+            })
+        }
+    }
+}
+
+/// Whether a media type such as `text/plain; charset=utf-8` or
+/// `application/ld+json` denotes text.
+pub fn is_text(content_type: &str) -> bool {
+    match content_type.parse::<Mime>() {
+        Ok(m) => {
+            m.type_() == TEXT
+                || m.subtype() == JSON
+                || m.subtype() == XML
+                || m.suffix() == Some(JSON)
+                || m.suffix() == Some(XML)
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,102 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::{auth::Authenticated, PasteMeta};
+use crate::{
+    dao::{Repository, RepositoryError},
+    domain::PasteId,
+};
+
+/// Most pastes a single bulk delete may name.
+const MAX_BULK_DELETE: usize = 1000;
+
+/// Response to `GET /api/v1/me/pastes`.
+#[derive(Debug, Serialize)]
+pub struct OwnedPastesResponse {
+    /// Newest first
+    pub pastes: Vec<PasteMeta>,
+}
+
+/// Body of `DELETE /api/v1/me/pastes`.
+#[derive(Debug, Deserialize)]
+pub struct BulkDeleteRequest {
+    pub ids: Vec<String>,
+}
+
+/// Response to `DELETE /api/v1/me/pastes`.
+#[derive(Debug, Serialize)]
+pub struct BulkDeleteResponse {
+    pub deleted: Vec<String>,
+    /// Ids that are missing or owned by someone else, which are not told
+    /// apart
+    pub not_found: Vec<String>,
+}
+
+#[tracing::instrument(name = "Listing owned pastes", skip(repo, key), fields(owner = %key.0.name))]
+pub async fn list_owned_pastes<R: Repository>(
+    repo: web::Data<R>,
+    key: Authenticated,
+) -> impl Responder {
+    let mut pastes = match repo.find_by_owner(key.0.name).await {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    let now = Utc::now();
+    pastes.retain(|p| !p.is_expired(now));
+    pastes.sort_by_key(|p| std::cmp::Reverse(p.created_at));
+
+    HttpResponse::Ok().json(OwnedPastesResponse {
+        pastes: pastes.iter().map(PasteMeta::from).collect(),
+    })
+}
+
+#[tracing::instrument(
+    name = "Deleting owned pastes",
+    skip(repo, key, request),
+    fields(owner = %key.0.name)
+)]
+pub async fn delete_owned_pastes<R: Repository>(
+    repo: web::Data<R>,
+    key: Authenticated,
+    request: web::Json<BulkDeleteRequest>,
+) -> impl Responder {
+    let ids = request.into_inner().ids;
+    if ids.len() > MAX_BULK_DELETE {
+        return HttpResponse::BadRequest().body(format!(
+            "at most {} pastes can be deleted at once",
+            MAX_BULK_DELETE
+        ));
+    }
+
+    let mut response = BulkDeleteResponse {
+        deleted: Vec::new(),
+        not_found: Vec::new(),
+    };
+
+    for raw_id in ids {
+        let paste_id = match PasteId::parse(raw_id.clone()) {
+            Ok(p) => p,
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        };
+
+        let owned = match repo.find_one(paste_id.clone()).await {
+            Ok(p) => p.owner.as_ref() == Some(&key.0.name),
+            Err(RepositoryError::NotFound(_)) => false,
+            Err(e) => return HttpResponse::from_error(e),
+        };
+
+        match owned {
+            true => match repo.delete(paste_id).await {
+                Ok(()) => response.deleted.push(raw_id),
+                // Deleted by another request in between
+                Err(RepositoryError::NotFound(_)) => response.not_found.push(raw_id),
+                Err(e) => return HttpResponse::from_error(e),
+            },
+            false => response.not_found.push(raw_id),
+        }
+    }
+
+    HttpResponse::Ok().json(response)
+}
@@ -0,0 +1,141 @@
+use std::time::Instant;
+
+use actix_web::{
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use super::{options::header, urls::paste_url, views};
+use crate::{
+    domain::{Paste, PastePassword, PastePasswordHash},
+    rate_limit::{too_many, RateLimiter},
+};
+
+/// Carries the password of a paste: sets it on create, and gives it on read
+/// as an alternative to basic auth.
+pub const PASSWORD_HEADER: &str = "X-Paste-Password";
+
+/// How to ask a reader for a password they did not give, or got wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Prompt {
+    /// `WWW-Authenticate: Basic`, for clients such as `curl -u`
+    Basic,
+    /// An HTML form that posts the password to `/paste/{id}/unlock`
+    Form,
+}
+
+/// Hash the password a new paste is to take, if any, on a blocking thread.
+pub(super) async fn hash_password(
+    password: Option<PastePassword>,
+) -> Result<Option<PastePasswordHash>, Box<HttpResponse>> {
+    let Some(password) = password else {
+        return Ok(None);
+    };
+
+    match web::block(move || password.hash()).await {
+        Ok(Ok(hash)) => Ok(Some(hash)),
+        Ok(Err(e)) => {
+            tracing::error!("{}", e);
+            Err(Box::new(HttpResponse::InternalServerError().finish()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to hash a paste password: {}", e);
+            Err(Box::new(HttpResponse::InternalServerError().finish()))
+        }
+    }
+}
+
+/// The password a read request gives, in the password header or as the
+/// password of `Authorization: Basic` credentials, whose user name is
+/// ignored. The header takes precedence.
+pub(super) fn supplied_password(req: &HttpRequest) -> Result<Option<String>, String> {
+    if let Some(password) = header(req, PASSWORD_HEADER)? {
+        return Ok(Some(password));
+    }
+
+    let Some(credentials) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+    else {
+        return Ok(None);
+    };
+
+    let invalid = || "Authorization header holds invalid basic credentials".to_string();
+    let decoded = BASE64.decode(credentials.trim()).map_err(|_| invalid())?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+    match decoded.split_once(':') {
+        Some((_, password)) => Ok(Some(password.to_owned())),
+        None => Err(invalid()),
+    }
+}
+
+/// Check that `supplied` is the password of `paste`, if it takes one,
+/// producing the response to send back if it is not. Wrong passwords count
+/// against the paste's rate limit, and once that runs out no more are
+/// checked until it refills.
+pub(super) async fn check_password(
+    req: &HttpRequest,
+    paste: &Paste,
+    supplied: Option<String>,
+    limiter: &RateLimiter,
+    prompt: Prompt,
+) -> Result<(), Box<HttpResponse>> {
+    let Some(hash) = paste.password_hash.clone() else {
+        return Ok(());
+    };
+
+    if let Err(wait) = limiter.check_password(&paste.id, Instant::now()) {
+        return Err(Box::new(too_many(wait, "Too many wrong passwords")));
+    }
+
+    let Some(supplied) = supplied else {
+        return Err(Box::new(ask(req, paste, prompt, None)));
+    };
+
+    let verified = match PastePassword::parse(supplied) {
+        Ok(password) => web::block(move || hash.verify(&password))
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to verify a paste password: {}", e);
+                Box::new(HttpResponse::InternalServerError().finish())
+            })?,
+        Err(_) => false,
+    };
+
+    if verified {
+        return Ok(());
+    }
+
+    limiter.wrong_password(&paste.id, Instant::now());
+    Err(Box::new(ask(req, paste, prompt, Some("wrong password"))))
+}
+
+fn ask(req: &HttpRequest, paste: &Paste, prompt: Prompt, error: Option<&str>) -> HttpResponse {
+    let status = match error {
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::UNAUTHORIZED,
+    };
+
+    match prompt {
+        Prompt::Form => {
+            // Keeps the query, so the unlocked paste is shown as asked for
+            let mut action = format!("{}/unlock", paste_url(req, &paste.id));
+            if !req.query_string().is_empty() {
+                action = format!("{}?{}", action, req.query_string());
+            }
+            views::password_prompt(paste, &action, error, status)
+        }
+        Prompt::Basic => match error {
+            Some(error) => HttpResponse::build(status).body(error.to_owned()),
+            None => HttpResponse::build(status)
+                .insert_header((WWW_AUTHENTICATE, "Basic realm=\"paste\", charset=\"UTF-8\""))
+                .body("this paste needs a password"),
+        },
+    }
+}
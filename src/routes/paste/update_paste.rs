@@ -0,0 +1,85 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+
+use super::{
+    auth::{check_owner, MaybeAuthenticated},
+    body::read_body,
+    multipart,
+    options::{content_type, parse_content},
+};
+use crate::{
+    config::LimitSettings,
+    dao::Repository,
+    domain::{PasteContent, PasteId},
+};
+
+#[tracing::instrument(
+    name = "Updating a paste",
+    skip(repo, req, payload, limits, key),
+    fields(
+        raw_paste_id = %raw_paste_id,
+    )
+)]
+pub async fn update_paste<R: Repository>(
+    raw_paste_id: web::Path<String>,
+    req: HttpRequest,
+    payload: web::Payload,
+    repo: web::Data<R>,
+    limits: web::Data<LimitSettings>,
+    key: MaybeAuthenticated,
+) -> impl Responder {
+    let raw_paste_id = raw_paste_id.into_inner();
+    let paste_id = match PasteId::parse(raw_paste_id) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    // Only a paste's content is replaced, never its type or files, so an
+    // upload would be stored as the form it came in
+    if content_type(&req).is_some_and(|ct| multipart::is_form_data(&ct)) {
+        return HttpResponse::UnsupportedMediaType().body(
+            "PUT replaces a paste's content with the request body as is; \
+             send the file itself, e.g. with --data-binary @FILE, not as a form",
+        );
+    }
+
+    let bytes = match read_body(&req, payload, limits.max_paste_bytes).await {
+        Ok(b) => b,
+        Err(response) => return *response,
+    };
+
+    let paste = match repo.find_one(paste_id.clone()).await {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    // Checked first, so only those who may manage a paste learn it expired
+    if let Err(response) = check_owner(&req, &paste, key.0.as_ref()) {
+        return *response;
+    }
+
+    if paste.is_expired(Utc::now()) {
+        return HttpResponse::Gone().body(format!("Expired: {}", paste_id.as_ref()));
+    }
+
+    // The new content of an encrypted paste is ciphertext too
+    let parsed = match paste.encryption {
+        Some(_) => PasteContent::parse_bytes(bytes, limits.max_paste_bytes),
+        None => parse_content(&req, bytes, limits.max_paste_bytes),
+    };
+    let paste_content = match parsed {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    // The paste takes the type its new content is declared as, just as it
+    // would on create
+    let updated = repo
+        .update(paste_id.clone(), paste_content, content_type(&req))
+        .await;
+
+    return match updated {
+        Ok(_) => HttpResponse::Ok().body(paste_id.as_ref().to_owned()),
+        Err(e) => HttpResponse::from_error(e),
+    };
+}
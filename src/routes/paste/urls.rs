@@ -0,0 +1,64 @@
+use actix_web::{http::header::HOST, web, HttpRequest};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::{config::ApplicationSettings, domain::PasteId, rate_limit::RateLimiter};
+
+/// Characters that cannot appear as-is in a URL path segment.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Absolute URL of a paste, under the configured base URL or else the address
+/// the request was made to.
+pub(super) fn paste_url(req: &HttpRequest, id: &PasteId) -> String {
+    let base_url = req
+        .app_data::<web::Data<ApplicationSettings>>()
+        .and_then(|settings| settings.base_url.clone());
+
+    let base_url = match base_url {
+        Some(base_url) => base_url.trim_end_matches('/').to_owned(),
+        None => request_origin(req),
+    };
+
+    format!(
+        "{}/paste/{}",
+        base_url,
+        utf8_percent_encode(id.as_ref(), PATH_SEGMENT)
+    )
+}
+
+/// The scheme and host the request was made to. Only requests from trusted
+/// proxies are seen past, as anyone could send the headers they forward.
+fn request_origin(req: &HttpRequest) -> String {
+    let from_proxy = req
+        .peer_addr()
+        .zip(req.app_data::<web::Data<RateLimiter>>())
+        .is_some_and(|(peer, limiter)| limiter.is_trusted(peer.ip()));
+
+    if from_proxy {
+        let conn = req.connection_info();
+        return format!("{}://{}", conn.scheme(), conn.host());
+    }
+
+    let config = req.app_config();
+    let scheme = match config.secure() {
+        true => "https",
+        false => "http",
+    };
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(config.host());
+
+    format!("{}://{}", scheme, host)
+}
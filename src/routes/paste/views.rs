@@ -0,0 +1,365 @@
+use actix_web::{
+    http::header::{
+        Accept, AcceptEncoding, Charset, ContentDisposition, DispositionParam, DispositionType,
+        Encoding, ExtendedValue, Header, Quality, CONTENT_ENCODING, CONTENT_SECURITY_POLICY,
+        X_CONTENT_TYPE_OPTIONS,
+    },
+    http::StatusCode,
+    HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use mime::{Mime, APPLICATION_JSON, HTML, IMAGE, STAR, SVG, TEXT_HTML};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+use super::{
+    multipart::{self, FilePart},
+    options::{is_text, ENCRYPTION_HEADER},
+    PasteMeta,
+};
+use crate::{
+    domain::{EncodedContent, Encryption, Paste},
+    highlight::{escape_html, highlight, Language},
+};
+
+/// The ways a paste can be presented to a reader.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum View {
+    /// The content exactly as it was submitted
+    Raw,
+    /// A syntax-highlighted page with line numbers
+    Html,
+    /// A JSON envelope of the content and its metadata
+    Json,
+}
+
+/// A paste as served by the JSON view.
+#[derive(Debug, Serialize)]
+pub struct PasteEnvelope {
+    #[serde(flatten)]
+    pub meta: PasteMeta,
+    pub content: String,
+    /// How `content` is encoded: `utf-8` for text, `base64` for binary data
+    pub encoding: &'static str,
+    /// The files of a multi-file paste
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileEnvelope>>,
+}
+
+/// One file of a multi-file paste, as served by the JSON view.
+#[derive(Debug, Serialize)]
+pub struct FileEnvelope {
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub content: String,
+    pub encoding: &'static str,
+}
+
+/// Pick the view that best suits the request's `Accept` header, or `None` if
+/// the paste cannot be served as anything the reader accepts. Readers that
+/// accept anything, or say nothing, get the raw content.
+pub(super) fn negotiate(req: &HttpRequest, paste: &Paste) -> Option<View> {
+    let accept = match Accept::parse(req) {
+        Ok(accept) if !accept.is_empty() => accept,
+        _ => return Some(View::Raw),
+    };
+
+    let refused: Vec<&Mime> = accept
+        .iter()
+        .filter(|q| q.quality == Quality::ZERO)
+        .map(|q| &q.item)
+        .collect();
+
+    let mut offers = vec![];
+    if let Ok(raw) = raw_content_type(paste).parse::<Mime>() {
+        offers.push((View::Raw, raw));
+    }
+    if paste.content.as_text().is_some() || paste.encryption.is_some() {
+        offers.push((View::Html, TEXT_HTML));
+    }
+    offers.push((View::Json, APPLICATION_JSON));
+
+    offers.retain(|(_, offer)| !refused.iter().any(|range| matches(range, offer)));
+
+    accept.ranked().iter().find_map(|range| {
+        offers
+            .iter()
+            .find(|(_, offer)| matches(range, offer))
+            .map(|(view, _)| *view)
+    })
+}
+
+/// Whether the media range `range`, e.g. `text/*`, covers `mime`.
+fn matches(range: &Mime, mime: &Mime) -> bool {
+    range.type_() == STAR
+        || (range.type_() == mime.type_()
+            && (range.subtype() == STAR || range.subtype() == mime.subtype()))
+}
+
+/// The type a paste's raw content is served as: whatever it was submitted
+/// as, or a guess based on whether it is text. Encrypted content is opaque
+/// whatever its author said it was.
+fn raw_content_type(paste: &Paste) -> String {
+    if paste.encryption.is_some() {
+        return "application/octet-stream".to_string();
+    }
+
+    paste
+        .content_type
+        .clone()
+        .unwrap_or_else(|| match paste.content.as_text() {
+            Some(_) => "text/plain; charset=utf-8".to_string(),
+            None => "application/octet-stream".to_string(),
+        })
+}
+
+/// Serve a paste's content as it was submitted. Types a browser can display
+/// safely are shown inline; anything else is offered as a download. Content
+/// stored compressed is sent as stored to clients that accept its coding.
+pub(super) fn raw(req: &HttpRequest, paste: Paste) -> HttpResponse {
+    let content_type = raw_content_type(&paste);
+
+    let displayable = match content_type.parse::<Mime>() {
+        Ok(m) if m.subtype() == HTML || m.subtype() == SVG => false,
+        Ok(m) => is_text(&content_type) || m.type_() == IMAGE,
+        Err(_) => false,
+    };
+
+    let disposition = ContentDisposition {
+        disposition: if displayable {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        },
+        parameters: vec![filename_param(paste.id.as_ref())],
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .insert_header(disposition)
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((CONTENT_SECURITY_POLICY, "sandbox"));
+    if let Some(encryption) = &paste.encryption {
+        response.insert_header((ENCRYPTION_HEADER, encryption.as_ref()));
+    }
+
+    match paste.encoded.filter(|e| accepts_encoding(req, e)) {
+        Some(encoded) => response
+            .insert_header((CONTENT_ENCODING, encoded.encoding))
+            .body(encoded.bytes),
+        None => response.body(paste.content.into_bytes()),
+    }
+}
+
+/// Whether the request's `Accept-Encoding` prefers `encoded`'s content coding
+/// to none at all.
+fn accepts_encoding(req: &HttpRequest, encoded: &EncodedContent) -> bool {
+    let Ok(encoding) = encoded.encoding.parse::<Encoding>() else {
+        return false;
+    };
+    let supported = [Encoding::identity(), encoding.clone()];
+
+    AcceptEncoding::parse(req)
+        .ok()
+        .and_then(|accept| accept.negotiate(supported.iter()))
+        .is_some_and(|chosen| chosen == encoding)
+}
+
+/// Render a paste as an HTML page with each line numbered and linkable as
+/// `#L<n>`. The language to highlight as is `language` if given, else the
+/// paste's own or one inferred from its id. The files of a multi-file paste
+/// each get a section, highlighted by their own file names, and their lines
+/// are linkable as `#F<file>L<n>`.
+pub(super) fn html(paste: Paste, language: Option<&Language>) -> HttpResponse {
+    if let Some(encryption) = &paste.encryption {
+        return encrypted_page(&paste, encryption);
+    }
+
+    let (body, shown_language) = match multipart::stored_files(&paste) {
+        Some(files) => {
+            let sections = files
+                .iter()
+                .enumerate()
+                .map(|(i, file)| file_section(i + 1, file, language))
+                .collect();
+            (sections, None)
+        }
+        None => {
+            let Some(text) = paste.content.as_text() else {
+                return HttpResponse::UnsupportedMediaType()
+                    .body("Binary pastes can only be viewed raw: use ?view=raw");
+            };
+
+            let language = language
+                .or_else(|| paste.language.as_deref().and_then(Language::find))
+                .or_else(|| Language::infer(paste.id.as_ref()));
+
+            (numbered_lines(text, language, "L"), language)
+        }
+    };
+
+    let page = include_str!("../../../static/paste.html")
+        .replace("{{title}}", &escape_html(paste.id.as_ref()))
+        .replace("{{language}}", shown_language.map_or("", |l| l.name))
+        .replace("{{raw}}", "?view=raw")
+        .replace("{{body}}", &body);
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((
+            CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'",
+        ))
+        .body(page)
+}
+
+/// The script that decrypts an encrypted paste in the reader's browser.
+const DECRYPT_SCRIPT: &str = include_str!("../../../static/decrypt.js");
+
+/// A page that decrypts a paste in the reader's browser, with the key from
+/// the URL fragment. The server never sees the key, so the ciphertext is all
+/// it can put on the page; its lines are linkable as `#<key>.L<n>`.
+fn encrypted_page(paste: &Paste, encryption: &Encryption) -> HttpResponse {
+    let page = include_str!("../../../static/encrypted.html")
+        .replace("{{scheme}}", encryption.as_ref())
+        .replace("{{raw}}", "?view=raw")
+        .replace("{{ciphertext}}", &BASE64.encode(paste.content.as_ref()))
+        .replace("{{script}}", DECRYPT_SCRIPT)
+        .replace("{{title}}", &escape_html(paste.id.as_ref()));
+
+    // Only the decryption script itself may run on the page
+    static POLICY: OnceLock<String> = OnceLock::new();
+    let policy = POLICY.get_or_init(|| {
+        format!(
+            "default-src 'none'; style-src 'unsafe-inline'; script-src 'sha256-{}'",
+            BASE64.encode(Sha256::digest(DECRYPT_SCRIPT))
+        )
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((CONTENT_SECURITY_POLICY, policy.as_str()))
+        .body(page)
+}
+
+/// A page asking for the password of a paste, which posts it to `action`.
+/// Sent with `status`, and saying why if the last one given was wrong.
+pub(super) fn password_prompt(
+    paste: &Paste,
+    action: &str,
+    error: Option<&str>,
+    status: StatusCode,
+) -> HttpResponse {
+    let message = error
+        .map(|e| format!("<p class=\"error\">{}</p>", escape_html(e)))
+        .unwrap_or_default();
+    let page = include_str!("../../../static/password.html")
+        .replace("{{action}}", &escape_html(action))
+        .replace("{{message}}", &message)
+        .replace("{{title}}", &escape_html(paste.id.as_ref()));
+
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((
+            CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'",
+        ))
+        .body(page)
+}
+
+/// A `<pre>` of highlighted lines, each anchored as `<prefix><n>`.
+fn numbered_lines(text: &str, language: Option<&Language>, prefix: &str) -> String {
+    let lines: String = highlight(text, language)
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            format!(
+                "<span class=\"line\" id=\"{p}{n}\"><a class=\"ln\" href=\"#{p}{n}\">{n}</a>{line}</span>",
+                p = prefix,
+                n = i + 1,
+                line = line,
+            )
+        })
+        .collect();
+
+    format!("<pre>{}</pre>", lines)
+}
+
+/// One file of a multi-file paste, anchored as `F<n>`.
+fn file_section(n: usize, file: &FilePart, language: Option<&Language>) -> String {
+    let name = file.filename.as_deref().unwrap_or("untitled");
+    let language = language.or_else(|| Language::infer(name));
+    let anchor = format!("F{}", n);
+
+    let content = match std::str::from_utf8(&file.content) {
+        Ok(text) => numbered_lines(text, language, &format!("{}L", anchor)),
+        Err(_) => "<p>Binary file</p>".to_string(),
+    };
+
+    format!(
+        "<section id=\"{a}\"><h2><a href=\"#{a}\">{name}</a> <small>{lang}</small></h2>{content}</section>",
+        a = anchor,
+        name = escape_html(name),
+        lang = language.map_or("", |l| l.name),
+        content = content,
+    )
+}
+
+/// Serve a paste and its metadata as JSON. Binary and encrypted content is
+/// base64 encoded, and the files of a multi-file paste are listed separately.
+pub(super) fn json(paste: Paste) -> HttpResponse {
+    let meta = PasteMeta::from(&paste);
+
+    let files = multipart::stored_files(&paste).map(|files| {
+        files
+            .into_iter()
+            .map(|file| {
+                let (content, encoding) = encode_content(&file.content);
+                FileEnvelope {
+                    filename: file.filename,
+                    content_type: file.content_type,
+                    content,
+                    encoding,
+                }
+            })
+            .collect()
+    });
+
+    let (content, encoding) = match paste.encryption {
+        Some(_) => (BASE64.encode(paste.content.as_ref()), "base64"),
+        None => encode_content(paste.content.as_ref()),
+    };
+
+    HttpResponse::Ok().json(PasteEnvelope {
+        meta,
+        content,
+        encoding,
+        files,
+    })
+}
+
+/// Content as a JSON string, with the name of its encoding.
+fn encode_content(bytes: &[u8]) -> (String, &'static str) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_owned(), "utf-8"),
+        Err(_) => (BASE64.encode(bytes), "base64"),
+    }
+}
+
+pub(super) fn filename_param(name: &str) -> DispositionParam {
+    if name.is_ascii() {
+        DispositionParam::Filename(name.to_owned())
+    } else {
+        DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_owned()),
+            language_tag: None,
+            value: name.as_bytes().to_vec(),
+        })
+    }
+}
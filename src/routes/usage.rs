@@ -0,0 +1,42 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+
+use super::Authenticated;
+use crate::{
+    config::{ApplicationSettings, QuotaPolicy, QuotaSettings},
+    dao::Repository,
+};
+
+/// How much storage is in use, against the quota.
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub pastes: u64,
+    pub bytes: u64,
+    pub max_pastes: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub policy: QuotaPolicy,
+}
+
+/// Only for operators, whose API keys are named in `admin_keys`.
+#[tracing::instrument(name = "Reporting storage usage", skip(repo, quota, application, key))]
+pub async fn usage<R: Repository>(
+    repo: web::Data<R>,
+    quota: web::Data<QuotaSettings>,
+    application: web::Data<ApplicationSettings>,
+    key: Authenticated,
+) -> impl Responder {
+    if !application.admin_keys.contains(&key.0.name) {
+        return HttpResponse::Forbidden().body("only operators can see storage usage");
+    }
+
+    match repo.usage().await {
+        Ok(usage) => HttpResponse::Ok().json(UsageReport {
+            pastes: usage.pastes,
+            bytes: usage.bytes,
+            max_pastes: quota.max_pastes,
+            max_bytes: quota.max_bytes,
+            policy: quota.policy,
+        }),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
@@ -1,24 +1,137 @@
 use actix_web::{dev::Server, web, App, HttpServer};
-use std::{net::TcpListener, sync::Mutex};
+use chrono::Utc;
+use std::{net::TcpListener, sync::Arc, time::Duration};
 use tracing_actix_web::TracingLogger;
 
-use crate::{dao::Repository, routes};
+use crate::{
+    config::Settings,
+    dao::{KeyStore, QuotaRepository, Repository},
+    rate_limit::{RateLimit, RateLimiter},
+    routes,
+};
 
-pub fn run<R: Repository>(listener: TcpListener, repo: R) -> Result<Server, std::io::Error> {
-    let repo = web::Data::new(Mutex::new(repo));
+/// How often expired pastes are purged from the repository.
+const REAPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often clients whose rate limits have reset are forgotten.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn run<R: Repository + KeyStore>(
+    listener: TcpListener,
+    repo: R,
+    config: Settings,
+) -> Result<Server, std::io::Error> {
+    // Whatever the backend, it is held to the quota
+    let quota = web::Data::new(config.storage.quota);
+    let repo = web::Data::new(QuotaRepository::new(repo, config.storage.quota));
+    let keys: web::Data<dyn KeyStore> =
+        web::Data::from(repo.clone().into_inner() as Arc<dyn KeyStore>);
+    let limits = web::Data::new(config.limits);
+    let application = web::Data::new(config.application);
+    let ids = web::Data::from(
+        config
+            .ids
+            .generator()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    );
+    let limiter = web::Data::new(
+        RateLimiter::new(&config.rate_limits)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    );
+
+    tokio::spawn(reap_expired(repo.clone().into_inner(), REAPER_INTERVAL));
+    tokio::spawn(sweep_rate_limits(
+        limiter.clone().into_inner(),
+        SWEEP_INTERVAL,
+    ));
 
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(RateLimit::new(limiter.clone().into_inner()))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(routes::index))
             .route("/health", web::get().to(routes::health))
-            .route("/paste", web::post().to(routes::create_paste_sans_id::<R>))
-            .route("/paste/{id}", web::post().to(routes::create_paste::<R>))
-            .route("/paste/{id}", web::get().to(routes::get_paste::<R>))
+            .route("/usage", web::get().to(routes::usage::<QuotaRepository<R>>))
+            .route(
+                "/paste",
+                web::post().to(routes::create_paste_sans_id::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}",
+                web::post().to(routes::create_paste::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}",
+                web::get().to(routes::get_paste::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}/unlock",
+                web::post().to(routes::unlock_paste::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/by-hash/{hash}",
+                web::get().to(routes::get_paste_by_hash::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}/meta",
+                web::get().to(routes::get_paste_meta::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}",
+                web::put().to(routes::update_paste::<QuotaRepository<R>>),
+            )
+            .route(
+                "/paste/{id}",
+                web::delete().to(routes::delete_paste::<QuotaRepository<R>>),
+            )
+            .route(
+                "/api/v1/pastes",
+                web::post().to(routes::create_paste_json::<QuotaRepository<R>>),
+            )
+            .route(
+                "/api/v1/me/pastes",
+                web::get().to(routes::list_owned_pastes::<QuotaRepository<R>>),
+            )
+            .route(
+                "/api/v1/me/pastes",
+                web::delete().to(routes::delete_owned_pastes::<QuotaRepository<R>>),
+            )
             .app_data(web::Data::clone(&repo))
+            .app_data(web::Data::clone(&keys))
+            .app_data(web::Data::clone(&limits))
+            .app_data(web::Data::clone(&quota))
+            .app_data(web::Data::clone(&application))
+            .app_data(web::Data::clone(&ids))
+            .app_data(web::Data::clone(&limiter))
     })
     .listen(listener)?
     .run();
 
     Ok(server)
 }
+
+/// Periodically purge expired pastes until the runtime shuts down.
+async fn reap_expired<R: Repository>(repo: Arc<R>, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        match repo.purge_expired(Utc::now()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Purged {} expired pastes", n),
+            Err(e) => tracing::error!("Failed to purge expired pastes: {}", e),
+        }
+    }
+}
+
+/// Periodically forget clients whose rate limits have reset, so the limiter
+/// only remembers recent clients.
+async fn sweep_rate_limits(limiter: Arc<RateLimiter>, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+        limiter.sweep();
+    }
+}
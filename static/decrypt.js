@@ -0,0 +1,67 @@
+"use strict";
+
+// Decrypts an AES-256-GCM paste with the key in the URL fragment, which
+// browsers never send to the server. Line links keep the key, as #KEY.L12.
+(async () => {
+  const status = document.getElementById("status");
+  const content = document.getElementById("content");
+  const [key] = location.hash.slice(1).split(".");
+
+  const decode = (b64) =>
+    Uint8Array.from(atob(b64.replace(/-/g, "+").replace(/_/g, "/")), (c) => c.charCodeAt(0));
+
+  if (!key) {
+    status.textContent =
+      "This paste is encrypted. Open it with the full link, including the key after the #.";
+    return;
+  }
+
+  let text;
+  try {
+    const data = decode(document.body.dataset.ciphertext);
+    const cryptoKey = await crypto.subtle.importKey("raw", decode(key), "AES-GCM", false, [
+      "decrypt",
+    ]);
+    const plain = await crypto.subtle.decrypt(
+      { name: "AES-GCM", iv: data.slice(0, 12) },
+      cryptoKey,
+      data.slice(12),
+    );
+    text = new TextDecoder("utf-8", { fatal: true }).decode(plain);
+  } catch (e) {
+    status.textContent = "This paste could not be decrypted: the key is wrong or incomplete.";
+    return;
+  }
+
+  status.remove();
+  text
+    .replace(/\n$/, "")
+    .split("\n")
+    .forEach((source, i) => {
+      const n = i + 1;
+      const line = document.createElement("span");
+      line.className = "line";
+      line.id = "L" + n;
+
+      const link = document.createElement("a");
+      link.className = "ln";
+      link.href = "#" + key + ".L" + n;
+      link.textContent = n;
+
+      line.append(link, source);
+      content.append(line);
+    });
+
+  const mark = () => {
+    const [, target] = location.hash.slice(1).split(".");
+    document.querySelectorAll(".line.target").forEach((line) => line.classList.remove("target"));
+
+    const line = target && document.getElementById(target);
+    if (line) {
+      line.classList.add("target");
+      line.scrollIntoView();
+    }
+  };
+  window.addEventListener("hashchange", mark);
+  mark();
+})();
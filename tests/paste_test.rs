@@ -1,25 +1,130 @@
 mod utils;
 
+use utils::{
+    spawn_app_with_config, spawn_app_with_keys, spawn_app_with_keys_and_config, test_config,
+    Backend, TestApp, MAX_PASTE_BYTES,
+};
+use whtpst::config::{BucketSettings, IdStrategy, QuotaPolicy};
+use whtpst::domain::ContentHash;
+
+/// Run each of the given tests once per storage backend, so every backend is
+/// held to the same behaviour.
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod inmemory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(crate::utils::spawn_app_with_backend(super::Backend::InMemory).await)
+                        .await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(crate::utils::spawn_app_with_backend(super::Backend::Sqlite).await)
+                        .await;
+                }
+            )*
+        }
+
+        mod filesystem {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(
+                        crate::utils::spawn_app_with_backend(super::Backend::FileSystem).await,
+                    )
+                    .await;
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    get_paste_returns_paste_when_exists,
+    get_paste_returns_404_when_not_found,
+    get_paste_returns_400_when_bad_id,
+    paste_returns_201_and_url_when_ok,
+    paste_returns_400_when_invalid_content,
+    paste_returns_400_when_invalid_id,
+    paste_generates_id_when_none_provided,
+    generated_id_retrieves_paste,
+    concurrent_pastes_are_all_stored,
+    paste_with_ttl_is_gone_after_expiry,
+    paste_with_ttl_header_is_retrievable_before_expiry,
+    paste_returns_400_when_invalid_ttl,
+    burn_after_reading_paste_is_served_once,
+    delete_paste_removes_paste_with_valid_token,
+    delete_paste_returns_403_when_wrong_token,
+    delete_paste_returns_401_when_no_token,
+    paste_returns_409_when_id_taken,
+    put_paste_updates_content_with_valid_token,
+    put_paste_returns_403_when_wrong_token,
+    put_paste_returns_404_when_not_found,
+    put_paste_rejects_form_uploads,
+    put_paste_replaces_the_content_type,
+    paste_meta_reports_metadata,
+    paste_meta_returns_404_when_not_found,
+    binary_paste_round_trips_with_content_type,
+    text_paste_returns_400_when_not_utf8,
+    paste_at_size_limit_is_accepted,
+    paste_returns_413_when_too_large,
+    streamed_paste_returns_413_when_too_large,
+    raw_view_is_plain_text_by_default,
+    html_view_highlights_by_extension,
+    html_view_highlights_by_lang_param,
+    html_view_escapes_content,
+    accept_text_plain_gets_raw_content,
+    accept_html_gets_html_view,
+    accept_json_gets_envelope,
+    accept_json_base64_encodes_binary_content,
+    accept_returns_406_when_nothing_fits,
+    api_create_paste_returns_urls_and_token,
+    api_create_paste_applies_options,
+    api_create_paste_generates_id_when_none_provided,
+    api_create_paste_returns_400_when_invalid,
+    api_create_paste_returns_409_when_id_taken,
+    api_create_paste_returns_413_when_too_large,
+    form_upload_of_one_file_becomes_paste,
+    form_upload_of_several_files_becomes_multi_file_paste,
+    form_upload_returns_400_when_no_files,
+    form_upload_returns_400_for_fields_besides_files,
+    form_upload_of_a_lone_field_becomes_paste,
+    paste_by_hash_redirects_to_paste,
+    paste_by_hash_returns_404_when_not_found,
+    paste_by_hash_returns_400_when_invalid,
+    paste_by_hash_skips_password_protected_pastes,
+    encrypted_paste_is_served_as_opaque_bytes,
+    encrypted_paste_html_view_decrypts_in_browser,
+    encrypted_paste_json_view_is_base64,
+    paste_returns_400_when_unknown_encryption,
+    api_create_encrypted_paste_takes_base64_content,
+    encrypted_form_upload_returns_400_when_several_files,
+);
+
 // GET /paste
-#[tokio::test]
-async fn get_paste_returns_paste_when_exists() {
-    let app = utils::spawn_app().await;
+async fn get_paste_returns_paste_when_exists(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = "abc";
     let paste_content = "somecontent";
 
     let response = client
-        .post(&format!("{}/paste/{}", &app.address, &paste_id))
+        .post(format!("{}/paste/{}", &app.address, &paste_id))
         .header("Content-Type", "text/plain")
         .body(paste_content)
         .send()
         .await
         .expect("Failed to execute request");
 
-    let paste_id = response.text().await.expect("Failed to get response data");
+    let url = response.text().await.expect("Failed to get response data");
 
     let response = client
-        .get(&format!("{}/paste/{}", &app.address, &paste_id))
+        .get(url.trim())
         .send()
         .await
         .expect("Failed to execute request");
@@ -29,14 +134,12 @@ async fn get_paste_returns_paste_when_exists() {
     assert_eq!(paste_content, payload);
 }
 
-#[tokio::test]
-async fn get_paste_returns_404_when_not_found() {
-    let app = utils::spawn_app().await;
+async fn get_paste_returns_404_when_not_found(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = "abc";
 
     let response = client
-        .get(&format!("{}/paste/{}", &app.address, &paste_id))
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
         .send()
         .await
         .expect("Failed to execute request");
@@ -46,14 +149,12 @@ async fn get_paste_returns_404_when_not_found() {
     assert_eq!(format!("Not found: {}", paste_id), payload);
 }
 
-#[tokio::test]
-async fn get_paste_returns_400_when_bad_id() {
-    let app = utils::spawn_app().await;
+async fn get_paste_returns_400_when_bad_id(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = ":{";
 
     let response = client
-        .get(&format!("{}/paste/{}", &app.address, &paste_id))
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
         .send()
         .await
         .expect("Failed to execute request");
@@ -68,33 +169,32 @@ async fn get_paste_returns_400_when_bad_id() {
 
 // POST /paste
 
-#[tokio::test]
-async fn paste_returns_200_and_id_when_ok() {
-    let app = utils::spawn_app().await;
+async fn paste_returns_201_and_url_when_ok(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = "abc";
 
     let response = client
-        .post(&format!("{}/paste/{}", &app.address, &paste_id))
+        .post(format!("{}/paste/{}", &app.address, &paste_id))
         .header("Content-Type", "text/plain")
         .body("somecontent")
         .send()
         .await
         .expect("Failed to execute request");
-    assert_eq!(200, response.status().as_u16());
+    assert_eq!(201, response.status().as_u16());
+
+    let url = format!("{}/paste/{}", &app.address, paste_id);
+    assert_eq!(url.as_str(), response.headers()["Location"]);
 
     let payload = response.text().await.expect("Failed to get response data");
-    assert_eq!(paste_id, payload);
+    assert_eq!(format!("{}\n", url), payload);
 }
 
-#[tokio::test]
-async fn paste_returns_400_when_invalid_content() {
-    let app = utils::spawn_app().await;
+async fn paste_returns_400_when_invalid_content(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = "abc";
 
     let response = client
-        .post(&format!("{}/paste/{}", &app.address, &paste_id))
+        .post(format!("{}/paste/{}", &app.address, &paste_id))
         .header("Content-Type", "text/plain")
         .body("")
         .send()
@@ -106,14 +206,12 @@ async fn paste_returns_400_when_invalid_content() {
     assert_eq!("not valid paste content - empty string", payload);
 }
 
-#[tokio::test]
-async fn paste_returns_400_when_invalid_id() {
-    let app = utils::spawn_app().await;
+async fn paste_returns_400_when_invalid_id(app: TestApp) {
     let client = reqwest::Client::new();
     let paste_id = "{s";
 
     let response = client
-        .post(&format!("{}/paste/{}", &app.address, &paste_id))
+        .post(format!("{}/paste/{}", &app.address, &paste_id))
         .header("Content-Type", "text/plain")
         .body("somecontent")
         .send()
@@ -128,51 +226,1908 @@ async fn paste_returns_400_when_invalid_id() {
     );
 }
 
-#[tokio::test]
-async fn paste_generates_id_when_none_provided() {
-    let app = utils::spawn_app().await;
+async fn paste_generates_id_when_none_provided(app: TestApp) {
     let client = reqwest::Client::new();
 
     let response = client
-        .post(&format!("{}/paste", &app.address))
+        .post(format!("{}/paste", &app.address))
         .header("Content-Type", "text/plain")
         .body("somecontent")
         .send()
         .await
         .expect("Failed to execute request");
-    assert_eq!(200, response.status().as_u16());
+    assert_eq!(201, response.status().as_u16());
 
     let payload = response.text().await.expect("Failed to get response data");
-    assert_eq!(36, payload.len()); // UUID
+    let (base, paste_id) = payload.trim().rsplit_once('/').unwrap();
+    assert_eq!(format!("{}/paste", &app.address), base);
+    assert_eq!(36, paste_id.len()); // UUID
 }
 
 // Full workflows
-#[tokio::test]
-async fn generated_id_retrieves_paste() {
-    let app = utils::spawn_app().await;
+async fn generated_id_retrieves_paste(app: TestApp) {
     let client = reqwest::Client::new();
 
     let content = "somecontent";
 
     let response = client
-        .post(&format!("{}/paste", &app.address))
+        .post(format!("{}/paste", &app.address))
         .header("Content-Type", "text/plain")
         .body(content)
         .send()
         .await
         .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    let url = response.text().await.expect("Failed to get response data");
+
+    let response = client
+        .get(url.trim())
+        .header("Content-Type", "text/plain")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!(content, payload);
+}
+
+async fn concurrent_pastes_are_all_stored(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    let handles: Vec<_> = (0..20)
+        .map(|i| {
+            let client = client.clone();
+            let url = format!("{}/paste/concurrent-{}", &app.address, i);
+            tokio::spawn(async move {
+                client
+                    .post(url)
+                    .header("Content-Type", "text/plain")
+                    .body(format!("content-{}", i))
+                    .send()
+                    .await
+                    .expect("Failed to execute request")
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(201, handle.await.unwrap().status().as_u16());
+    }
+
+    for i in 0..20 {
+        let response = client
+            .get(format!("{}/paste/concurrent-{}", &app.address, i))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(200, response.status().as_u16());
+
+        let payload = response.text().await.expect("Failed to get response data");
+        assert_eq!(format!("content-{}", i), payload);
+    }
+}
+
+// Expiry
+async fn paste_with_ttl_is_gone_after_expiry(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    let response = client
+        .post(format!("{}/paste/{}?ttl=1s", &app.address, &paste_id))
+        .header("Content-Type", "text/plain")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(410, response.status().as_u16());
+
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!(format!("Expired: {}", paste_id), payload);
+}
+
+async fn paste_with_ttl_header_is_retrievable_before_expiry(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/paste", &app.address))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-TTL", "1h")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    let url = response.text().await.expect("Failed to get response data");
+
+    let response = client
+        .get(url.trim())
+        .send()
+        .await
+        .expect("Failed to execute request");
     assert_eq!(200, response.status().as_u16());
+}
+
+async fn paste_returns_400_when_invalid_ttl(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/paste?ttl=soon", &app.address))
+        .header("Content-Type", "text/plain")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(400, response.status().as_u16());
 
     let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!("soon is not a valid ttl - expected e.g. 30m or 1h", payload);
+}
+
+// Burn after reading
+async fn burn_after_reading_paste_is_served_once(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+    let paste_content = "somecontent";
 
     let response = client
-        .get(&format!("{}/paste/{}", &app.address, payload))
+        .post(format!("{}/paste/{}?burn", &app.address, &paste_id))
         .header("Content-Type", "text/plain")
+        .body(paste_content)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
         .send()
         .await
         .expect("Failed to execute request");
     assert_eq!(200, response.status().as_u16());
 
     let payload = response.text().await.expect("Failed to get response data");
-    assert_eq!(content, payload);
+    assert_eq!(paste_content, payload);
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+// DELETE /paste
+async fn create_paste(app: &TestApp, paste_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/paste/{}", &app.address, paste_id))
+        .header("Content-Type", "text/plain")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn delete_paste_removes_paste_with_valid_token(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    let response = create_paste(&app, paste_id).await;
+    let token = response
+        .headers()
+        .get("X-Paste-Token")
+        .expect("Missing paste token")
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .delete(format!("{}/paste/{}", &app.address, &paste_id))
+        .header("X-Paste-Token", token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(204, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn delete_paste_returns_403_when_wrong_token(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    create_paste(&app, paste_id).await;
+
+    let response = client
+        .delete(format!("{}/paste/{}", &app.address, &paste_id))
+        .header("X-Paste-Token", "not-the-token")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(403, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+}
+
+async fn delete_paste_returns_401_when_no_token(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    create_paste(&app, paste_id).await;
+
+    let response = client
+        .delete(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(401, response.status().as_u16());
+}
+
+// PUT /paste
+async fn paste_returns_409_when_id_taken(app: TestApp) {
+    let paste_id = "abc";
+
+    let response = create_paste(&app, paste_id).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = create_paste(&app, paste_id).await;
+    assert_eq!(409, response.status().as_u16());
+
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!(format!("Already exists: {}", paste_id), payload);
+}
+
+async fn put_paste_updates_content_with_valid_token(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    let response = create_paste(&app, paste_id).await;
+    let token = response
+        .headers()
+        .get("X-Paste-Token")
+        .expect("Missing paste token")
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .put(format!("{}/paste/{}", &app.address, &paste_id))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-Token", token)
+        .body("newcontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!("newcontent", payload);
+}
+
+async fn put_paste_returns_403_when_wrong_token(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    create_paste(&app, paste_id).await;
+
+    let response = client
+        .put(format!("{}/paste/{}", &app.address, &paste_id))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-Token", "not-the-token")
+        .body("newcontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(403, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!("somecontent", payload);
+}
+
+async fn put_paste_returns_404_when_not_found(app: TestApp) {
+    let response = reqwest::Client::new()
+        .put(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-Token", "sometoken")
+        .body("newcontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn put_paste_rejects_form_uploads(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    let response = create_paste(&app, paste_id).await;
+    let token = response.headers()["X-Paste-Token"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .put(format!("{}/paste/{}", &app.address, &paste_id))
+        .header("Content-Type", "multipart/form-data; boundary=XYZ")
+        .header("X-Paste-Token", token)
+        .body("--XYZ\r\nContent-Disposition: form-data; name=\"f\"; filename=\"a.txt\"\r\n\r\nnewcontent\r\n--XYZ--\r\n")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(415, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/{}", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let payload = response.text().await.expect("Failed to get response data");
+    assert_eq!("somecontent", payload);
+}
+
+async fn put_paste_replaces_the_content_type(app: TestApp) {
+    let client = reqwest::Client::new();
+    let content: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0x00];
+
+    let response = client
+        .post(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "image/png")
+        .body(content)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let token = response.headers()["X-Paste-Token"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = client
+        .put(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-Token", token)
+        .body("now text")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!("text/plain", response.headers()["Content-Type"]);
+    assert_eq!("now text", response.text().await.unwrap());
+}
+
+// GET /paste/{id}/meta
+async fn paste_meta_reports_metadata(app: TestApp) {
+    let client = reqwest::Client::new();
+    let paste_id = "abc";
+
+    create_paste(&app, paste_id).await;
+
+    for _ in 0..2 {
+        client
+            .get(format!("{}/paste/{}", &app.address, &paste_id))
+            .send()
+            .await
+            .expect("Failed to execute request");
+    }
+
+    let response = client
+        .get(format!("{}/paste/{}/meta", &app.address, &paste_id))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let meta: serde_json::Value = response.json().await.expect("Failed to parse metadata");
+    assert_eq!(paste_id, meta["id"]);
+    assert_eq!(11, meta["size"]);
+    assert_eq!("text/plain", meta["content_type"]);
+    assert_eq!(2, meta["views"]);
+    assert!(meta["created_at"].is_string());
+    assert!(meta["updated_at"].is_null());
+}
+
+async fn paste_meta_returns_404_when_not_found(app: TestApp) {
+    let response = reqwest::Client::new()
+        .get(format!("{}/paste/abc/meta", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+// Binary pastes
+async fn binary_paste_round_trips_with_content_type(app: TestApp) {
+    let client = reqwest::Client::new();
+    let content: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0x00];
+
+    let response = client
+        .post(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "image/png")
+        .body(content)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("image/png", response.headers()["Content-Type"]);
+    assert_eq!(
+        "inline; filename=\"abc\"",
+        response.headers()["Content-Disposition"]
+    );
+    assert_eq!(
+        content,
+        response
+            .bytes()
+            .await
+            .expect("Failed to read body")
+            .as_ref()
+    );
+}
+
+async fn text_paste_returns_400_when_not_utf8(app: TestApp) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "text/plain")
+        .body(vec![0xff, 0xfe, 0x00])
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(400, response.status().as_u16());
+}
+
+// Size limit
+async fn paste_at_size_limit_is_accepted(app: TestApp) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste", &app.address))
+        .header("Content-Type", "text/plain")
+        .body("a".repeat(MAX_PASTE_BYTES))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+}
+
+async fn paste_returns_413_when_too_large(app: TestApp) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "text/plain")
+        .body("a".repeat(MAX_PASTE_BYTES + 1))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(413, response.status().as_u16());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn streamed_paste_returns_413_when_too_large(app: TestApp) {
+    // Chunked, so the server only learns the size while reading the body
+    let chunks: Vec<Result<Vec<u8>, std::io::Error>> = (0..4)
+        .map(|_| Ok(vec![b'a'; MAX_PASTE_BYTES / 2]))
+        .collect();
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste", &app.address))
+        .header("Content-Type", "text/plain")
+        .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks)))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(413, response.status().as_u16());
+}
+
+// Views
+async fn raw_view_is_plain_text_by_default(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/abc", &app.address))
+        .body("fn main() {}")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = client
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/plain; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    assert_eq!("fn main() {}", response.text().await.unwrap());
+}
+
+async fn html_view_highlights_by_extension(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/foo.rs", &app.address))
+        .body("fn main() {}\nlet x = 1;\n")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = client
+        .get(format!("{}/paste/foo.rs.html", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/html; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<span class=\"kw\">fn</span> main"));
+    assert!(body.contains("id=\"L2\""));
+    assert!(body.contains("href=\"#L2\""));
+    assert!(!body.contains("id=\"L3\""));
+}
+
+async fn html_view_highlights_by_lang_param(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/py", &app.address))
+        .body("def f(): pass")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = client
+        .get(format!("{}/paste/py?view=html&lang=python", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<span class=\"kw\">def</span> f"));
+}
+
+async fn html_view_escapes_content(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/abc", &app.address))
+        .body("<script>alert(1)</script>")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = client
+        .get(format!("{}/paste/abc?view=html", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("<script>alert"));
+    assert!(body.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+}
+
+// Content negotiation
+async fn get_with_accept(app: &TestApp, paste_id: &str, accept: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/paste/{}", &app.address, paste_id))
+        .header("Accept", accept)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn accept_text_plain_gets_raw_content(app: TestApp) {
+    create_paste(&app, "abc").await;
+
+    let response = get_with_accept(&app, "abc", "text/plain").await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("text/plain", response.headers()["Content-Type"]);
+    assert_eq!("accept", response.headers()["Vary"]);
+    assert_eq!("somecontent", response.text().await.unwrap());
+}
+
+async fn accept_html_gets_html_view(app: TestApp) {
+    create_paste(&app, "abc").await;
+
+    let accept = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    let response = get_with_accept(&app, "abc", accept).await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/html; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    assert!(response.text().await.unwrap().contains("id=\"L1\""));
+}
+
+async fn accept_json_gets_envelope(app: TestApp) {
+    create_paste(&app, "abc").await;
+
+    let response = get_with_accept(&app, "abc", "application/json").await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("application/json", response.headers()["Content-Type"]);
+
+    let envelope: serde_json::Value = response.json().await.expect("Failed to parse envelope");
+    assert_eq!("abc", envelope["id"]);
+    assert_eq!("somecontent", envelope["content"]);
+    assert_eq!("utf-8", envelope["encoding"]);
+    assert_eq!(11, envelope["size"]);
+    assert_eq!("text/plain", envelope["content_type"]);
+}
+
+async fn accept_json_base64_encodes_binary_content(app: TestApp) {
+    reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("Content-Type", "application/octet-stream")
+        .body(vec![0xff, 0x00, 0xfe])
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = get_with_accept(&app, "abc", "application/json").await;
+    assert_eq!(200, response.status().as_u16());
+
+    let envelope: serde_json::Value = response.json().await.expect("Failed to parse envelope");
+    assert_eq!("/wD+", envelope["content"]);
+    assert_eq!("base64", envelope["encoding"]);
+}
+
+async fn accept_returns_406_when_nothing_fits(app: TestApp) {
+    reqwest::Client::new()
+        .post(format!("{}/paste/abc?burn", &app.address))
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = get_with_accept(&app, "abc", "image/png").await;
+    assert_eq!(406, response.status().as_u16());
+
+    // A refused request does not burn the paste
+    let response = get_with_accept(&app, "abc", "*/*").await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+// POST /api/v1/pastes
+async fn api_create_paste(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/pastes", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn api_create_paste_returns_urls_and_token(app: TestApp) {
+    let response = api_create_paste(&app, serde_json::json!({"id": "abc", "content": "x"})).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let url = format!("{}/paste/abc", &app.address);
+    assert_eq!(url.as_str(), response.headers()["Location"]);
+
+    let created: serde_json::Value = response.json().await.expect("Failed to parse response");
+    assert_eq!("abc", created["id"]);
+    assert_eq!(url, created["url"]);
+    assert_eq!(format!("{}?view=raw", url), created["raw_url"]);
+
+    let response = reqwest::Client::new()
+        .delete(&url)
+        .header("X-Paste-Token", created["token"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(204, response.status().as_u16());
+}
+
+async fn api_create_paste_applies_options(app: TestApp) {
+    let response = api_create_paste(
+        &app,
+        serde_json::json!({
+            "id": "abc",
+            "content": "fn main() {}",
+            "ttl": "1h",
+            "language": "rs",
+            "visibility": "unlisted",
+            "burn_after_reading": true,
+        }),
+    )
+    .await;
+    assert_eq!(201, response.status().as_u16());
+
+    let meta: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/paste/abc/meta", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to parse metadata");
+    assert_eq!("rust", meta["language"]);
+    assert_eq!("unlisted", meta["visibility"]);
+    assert_eq!(true, meta["burn_after_reading"]);
+    assert!(meta["expires_at"].is_string());
+
+    // The stored language drives highlighting even without an extension
+    let body = reqwest::Client::new()
+        .get(format!("{}/paste/abc?view=html", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("<span class=\"kw\">fn</span>"));
+}
+
+async fn api_create_paste_generates_id_when_none_provided(app: TestApp) {
+    let response = api_create_paste(&app, serde_json::json!({"content": "x"})).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let created: serde_json::Value = response.json().await.expect("Failed to parse response");
+    let response = reqwest::get(created["raw_url"].as_str().unwrap())
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("x", response.text().await.unwrap());
+}
+
+async fn api_create_paste_returns_400_when_invalid(app: TestApp) {
+    let test_cases = vec![
+        (serde_json::json!({"content": " "}), "blank content"),
+        (
+            serde_json::json!({"id": "a/b", "content": "x"}),
+            "invalid id",
+        ),
+        (
+            serde_json::json!({"content": "x", "ttl": "soon"}),
+            "invalid ttl",
+        ),
+        (
+            serde_json::json!({"content": "x", "language": "klingon"}),
+            "unknown language",
+        ),
+        (
+            serde_json::json!({"content": "x", "visibility": "secret"}),
+            "invalid visibility",
+        ),
+        (serde_json::json!({"id": "abc"}), "missing content"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = api_create_paste(&app, body).await;
+        assert_eq!(400, response.status().as_u16(), "{}", description);
+    }
+}
+
+async fn api_create_paste_returns_409_when_id_taken(app: TestApp) {
+    create_paste(&app, "abc").await;
+
+    let response = api_create_paste(&app, serde_json::json!({"id": "abc", "content": "x"})).await;
+    assert_eq!(409, response.status().as_u16());
+}
+
+async fn api_create_paste_returns_413_when_too_large(app: TestApp) {
+    let content = "a".repeat(MAX_PASTE_BYTES + 1);
+
+    let response = api_create_paste(&app, serde_json::json!({ "content": content })).await;
+    assert_eq!(413, response.status().as_u16());
+}
+
+// multipart/form-data uploads
+const FORM_BOUNDARY: &str = "------------------------d74496d66958873e";
+
+/// A form body as `curl -F` sends it, with one part per `(filename, content)`.
+fn form_body(files: &[(&str, &str)]) -> String {
+    let mut body = String::new();
+    for (i, (filename, content)) in files.iter().enumerate() {
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"f{}\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n{}\r\n",
+            FORM_BOUNDARY, i, filename, content
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", FORM_BOUNDARY));
+    body
+}
+
+async fn post_form(app: &TestApp, path: &str, body: String) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}{}", &app.address, path))
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", FORM_BOUNDARY),
+        )
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn form_upload_of_one_file_becomes_paste(app: TestApp) {
+    let body = form_body(&[("main.rs", "fn main() {}")]);
+    let response = post_form(&app, "/paste/abc", body).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(
+        "text/plain; charset=utf-8",
+        response.headers()["Content-Type"]
+    );
+    assert_eq!("fn main() {}", response.text().await.unwrap());
+
+    let meta: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/paste/abc/meta", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to parse metadata");
+    assert_eq!("rust", meta["language"]);
+}
+
+async fn form_upload_of_several_files_becomes_multi_file_paste(app: TestApp) {
+    let body = form_body(&[("main.rs", "fn main() {}"), ("run.py", "def run(): pass")]);
+    let response = post_form(&app, "/paste", body).await;
+    assert_eq!(201, response.status().as_u16());
+    let url = response.text().await.unwrap();
+
+    let html = reqwest::Client::new()
+        .get(format!("{}?view=html", url.trim()))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("main.rs"));
+    assert!(html.contains("<span class=\"kw\">fn</span>"));
+    assert!(html.contains("id=\"F2L1\""));
+    assert!(html.contains("<span class=\"kw\">def</span>"));
+
+    let envelope: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}?view=json", url.trim()))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to parse envelope");
+    let files = envelope["files"].as_array().expect("Missing files");
+    assert_eq!(2, files.len());
+    assert_eq!("run.py", files[1]["filename"]);
+    assert_eq!("def run(): pass", files[1]["content"]);
+}
+
+async fn form_upload_returns_400_when_no_files(app: TestApp) {
+    let response = post_form(&app, "/paste", form_body(&[])).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+async fn form_upload_returns_400_for_fields_besides_files(app: TestApp) {
+    // As sent by curl -F f=@a.txt -F ttl=1h
+    let mut body = form_body(&[("a.txt", "hello")]);
+    body.insert_str(
+        0,
+        &format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"ttl\"\r\n\r\n1h\r\n",
+            FORM_BOUNDARY
+        ),
+    );
+
+    let response = post_form(&app, "/paste/abc", body).await;
+    assert_eq!(400, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("ttl"));
+}
+
+async fn form_upload_of_a_lone_field_becomes_paste(app: TestApp) {
+    // As sent by curl -F 'f=<-'
+    let body = format!(
+        "--{0}\r\nContent-Disposition: form-data; name=\"f\"\r\n\r\nhello\r\n--{0}--\r\n",
+        FORM_BOUNDARY
+    );
+
+    let response = post_form(&app, "/paste/abc", body).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/paste/abc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!("hello", response.text().await.unwrap());
+}
+
+// Paste URLs
+#[tokio::test]
+async fn paste_url_uses_configured_base_url() {
+    let mut config = test_config();
+    config.application.base_url = Some("https://paste.example.com/".to_string());
+    let app = spawn_app_with_config(config).await;
+
+    let response = create_paste(&app, "a b").await;
+    assert_eq!(201, response.status().as_u16());
+    assert_eq!(
+        "https://paste.example.com/paste/a%20b",
+        response.headers()["Location"]
+    );
+    assert_eq!(
+        "https://paste.example.com/paste/a%20b\n",
+        response.text().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn paste_url_follows_forwarded_headers_from_trusted_proxies() {
+    let mut config = test_config();
+    config.rate_limits.trusted_proxies = vec!["127.0.0.1".to_string()];
+    let app = spawn_app_with_config(config).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("X-Forwarded-Proto", "https")
+        .header("X-Forwarded-Host", "proxy.example.com")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+    assert_eq!(
+        "https://proxy.example.com/paste/abc",
+        response.headers()["Location"]
+    );
+}
+
+#[tokio::test]
+async fn paste_url_ignores_forwarded_headers_from_anyone_else() {
+    let app = spawn_app_with_config(test_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("X-Forwarded-Proto", "https")
+        .header("X-Forwarded-Host", "evil.example.com")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+    assert_eq!(
+        format!("{}/paste/abc", &app.address),
+        response.headers()["Location"]
+    );
+}
+
+// Generated ids
+async fn generated_id(app: &TestApp) -> String {
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste", &app.address))
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+
+    let url = response.text().await.expect("Failed to get response data");
+    url.trim().rsplit_once('/').unwrap().1.to_owned()
+}
+
+#[tokio::test]
+async fn generated_ids_follow_configured_strategy() {
+    let mut config = test_config();
+    config.ids.strategy = IdStrategy::Words;
+    let app = spawn_app_with_config(config).await;
+
+    let paste_id = generated_id(&app).await;
+    assert_eq!(4, paste_id.split('-').count(), "{}", paste_id);
+
+    let mut config = test_config();
+    config.ids.strategy = IdStrategy::Base62;
+    config.ids.length = 8;
+    let app = spawn_app_with_config(config).await;
+
+    let paste_id = generated_id(&app).await;
+    assert_eq!(8, paste_id.len(), "{}", paste_id);
+    assert!(paste_id.chars().all(|c| c.is_ascii_alphanumeric()));
+}
+
+// GET /paste/by-hash
+async fn paste_by_hash_redirects_to_paste(app: TestApp) {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    client
+        .post(format!("{}/paste/abc", &app.address))
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let meta: serde_json::Value = client
+        .get(format!("{}/paste/abc/meta", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let sha256 = meta["sha256"].as_str().unwrap();
+    assert_eq!(64, sha256.len());
+
+    let response = client
+        .get(format!("{}/paste/by-hash/{}", &app.address, sha256))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(303, response.status().as_u16());
+    assert_eq!(
+        format!("{}/paste/abc", &app.address),
+        response.headers()["Location"]
+    );
+}
+
+async fn paste_by_hash_returns_404_when_not_found(app: TestApp) {
+    let response = reqwest::get(format!("{}/paste/by-hash/{}", &app.address, "0".repeat(64)))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn paste_by_hash_returns_400_when_invalid(app: TestApp) {
+    let response = reqwest::get(format!("{}/paste/by-hash/nothex", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+async fn paste_by_hash_skips_password_protected_pastes(app: TestApp) {
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/locked", &app.address))
+        .header("X-Paste-Password", "hunter2")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Guessing the content must not find the paste
+    let sha256 = ContentHash::of(b"somecontent");
+    let response = client
+        .get(format!(
+            "{}/paste/by-hash/{}",
+            &app.address,
+            sha256.as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+// Encrypted pastes
+const CIPHERTEXT: &[u8] = &[0x01, 0x02, 0xff, 0x00, 0x9c, b'a'];
+
+async fn create_encrypted_paste(app: &TestApp, paste_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/paste/{}", &app.address, paste_id))
+        .header("Content-Type", "text/plain")
+        .header("X-Paste-Encryption", "aes-256-gcm")
+        .body(CIPHERTEXT)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn encrypted_paste_is_served_as_opaque_bytes(app: TestApp) {
+    let response = create_encrypted_paste(&app, "abc").await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/paste/abc?view=raw", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "application/octet-stream",
+        response.headers()["Content-Type"]
+    );
+    assert_eq!("aes-256-gcm", response.headers()["X-Paste-Encryption"]);
+    assert_eq!(
+        "attachment; filename=\"abc\"",
+        response.headers()["Content-Disposition"]
+    );
+    assert_eq!(CIPHERTEXT, response.bytes().await.unwrap().as_ref());
+
+    let meta: serde_json::Value = reqwest::get(format!("{}/paste/abc/meta", &app.address))
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to parse metadata");
+    assert_eq!("aes-256-gcm", meta["encryption"]);
+}
+
+async fn encrypted_paste_html_view_decrypts_in_browser(app: TestApp) {
+    create_encrypted_paste(&app, "abc").await;
+
+    let response = get_with_accept(&app, "abc", "text/html").await;
+    assert_eq!(200, response.status().as_u16());
+    let policy = response.headers()["Content-Security-Policy"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert!(policy.contains("script-src 'sha256-"));
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("data-ciphertext=\"AQL/AJxh\""));
+    assert!(html.contains("crypto.subtle.decrypt"));
+}
+
+async fn encrypted_paste_json_view_is_base64(app: TestApp) {
+    reqwest::Client::new()
+        .post(format!("{}/paste/abc?encryption=aes-256-gcm", &app.address))
+        .header("Content-Type", "application/octet-stream")
+        .body("looks like text")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let response = get_with_accept(&app, "abc", "application/json").await;
+    let envelope: serde_json::Value = response.json().await.expect("Failed to parse envelope");
+    assert_eq!("bG9va3MgbGlrZSB0ZXh0", envelope["content"]);
+    assert_eq!("base64", envelope["encoding"]);
+    assert_eq!("aes-256-gcm", envelope["encryption"]);
+}
+
+async fn paste_returns_400_when_unknown_encryption(app: TestApp) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/paste/abc", &app.address))
+        .header("X-Paste-Encryption", "rot13")
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(400, response.status().as_u16());
+}
+
+async fn api_create_encrypted_paste_takes_base64_content(app: TestApp) {
+    let response = api_create_paste(
+        &app,
+        serde_json::json!({"id": "abc", "content": "AQL/AJxh", "encryption": "aes-256-gcm"}),
+    )
+    .await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = reqwest::get(format!("{}/paste/abc?view=raw", &app.address))
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(CIPHERTEXT, response.bytes().await.unwrap().as_ref());
+
+    let response = api_create_paste(
+        &app,
+        serde_json::json!({"id": "def", "content": "not base64!", "encryption": "aes-256-gcm"}),
+    )
+    .await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+async fn encrypted_form_upload_returns_400_when_several_files(app: TestApp) {
+    let body = form_body(&[("a.bin", "one"), ("b.bin", "two")]);
+    let response = post_form(&app, "/paste?encryption=aes-256-gcm", body).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+// Compression
+#[tokio::test]
+async fn compressed_paste_is_served_as_stored_when_accepted() {
+    for backend in [Backend::Sqlite, Backend::FileSystem] {
+        let app = utils::spawn_app_with_backend(backend).await;
+        let client = reqwest::Client::new();
+        let content = "the same line, over and over\n".repeat(100);
+
+        client
+            .post(format!("{}/paste/abc", &app.address))
+            .body(content.clone())
+            .send()
+            .await
+            .expect("Failed to execute request");
+
+        let response = client
+            .get(format!("{}/paste/abc", &app.address))
+            .header("Accept-Encoding", "zstd")
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!("zstd", response.headers()["Content-Encoding"]);
+        assert_eq!("accept, accept-encoding", response.headers()["Vary"]);
+
+        let body = response.bytes().await.unwrap();
+        assert!(body.len() < content.len());
+        assert_eq!(content.as_bytes(), zstd::decode_all(&body[..]).unwrap());
+
+        let response = client
+            .get(format!("{}/paste/abc", &app.address))
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert!(response.headers().get("Content-Encoding").is_none());
+        assert_eq!(content, response.text().await.unwrap());
+    }
+}
+
+// Rate limiting
+fn rate_limited_config(trusted_proxies: &[&str]) -> whtpst::config::Settings {
+    let mut config = test_config();
+    config.rate_limits.trusted_proxies = trusted_proxies.iter().map(|p| p.to_string()).collect();
+    config.rate_limits.create = Some(BucketSettings {
+        burst: 2,
+        per_minute: 1,
+    });
+    config.rate_limits.read = Some(BucketSettings {
+        burst: 3,
+        per_minute: 1,
+    });
+    config
+}
+
+async fn post_as(app: &TestApp, forwarded_for: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/paste", &app.address))
+        .header("X-Forwarded-For", forwarded_for)
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn creates_beyond_the_limit_get_429_with_retry_after() {
+    let app = spawn_app_with_config(rate_limited_config(&[])).await;
+
+    for _ in 0..2 {
+        assert_eq!(201, post_as(&app, "192.0.2.1").await.status().as_u16());
+    }
+
+    // A made-up X-Forwarded-For from an untrusted peer changes nothing
+    let response = post_as(&app, "192.0.2.2").await;
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    // Reads are limited apart from creates, and health checks not at all
+    let response = reqwest::get(format!("{}/", &app.address)).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    for _ in 0..5 {
+        let response = reqwest::get(format!("{}/health", &app.address))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn reads_beyond_the_limit_get_429() {
+    let app = spawn_app_with_config(rate_limited_config(&[])).await;
+    let url = format!("{}/paste/abc", &app.address);
+
+    for _ in 0..3 {
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(404, response.status().as_u16());
+    }
+    let response = reqwest::get(&url).await.unwrap();
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_limited_apart() {
+    let app = spawn_app_with_config(rate_limited_config(&["127.0.0.0/8"])).await;
+
+    for _ in 0..2 {
+        assert_eq!(201, post_as(&app, "192.0.2.1").await.status().as_u16());
+    }
+    assert_eq!(429, post_as(&app, "192.0.2.1").await.status().as_u16());
+
+    // Anything before the address the proxy saw may be made up
+    assert_eq!(
+        429,
+        post_as(&app, "203.0.113.9, 192.0.2.1")
+            .await
+            .status()
+            .as_u16()
+    );
+    assert_eq!(201, post_as(&app, "192.0.2.2").await.status().as_u16());
+}
+
+// Storage quota
+fn quota_config(max_pastes: u64, policy: QuotaPolicy) -> whtpst::config::Settings {
+    let mut config = test_config();
+    config.storage.quota.max_pastes = Some(max_pastes);
+    config.storage.quota.policy = policy;
+    config
+}
+
+async fn post_paste(app: &TestApp, id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/paste/{}", &app.address, id))
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn pastes_beyond_the_quota_get_507() {
+    let app = spawn_app_with_config(quota_config(1, QuotaPolicy::Reject)).await;
+
+    assert_eq!(201, post_paste(&app, "first").await.status().as_u16());
+    assert_eq!(507, post_paste(&app, "second").await.status().as_u16());
+
+    let response = reqwest::get(format!("{}/paste/first", &app.address))
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn pastes_beyond_the_quota_evict_the_oldest() {
+    let app = spawn_app_with_config(quota_config(1, QuotaPolicy::EvictOldest)).await;
+
+    assert_eq!(201, post_paste(&app, "first").await.status().as_u16());
+    assert_eq!(201, post_paste(&app, "second").await.status().as_u16());
+
+    for (id, status) in [("first", 404), ("second", 200)] {
+        let response = reqwest::get(format!("{}/paste/{}", &app.address, id))
+            .await
+            .unwrap();
+        assert_eq!(status, response.status().as_u16(), "{}", id);
+    }
+}
+
+#[tokio::test]
+async fn usage_is_reported_against_the_quota() {
+    let mut config = quota_config(5, QuotaPolicy::EvictLeastRecentlyRead);
+    config.application.admin_keys = vec!["ops".to_string()];
+    let (app, keys) = spawn_app_with_keys_and_config(&["ops"], config).await;
+    post_paste(&app, "first").await;
+
+    let usage: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/usage", &app.address))
+        .bearer_auth(&keys[0])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(1, usage["pastes"]);
+    assert_eq!(11, usage["bytes"]);
+    assert_eq!(5, usage["max_pastes"]);
+    assert_eq!("evict_least_recently_read", usage["policy"]);
+}
+
+#[tokio::test]
+async fn usage_is_only_for_operators() {
+    let mut config = test_config();
+    config.application.admin_keys = vec!["ops".to_string()];
+    let (app, keys) = spawn_app_with_keys_and_config(&["ops", "team"], config).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/usage", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/usage", &app.address))
+        .bearer_auth(&keys[1])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+}
+
+// API keys
+async fn post_with_key(app: &TestApp, id: &str, key: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/paste/{}", &app.address, id))
+        .bearer_auth(key)
+        .body("somecontent")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn owned_pastes(app: &TestApp, key: &str) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/me/pastes", &app.address))
+        .bearer_auth(key)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pastes_created_with_a_key_are_listed_as_owned() {
+    let (app, keys) = spawn_app_with_keys(&["team"]).await;
+    let key = &keys[0];
+    let client = reqwest::Client::new();
+
+    assert_eq!(
+        201,
+        post_with_key(&app, "first", key).await.status().as_u16()
+    );
+    let response = client
+        .post(format!("{}/api/v1/pastes", &app.address))
+        .bearer_auth(key)
+        .json(&serde_json::json!({"id": "second", "content": "more"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    post_paste(&app, "anonymous").await;
+
+    let owned = owned_pastes(&app, key).await;
+    let ids: Vec<_> = owned["pastes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(2, ids.len());
+    assert!(ids.contains(&"first") && ids.contains(&"second"));
+}
+
+#[tokio::test]
+async fn owned_pastes_need_a_valid_key() {
+    let (app, _) = spawn_app_with_keys(&["team"]).await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/v1/me/pastes", &app.address);
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!("Bearer", response.headers()["WWW-Authenticate"]);
+
+    let response = client.get(&url).bearer_auth("nope").send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    // An unknown key is refused outright, not taken for no key at all
+    let response = post_with_key(&app, "first", "nope").await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn bulk_delete_removes_only_owned_pastes() {
+    let (app, keys) = spawn_app_with_keys(&["team"]).await;
+    let key = &keys[0];
+    post_with_key(&app, "mine", key).await;
+    post_paste(&app, "theirs").await;
+
+    let response = reqwest::Client::new()
+        .delete(format!("{}/api/v1/me/pastes", &app.address))
+        .bearer_auth(key)
+        .json(&serde_json::json!({"ids": ["mine", "theirs", "missing"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(serde_json::json!(["mine"]), body["deleted"]);
+    assert_eq!(serde_json::json!(["theirs", "missing"]), body["not_found"]);
+
+    for (id, status) in [("mine", 404), ("theirs", 200)] {
+        let response = reqwest::get(format!("{}/paste/{}", &app.address, id))
+            .await
+            .unwrap();
+        assert_eq!(status, response.status().as_u16(), "{}", id);
+    }
+}
+
+#[tokio::test]
+async fn a_key_can_manage_the_pastes_it_owns_without_their_tokens() {
+    let (app, keys) = spawn_app_with_keys(&["team"]).await;
+    let key = &keys[0];
+    let client = reqwest::Client::new();
+    post_with_key(&app, "mine", key).await;
+    post_paste(&app, "theirs").await;
+
+    let response = client
+        .put(format!("{}/paste/mine", &app.address))
+        .bearer_auth(key)
+        .body("changed")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client
+        .delete(format!("{}/paste/theirs", &app.address))
+        .bearer_auth(key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    let response = client
+        .delete(format!("{}/paste/mine", &app.address))
+        .bearer_auth(key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+}
+
+// Visibility
+#[tokio::test]
+async fn private_pastes_are_readable_only_with_their_owners_key() {
+    let (app, keys) = spawn_app_with_keys(&["team", "other"]).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/paste/secret?visibility=private", &app.address))
+        .bearer_auth(&keys[0])
+        .body("somecontent")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let token = response.headers()["X-Paste-Token"].clone();
+
+    for url in ["paste/secret", "paste/secret/meta", "paste/secret.html"] {
+        let url = format!("{}/{}", &app.address, url);
+
+        let response = client.get(&url).bearer_auth(&keys[0]).send().await.unwrap();
+        assert_eq!(200, response.status().as_u16(), "{}", url);
+
+        // Told apart from a missing paste by neither status nor body
+        for request in [client.get(&url), client.get(&url).bearer_auth(&keys[1])] {
+            let response = request.send().await.unwrap();
+            assert_eq!(404, response.status().as_u16(), "{}", url);
+            assert_eq!("Not found: secret", response.text().await.unwrap());
+        }
+    }
+
+    let response = client
+        .get(format!("{}/paste/secret", &app.address))
+        .bearer_auth(&keys[0])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!("private, no-store", response.headers()["Cache-Control"]);
+
+    // Managing it without its key or token is no more telling
+    let response = client
+        .delete(format!("{}/paste/secret", &app.address))
+        .bearer_auth(&keys[1])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    let response = client
+        .delete(format!("{}/paste/secret", &app.address))
+        .header("X-Paste-Token", token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn private_pastes_need_a_key_to_own_them() {
+    let app = spawn_app_with_config(test_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/paste", &app.address))
+        .header("X-Paste-Visibility", "private")
+        .body("somecontent")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+
+    let response = client
+        .post(format!("{}/api/v1/pastes", &app.address))
+        .json(&serde_json::json!({"content": "somecontent", "visibility": "private"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn raw_creates_accept_a_visibility() {
+    let app = spawn_app_with_config(test_config()).await;
+    let client = reqwest::Client::new();
+
+    for (url, header) in [
+        ("paste/by-query?visibility=unlisted", None),
+        ("paste/by-header", Some("Unlisted")),
+    ] {
+        let mut request = client
+            .post(format!("{}/{}", &app.address, url))
+            .body("somecontent");
+        if let Some(header) = header {
+            request = request.header("X-Paste-Visibility", header);
+        }
+        assert_eq!(201, request.send().await.unwrap().status().as_u16());
+    }
+
+    for id in ["by-query", "by-header"] {
+        let meta: serde_json::Value = reqwest::get(format!("{}/paste/{}/meta", &app.address, id))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!("unlisted", meta["visibility"], "{}", id);
+    }
+
+    let response = client
+        .post(format!("{}/paste/bad?visibility=secret", &app.address))
+        .body("somecontent")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn password_protected_pastes_need_their_password_to_be_read() {
+    let app = spawn_app_with_config(test_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/paste/locked", &app.address))
+        .header("X-Paste-Password", "hunter2")
+        .body("somecontent")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+
+    for url in ["paste/locked?view=raw", "paste/locked/meta"] {
+        let response = client
+            .get(format!("{}/{}", &app.address, url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(401, response.status().as_u16(), "{}", url);
+        assert!(response.headers()["WWW-Authenticate"]
+            .to_str()
+            .unwrap()
+            .starts_with("Basic"));
+
+        let response = client
+            .get(format!("{}/{}", &app.address, url))
+            .header("X-Paste-Password", "hunter3")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(403, response.status().as_u16(), "{}", url);
+    }
+
+    // Basic auth takes any user name
+    let response = client
+        .get(format!("{}/paste/locked?view=raw", &app.address))
+        .basic_auth("anyone", Some("hunter2"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("private, no-store", response.headers()["Cache-Control"]);
+    assert_eq!("somecontent", response.text().await.unwrap());
+
+    let meta: serde_json::Value = client
+        .get(format!("{}/paste/locked/meta", &app.address))
+        .header("X-Paste-Password", "hunter2")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(true, meta["password_protected"]);
+}
+
+#[tokio::test]
+async fn json_creates_accept_a_password() {
+    let app = spawn_app_with_config(test_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/api/v1/pastes", &app.address))
+        .json(&serde_json::json!({"id": "locked", "content": "somecontent", "password": "hunter2"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+
+    let response = client
+        .get(format!("{}/paste/locked?view=json", &app.address))
+        .header("X-Paste-Password", "hunter2")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client
+        .post(format!("{}/api/v1/pastes", &app.address))
+        .json(&serde_json::json!({"content": "somecontent", "password": ""}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_html_view_prompts_for_a_password() {
+    let app = spawn_app_with_config(test_config()).await;
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}/paste/locked.rs", &app.address))
+        .header("X-Paste-Password", "hunter2")
+        .body("fn main() {}")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!("{}/paste/locked.rs?view=html", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+    assert!(response.headers().get("WWW-Authenticate").is_none());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(&format!(
+        "action=\"{}/paste/locked.rs/unlock?view=html\"",
+        &app.address
+    )));
+
+    let response = client
+        .post(format!("{}/paste/locked.rs/unlock", &app.address))
+        .form(&[("password", "hunter3")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("wrong password"));
+
+    let response = client
+        .post(format!("{}/paste/locked.rs/unlock", &app.address))
+        .form(&[("password", "hunter2")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .headers()
+        .get("Content-Type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(response.text().await.unwrap().contains("id=\"L1\""));
+}
+
+#[tokio::test]
+async fn wrong_passwords_are_rate_limited_per_paste() {
+    let mut config = test_config();
+    config.rate_limits.passwords = Some(BucketSettings {
+        burst: 2,
+        per_minute: 1,
+    });
+    let app = spawn_app_with_config(config).await;
+    let client = reqwest::Client::new();
+
+    for id in ["locked", "other"] {
+        client
+            .post(format!("{}/paste/{}", &app.address, id))
+            .header("X-Paste-Password", "hunter2")
+            .body("somecontent")
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let read = |id: &str, password: &str| {
+        client
+            .get(format!("{}/paste/{}?view=raw", &app.address, id))
+            .header("X-Paste-Password", password)
+            .send()
+    };
+
+    for _ in 0..2 {
+        assert_eq!(
+            403,
+            read("locked", "guess").await.unwrap().status().as_u16()
+        );
+    }
+
+    // Even the right password waits, or guessing would carry on regardless
+    let response = read("locked", "hunter2").await.unwrap();
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().get("Retry-After").is_some());
+
+    assert_eq!(
+        200,
+        read("other", "hunter2").await.unwrap().status().as_u16()
+    );
 }
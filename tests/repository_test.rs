@@ -0,0 +1,1058 @@
+mod utils;
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use whtpst::config::{QuotaPolicy, QuotaSettings};
+use whtpst::dao::{
+    EvictionOrder, FileSystemRepository, InMemoryRepository, Inserted, QuotaRepository, Repository,
+    RepositoryError, SqliteRepository, Usage,
+};
+use whtpst::domain::{
+    ApiKey, ContentHash, NewPaste, OwnerToken, PasteContent, PasteId, PastePassword, StoredApiKey,
+    Visibility,
+};
+use whtpst::keyring::Keyring;
+
+fn new_paste(id: &str, expires_in: Option<Duration>) -> NewPaste {
+    NewPaste {
+        id: PasteId::parse(id.to_string()).unwrap(),
+        content: PasteContent::parse("somecontent".to_string(), usize::MAX).unwrap(),
+        content_type: Some("text/plain".to_string()),
+        language: Some("rust".to_string()),
+        visibility: Visibility::Unlisted,
+        encryption: None,
+        created_at: Utc::now(),
+        expires_at: expires_in.map(|d| Utc::now() + d),
+        burn_after_reading: false,
+        owner_token_hash: OwnerToken::generate().hash(),
+        owner: None,
+        password_hash: None,
+    }
+}
+
+#[tokio::test]
+async fn purge_expired_removes_only_expired_pastes() {
+    for (backend, repo) in utils::repositories() {
+        repo.insert(new_paste("expired", Some(Duration::seconds(-10))))
+            .await
+            .unwrap();
+        repo.insert(new_paste("live", Some(Duration::hours(1))))
+            .await
+            .unwrap();
+        repo.insert(new_paste("forever", None)).await.unwrap();
+
+        let purged = repo.purge_expired(Utc::now()).await.unwrap();
+        assert_eq!(1, purged, "{}", backend);
+
+        for (id, exists) in [("expired", false), ("live", true), ("forever", true)] {
+            let found = repo.find_one(PasteId::parse(id.to_string()).unwrap()).await;
+            assert_eq!(exists, found.is_ok(), "{}: {}", backend, id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn expiry_round_trips_through_storage() {
+    for (backend, repo) in utils::repositories() {
+        let paste = new_paste("abc", Some(Duration::hours(1)));
+        let expires_at = paste.expires_at.unwrap();
+        repo.insert(paste).await.unwrap();
+
+        let found = repo
+            .find_one(PasteId::parse("abc".to_string()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            expires_at.timestamp(),
+            found.expires_at.unwrap().timestamp(),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_takes_hand_out_a_paste_once() {
+    for (backend, repo) in utils::repositories() {
+        let repo: Arc<dyn Repository> = Arc::from(repo);
+        repo.insert(new_paste("abc", None)).await.unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let repo = Arc::clone(&repo);
+                tokio::spawn(
+                    async move { repo.take(PasteId::parse("abc".to_string()).unwrap()).await },
+                )
+            })
+            .collect();
+
+        let mut taken = 0;
+        for handle in handles {
+            if handle.await.unwrap().is_ok() {
+                taken += 1;
+            }
+        }
+
+        assert_eq!(1, taken, "{}", backend);
+        assert!(repo
+            .find_one(PasteId::parse("abc".to_string()).unwrap())
+            .await
+            .is_err());
+    }
+}
+
+#[tokio::test]
+async fn delete_removes_a_paste_and_reports_missing_ones() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        repo.insert(new_paste("abc", None)).await.unwrap();
+
+        assert!(repo.delete(id()).await.is_ok(), "{}", backend);
+        assert!(repo.find_one(id()).await.is_err(), "{}", backend);
+        assert!(repo.delete(id()).await.is_err(), "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn insert_refuses_to_overwrite_a_live_paste() {
+    for (backend, repo) in utils::repositories() {
+        repo.insert(new_paste("abc", None)).await.unwrap();
+
+        let result = repo.insert(new_paste("abc", None)).await;
+        assert!(
+            matches!(result, Err(RepositoryError::Conflict(_))),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn insert_replaces_an_expired_paste() {
+    for (backend, repo) in utils::repositories() {
+        repo.insert(new_paste("abc", Some(Duration::seconds(-10))))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Inserted::ReplacedExpired,
+            repo.insert(new_paste("abc", None)).await.unwrap(),
+            "{}",
+            backend
+        );
+
+        let found = repo
+            .find_one(PasteId::parse("abc".to_string()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(None, found.expires_at, "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn update_replaces_content_and_its_type_and_keeps_the_rest() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        let paste = new_paste("abc", Some(Duration::hours(1)));
+        let token_hash = paste.owner_token_hash.clone();
+        repo.insert(paste).await.unwrap();
+
+        let content = PasteContent::parse("newcontent".to_string(), usize::MAX).unwrap();
+        repo.update(id(), content, Some("text/markdown".to_string()))
+            .await
+            .unwrap();
+
+        let found = repo.find_one(id()).await.unwrap();
+        assert_eq!(b"newcontent", found.content.as_ref(), "{}", backend);
+        assert_eq!(10, found.size, "{}", backend);
+        assert_eq!(
+            Some("text/markdown"),
+            found.content_type.as_deref(),
+            "{}",
+            backend
+        );
+        assert_eq!(Some("rust"), found.language.as_deref(), "{}", backend);
+        assert!(found.updated_at.is_some(), "{}", backend);
+        assert!(found.expires_at.is_some(), "{}", backend);
+        assert_eq!(Some(token_hash), found.owner_token_hash, "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn update_reports_missing_pastes() {
+    for (backend, repo) in utils::repositories() {
+        let content = PasteContent::parse("newcontent".to_string(), usize::MAX).unwrap();
+        let result = repo
+            .update(PasteId::parse("abc".to_string()).unwrap(), content, None)
+            .await;
+        assert!(
+            matches!(result, Err(RepositoryError::NotFound(_))),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn metadata_round_trips_through_storage() {
+    for (backend, repo) in utils::repositories() {
+        let paste = new_paste("abc", None);
+        let created_at = paste.created_at;
+        repo.insert(paste).await.unwrap();
+
+        let found = repo
+            .find_one(PasteId::parse("abc".to_string()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!("abc", found.id.as_ref(), "{}", backend);
+        assert_eq!(
+            created_at.timestamp(),
+            found.created_at.timestamp(),
+            "{}",
+            backend
+        );
+        assert_eq!(11, found.size, "{}", backend);
+        assert_eq!(
+            Some("text/plain".to_string()),
+            found.content_type,
+            "{}",
+            backend
+        );
+        assert_eq!(Some("rust".to_string()), found.language, "{}", backend);
+        assert_eq!(Visibility::Unlisted, found.visibility, "{}", backend);
+        assert_eq!(0, found.views, "{}", backend);
+        assert_eq!(None, found.updated_at, "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn record_view_counts_views() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        repo.insert(new_paste("abc", None)).await.unwrap();
+
+        for _ in 0..3 {
+            repo.record_view(id()).await.unwrap();
+        }
+
+        assert_eq!(3, repo.find_one(id()).await.unwrap().views, "{}", backend);
+    }
+}
+
+fn public_paste(id: &str, content: &str) -> NewPaste {
+    NewPaste {
+        content: PasteContent::parse(content.to_string(), usize::MAX).unwrap(),
+        visibility: Visibility::Public,
+        encryption: None,
+        ..new_paste(id, None)
+    }
+}
+
+#[tokio::test]
+async fn identical_content_outlives_each_paste_holding_it() {
+    for (backend, repo) in utils::repositories() {
+        let id = |id: &str| PasteId::parse(id.to_string()).unwrap();
+        let hash = || ContentHash::of(b"shared");
+        repo.insert(public_paste("a", "shared")).await.unwrap();
+        repo.insert(public_paste("b", "shared")).await.unwrap();
+
+        repo.delete(id("a")).await.unwrap();
+        let found = repo.find_one(id("b")).await.unwrap();
+        assert_eq!(b"shared", found.content.as_ref(), "{}", backend);
+        assert_eq!(
+            "b",
+            repo.find_by_hash(hash()).await.unwrap().as_ref(),
+            "{}",
+            backend
+        );
+
+        repo.take(id("b")).await.unwrap();
+        let result = repo.find_by_hash(hash()).await;
+        assert!(
+            matches!(result, Err(RepositoryError::NotFound(_))),
+            "{}",
+            backend
+        );
+
+        // Content is stored afresh once it has been reclaimed
+        repo.insert(public_paste("c", "shared")).await.unwrap();
+        let found = repo.find_one(id("c")).await.unwrap();
+        assert_eq!(b"shared", found.content.as_ref(), "{}", backend);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_racing_updates_never_miss_content() {
+    for (backend, repo) in utils::repositories() {
+        let repo: Arc<dyn Repository> = Arc::from(repo);
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        repo.insert(public_paste("abc", "0")).await.unwrap();
+
+        // Every update releases the content the paste held before
+        let writer = {
+            let repo = Arc::clone(&repo);
+            tokio::spawn(async move {
+                for n in 1..200 {
+                    let content = PasteContent::parse(n.to_string(), usize::MAX).unwrap();
+                    repo.update(id(), content, None).await.unwrap();
+                }
+            })
+        };
+
+        while !writer.is_finished() {
+            if let Err(e) = repo.find_one(id()).await {
+                panic!("{}: {}", backend, e);
+            }
+        }
+        writer.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn updated_and_purged_content_is_released() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        repo.insert(public_paste("abc", "before")).await.unwrap();
+
+        let content = PasteContent::parse("after".to_string(), usize::MAX).unwrap();
+        repo.update(id(), content, None).await.unwrap();
+        assert!(
+            repo.find_by_hash(ContentHash::of(b"before")).await.is_err(),
+            "{}",
+            backend
+        );
+        assert!(
+            repo.find_by_hash(ContentHash::of(b"after")).await.is_ok(),
+            "{}",
+            backend
+        );
+
+        let expired = NewPaste {
+            expires_at: Some(Utc::now() - Duration::seconds(10)),
+            ..public_paste("old", "stale")
+        };
+        repo.insert(expired).await.unwrap();
+        repo.purge_expired(Utc::now()).await.unwrap();
+        assert!(
+            repo.find_by_hash(ContentHash::of(b"stale")).await.is_err(),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn find_by_hash_skips_unlisted_burning_protected_and_expired_pastes() {
+    for (backend, repo) in utils::repositories() {
+        repo.insert(NewPaste {
+            visibility: Visibility::Unlisted,
+            ..public_paste("unlisted", "hidden")
+        })
+        .await
+        .unwrap();
+        repo.insert(NewPaste {
+            burn_after_reading: true,
+            ..public_paste("burning", "hidden")
+        })
+        .await
+        .unwrap();
+        repo.insert(NewPaste {
+            password_hash: Some(
+                PastePassword::parse("hunter2".to_string())
+                    .unwrap()
+                    .hash()
+                    .unwrap(),
+            ),
+            ..public_paste("protected", "hidden")
+        })
+        .await
+        .unwrap();
+        repo.insert(NewPaste {
+            expires_at: Some(Utc::now() - Duration::seconds(10)),
+            ..public_paste("expired", "hidden")
+        })
+        .await
+        .unwrap();
+
+        let result = repo.find_by_hash(ContentHash::of(b"hidden")).await;
+        assert!(
+            matches!(result, Err(RepositoryError::NotFound(_))),
+            "{}",
+            backend
+        );
+
+        repo.insert(public_paste("public", "hidden")).await.unwrap();
+        assert_eq!(
+            "public",
+            repo.find_by_hash(ContentHash::of(b"hidden"))
+                .await
+                .unwrap()
+                .as_ref(),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn sqlite_migration_moves_existing_content_into_blobs() {
+    let path = std::env::temp_dir().join(format!("whtpst-test-{}.db", uuid::Uuid::new_v4()));
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        for migration in [
+            include_str!("../migrations/0001_create_pastes.sql"),
+            include_str!("../migrations/0002_add_paste_expiry.sql"),
+            include_str!("../migrations/0003_add_burn_after_reading.sql"),
+            include_str!("../migrations/0004_add_owner_token_hash.sql"),
+            include_str!("../migrations/0005_add_paste_metadata.sql"),
+            include_str!("../migrations/0006_add_paste_language_and_visibility.sql"),
+        ] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 6).unwrap();
+        conn.execute_batch(
+            "INSERT INTO pastes (id, content, created_at, size)
+             VALUES ('a', 'shared', 0, 6), ('b', 'shared', 0, 6), ('c', 'other', 0, 5);",
+        )
+        .unwrap();
+    }
+
+    let repo = whtpst::dao::SqliteRepository::open(&path).unwrap();
+    let id = |id: &str| PasteId::parse(id.to_string()).unwrap();
+
+    repo.delete(id("a")).await.unwrap();
+    assert_eq!(
+        b"shared",
+        repo.find_one(id("b")).await.unwrap().content.as_ref()
+    );
+    assert_eq!(
+        b"other",
+        repo.find_one(id("c")).await.unwrap().content.as_ref()
+    );
+    assert_eq!(
+        "b",
+        repo.find_by_hash(ContentHash::of(b"shared"))
+            .await
+            .unwrap()
+            .as_ref()
+    );
+}
+
+#[tokio::test]
+async fn compressed_content_round_trips_through_storage() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        let content = "the same line, over and over\n".repeat(100);
+        repo.insert(public_paste("abc", &content)).await.unwrap();
+
+        let found = repo.find_one(id()).await.unwrap();
+        assert_eq!(content.as_bytes(), found.content.as_ref(), "{}", backend);
+        assert_eq!(content.len() as u64, found.size, "{}", backend);
+
+        let compressed = backend.contains("+zstd") || backend.contains("+gzip");
+        assert_eq!(compressed, found.encoded.is_some(), "{}", backend);
+        if let Some(encoded) = found.encoded {
+            assert!(encoded.bytes.len() < content.len(), "{}", backend);
+        }
+    }
+}
+
+#[tokio::test]
+async fn encrypted_content_needs_its_key_to_be_read() {
+    let id = || PasteId::parse("abc".to_string()).unwrap();
+    let sqlite = utils::temp_path(".db");
+    let filesystem = utils::temp_path("");
+
+    let open = |keyring: Option<Arc<Keyring>>| -> Vec<(&str, Box<dyn Repository>)> {
+        vec![
+            (
+                "sqlite",
+                Box::new(
+                    SqliteRepository::open(&sqlite)
+                        .unwrap()
+                        .with_encryption(keyring.clone()),
+                ),
+            ),
+            (
+                "filesystem",
+                Box::new(
+                    FileSystemRepository::open(&filesystem)
+                        .unwrap()
+                        .with_encryption(keyring),
+                ),
+            ),
+        ]
+    };
+
+    for (_, repo) in open(Some(utils::test_keyring(Some("k1"), &["k1"]))) {
+        repo.insert(public_paste("abc", "top secret"))
+            .await
+            .unwrap();
+    }
+
+    for (backend, repo) in open(None) {
+        let result = repo.find_one(id()).await;
+        assert!(
+            matches!(result, Err(RepositoryError::ReadFailure(_))),
+            "{}",
+            backend
+        );
+    }
+    for (backend, repo) in open(Some(utils::test_keyring(None, &["k2"]))) {
+        assert!(repo.find_one(id()).await.is_err(), "{}", backend);
+    }
+    for (backend, repo) in open(Some(utils::test_keyring(None, &["k1"]))) {
+        let found = repo.find_one(id()).await.unwrap();
+        assert_eq!(b"top secret", found.content.as_ref(), "{}", backend);
+    }
+
+    // Nothing on disk, including the SQLite write-ahead log, holds it in the
+    // clear
+    let wal = sqlite.with_extension("db-wal");
+    for file in [sqlite.clone(), wal] {
+        let stored = std::fs::read(&file).unwrap_or_default();
+        assert!(!stored.windows(10).any(|w| w == b"top secret"));
+    }
+}
+
+#[tokio::test]
+async fn reencrypt_moves_content_onto_the_current_key() {
+    let id = |id: &str| PasteId::parse(id.to_string()).unwrap();
+    let sqlite = utils::temp_path(".db");
+    let filesystem = utils::temp_path("");
+
+    // Content stored in the clear, then under k1
+    let plain = SqliteRepository::open(&sqlite).unwrap();
+    plain.insert(public_paste("a", "first")).await.unwrap();
+    let plain = FileSystemRepository::open(&filesystem).unwrap();
+    plain.insert(public_paste("a", "first")).await.unwrap();
+
+    let k1 = Some(utils::test_keyring(Some("k1"), &["k1"]));
+    let repo = SqliteRepository::open(&sqlite)
+        .unwrap()
+        .with_encryption(k1.clone());
+    repo.insert(public_paste("b", "second")).await.unwrap();
+    let repo = FileSystemRepository::open(&filesystem)
+        .unwrap()
+        .with_encryption(k1);
+    repo.insert(public_paste("b", "second")).await.unwrap();
+
+    // Rotate to k2
+    let k2 = Some(utils::test_keyring(Some("k2"), &["k1", "k2"]));
+    let repo = SqliteRepository::open(&sqlite)
+        .unwrap()
+        .with_encryption(k2.clone());
+    assert_eq!(2, repo.reencrypt().await.unwrap());
+    assert_eq!(0, repo.reencrypt().await.unwrap());
+    let repo = FileSystemRepository::open(&filesystem)
+        .unwrap()
+        .with_encryption(k2);
+    assert_eq!(2, repo.reencrypt().await.unwrap());
+    assert_eq!(0, repo.reencrypt().await.unwrap());
+
+    // k1 can now be retired
+    let only_k2 = Some(utils::test_keyring(Some("k2"), &["k2"]));
+    let repos: Vec<(&str, Box<dyn Repository>)> = vec![
+        (
+            "sqlite",
+            Box::new(
+                SqliteRepository::open(&sqlite)
+                    .unwrap()
+                    .with_encryption(only_k2.clone()),
+            ),
+        ),
+        (
+            "filesystem",
+            Box::new(
+                FileSystemRepository::open(&filesystem)
+                    .unwrap()
+                    .with_encryption(only_k2),
+            ),
+        ),
+    ];
+    for (backend, repo) in repos {
+        let a = repo.find_one(id("a")).await.unwrap();
+        assert_eq!(b"first", a.content.as_ref(), "{}", backend);
+        let b = repo.find_one(id("b")).await.unwrap();
+        assert_eq!(b"second", b.content.as_ref(), "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn reencrypt_moves_inline_filesystem_content_onto_blobs() {
+    let root = utils::temp_path("");
+    let name = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"legacy"));
+    let path = root.join(&name[0..2]).join(&name[2..4]).join(&name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, "{\"id\":\"legacy\"}\nwritten long ago").unwrap();
+
+    let repo = FileSystemRepository::open(&root)
+        .unwrap()
+        .with_encryption(Some(utils::test_keyring(Some("k1"), &["k1"])));
+    assert_eq!(1, repo.reencrypt().await.unwrap());
+    assert_eq!(0, repo.reencrypt().await.unwrap());
+
+    let stored = std::fs::read_to_string(&path).unwrap();
+    assert!(!stored.contains("written long ago"));
+
+    let found = repo
+        .find_one(PasteId::parse("legacy".to_string()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(b"written long ago", found.content.as_ref());
+    assert_eq!(16, found.size);
+}
+
+#[tokio::test]
+async fn reencrypt_moves_content_onto_its_keyed_hash() {
+    let id = |id: &str| PasteId::parse(id.to_string()).unwrap();
+    let plain_hash = ContentHash::of(b"guessable");
+    let sqlite = utils::temp_path(".db");
+    let filesystem = utils::temp_path("");
+
+    // Content stored under its plain SHA-256, before there was an index key
+    let plain = SqliteRepository::open(&sqlite).unwrap();
+    plain.insert(public_paste("a", "guessable")).await.unwrap();
+    let plain = FileSystemRepository::open(&filesystem).unwrap();
+    plain.insert(public_paste("a", "guessable")).await.unwrap();
+
+    let indexed = Some(utils::test_indexed_keyring(Some("k1"), &["k1"]));
+    let repos: Vec<(&str, Box<dyn Repository>)> = vec![
+        ("sqlite", {
+            let repo = SqliteRepository::open(&sqlite)
+                .unwrap()
+                .with_encryption(indexed.clone());
+            assert_eq!(1, repo.reencrypt().await.unwrap());
+            assert_eq!(0, repo.reencrypt().await.unwrap());
+            Box::new(repo)
+        }),
+        ("filesystem", {
+            let repo = FileSystemRepository::open(&filesystem)
+                .unwrap()
+                .with_encryption(indexed);
+            assert_eq!(1, repo.reencrypt().await.unwrap());
+            assert_eq!(0, repo.reencrypt().await.unwrap());
+            Box::new(repo)
+        }),
+    ];
+
+    for (backend, repo) in repos {
+        let a = repo.find_one(id("a")).await.unwrap();
+        assert_eq!(b"guessable", a.content.as_ref(), "{}", backend);
+        assert_eq!(
+            id("a"),
+            repo.find_by_hash(plain_hash.clone()).await.unwrap(),
+            "{}",
+            backend
+        );
+    }
+
+    // No blob is named after the plain hash any more
+    let blobs = walk(&filesystem.join("blobs"));
+    assert!(!blobs.is_empty());
+    assert!(blobs
+        .iter()
+        .all(|blob| !blob.to_string_lossy().contains(plain_hash.as_ref())));
+}
+
+#[tokio::test]
+async fn purge_expired_sweeps_up_unfinished_burn_after_reading() {
+    let root = utils::temp_path("");
+    let repo = FileSystemRepository::open(&root).unwrap();
+    repo.insert(public_paste("a", "burnt")).await.unwrap();
+    repo.insert(public_paste("b", "kept")).await.unwrap();
+    repo.record_view(PasteId::parse("a".to_string()).unwrap())
+        .await
+        .unwrap();
+
+    // A read that claimed the paste, then crashed before cleaning up
+    let name = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"a"));
+    let path = root.join(&name[0..2]).join(&name[2..4]).join(&name);
+    let claimed = path.with_file_name(format!(".{}.1234.taken", name));
+    std::fs::rename(&path, &claimed).unwrap();
+
+    assert_eq!(1, repo.purge_expired(Utc::now()).await.unwrap());
+    assert!(!claimed.exists());
+    assert!(!path.with_extension("views").exists());
+
+    let expected = Usage {
+        pastes: 1,
+        bytes: 4,
+    };
+    assert_eq!(expected, repo.usage().await.unwrap());
+    assert_eq!(
+        1,
+        walk(&root.join("blobs"))
+            .iter()
+            .filter(|blob| blob.extension().is_none())
+            .count()
+    );
+}
+
+/// Every file beneath `dir`.
+fn walk(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .flat_map(|entry| {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => walk(&path),
+                false => vec![path],
+            }
+        })
+        .collect()
+}
+
+/// A paste of `content` created `hours_ago`.
+fn paste_created(id: &str, content: &str, hours_ago: i64) -> NewPaste {
+    NewPaste {
+        content: PasteContent::parse(content.to_string(), usize::MAX).unwrap(),
+        created_at: Utc::now() - Duration::hours(hours_ago),
+        ..new_paste(id, None)
+    }
+}
+
+#[tokio::test]
+async fn usage_counts_pastes_and_their_sizes() {
+    for (backend, repo) in utils::repositories() {
+        assert_eq!(Usage::default(), repo.usage().await.unwrap(), "{}", backend);
+
+        repo.insert(paste_created("a", "four", 0)).await.unwrap();
+        repo.insert(paste_created("b", "four", 0)).await.unwrap();
+        repo.insert(paste_created("c", "sixsix", 0)).await.unwrap();
+        repo.delete(PasteId::parse("b".to_string()).unwrap())
+            .await
+            .unwrap();
+
+        let expected = Usage {
+            pastes: 2,
+            bytes: 10,
+        };
+        assert_eq!(expected, repo.usage().await.unwrap(), "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn eviction_candidates_follow_the_eviction_order() {
+    for (backend, repo) in utils::repositories() {
+        let ids = |candidates: Vec<(PasteId, u64)>| {
+            candidates
+                .into_iter()
+                .map(|(id, _)| id.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        };
+        repo.insert(paste_created("a", "a", 3)).await.unwrap();
+        repo.insert(paste_created("b", "bb", 2)).await.unwrap();
+        repo.insert(paste_created("c", "ccc", 1)).await.unwrap();
+        repo.record_view(PasteId::parse("a".to_string()).unwrap())
+            .await
+            .unwrap();
+
+        let oldest = repo
+            .eviction_candidates(EvictionOrder::Oldest, 10)
+            .await
+            .unwrap();
+        assert_eq!(vec!["a", "b", "c"], ids(oldest), "{}", backend);
+
+        let least_recently_read = repo
+            .eviction_candidates(EvictionOrder::LeastRecentlyRead, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![
+                (PasteId::parse("b".to_string()).unwrap(), 2),
+                (PasteId::parse("c".to_string()).unwrap(), 3)
+            ],
+            least_recently_read,
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn record_view_notes_when_a_paste_was_last_read() {
+    for (backend, repo) in utils::repositories() {
+        let id = || PasteId::parse("abc".to_string()).unwrap();
+        repo.insert(new_paste("abc", None)).await.unwrap();
+        assert_eq!(None, repo.find_one(id()).await.unwrap().last_read_at);
+
+        let before = Utc::now() - Duration::seconds(1);
+        repo.record_view(id()).await.unwrap();
+
+        let last_read_at = repo.find_one(id()).await.unwrap().last_read_at.unwrap();
+        assert!(last_read_at >= before, "{}", backend);
+    }
+}
+
+fn quota(max_pastes: Option<u64>, max_bytes: Option<u64>, policy: QuotaPolicy) -> QuotaSettings {
+    QuotaSettings {
+        max_pastes,
+        max_bytes,
+        policy,
+    }
+}
+
+#[tokio::test]
+async fn quota_rejects_pastes_once_full() {
+    let repo = QuotaRepository::new(
+        InMemoryRepository::new(),
+        quota(Some(2), Some(10), QuotaPolicy::Reject),
+    );
+
+    repo.insert(paste_created("a", "four", 0)).await.unwrap();
+    repo.insert(paste_created("b", "four", 0)).await.unwrap();
+
+    // Over both the count and, with three bytes, the size
+    let full = repo.insert(paste_created("c", "six", 0)).await;
+    assert!(matches!(full, Err(RepositoryError::InsufficientStorage(_))));
+
+    repo.delete(PasteId::parse("a".to_string()).unwrap())
+        .await
+        .unwrap();
+    repo.insert(paste_created("c", "six", 0)).await.unwrap();
+
+    // Growing a paste needs room as much as adding one
+    let grown = repo
+        .update(
+            PasteId::parse("c".to_string()).unwrap(),
+            PasteContent::parse("sevenseven".to_string(), usize::MAX).unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(
+        grown,
+        Err(RepositoryError::InsufficientStorage(_))
+    ));
+}
+
+#[tokio::test]
+async fn quota_counts_out_an_expired_paste_that_is_replaced() {
+    let repo = QuotaRepository::new(
+        InMemoryRepository::new(),
+        quota(Some(3), None, QuotaPolicy::Reject),
+    );
+
+    repo.insert(paste_created("a", "four", 0)).await.unwrap();
+    repo.insert(new_paste("b", Some(Duration::seconds(-10))))
+        .await
+        .unwrap();
+
+    // Re-created in place of the expired paste, so still two pastes
+    let inserted = repo.insert(new_paste("b", None)).await.unwrap();
+    assert_eq!(Inserted::ReplacedExpired, inserted);
+
+    // Nothing is left to purge, so this only fits if b was counted once
+    repo.insert(paste_created("c", "six", 0)).await.unwrap();
+}
+
+#[tokio::test]
+async fn quota_purges_expired_pastes_before_rejecting() {
+    let repo = QuotaRepository::new(
+        InMemoryRepository::new(),
+        quota(Some(1), None, QuotaPolicy::Reject),
+    );
+
+    repo.insert(new_paste("expired", Some(Duration::seconds(-10))))
+        .await
+        .unwrap();
+    repo.insert(new_paste("live", None)).await.unwrap();
+
+    let usage = repo.usage().await.unwrap();
+    assert_eq!(1, usage.pastes);
+}
+
+#[tokio::test]
+async fn quota_evicts_pastes_in_policy_order() {
+    for (policy, evicted) in [
+        (QuotaPolicy::EvictOldest, "a"),
+        (QuotaPolicy::EvictLeastRecentlyRead, "b"),
+    ] {
+        let repo = QuotaRepository::new(utils::sqlite_repository(), quota(None, Some(10), policy));
+        repo.insert(paste_created("a", "four", 3)).await.unwrap();
+        repo.insert(paste_created("b", "four", 2)).await.unwrap();
+        repo.record_view(PasteId::parse("a".to_string()).unwrap())
+            .await
+            .unwrap();
+
+        repo.insert(paste_created("c", "four", 0)).await.unwrap();
+
+        for id in ["a", "b", "c"] {
+            let found = repo.find_one(PasteId::parse(id.to_string()).unwrap()).await;
+            assert_eq!(id != evicted, found.is_ok(), "{:?}: {}", policy, id);
+        }
+        assert_eq!(
+            Usage {
+                pastes: 2,
+                bytes: 8
+            },
+            repo.usage().await.unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn quota_never_evicts_for_a_write_that_fails() {
+    let repo = QuotaRepository::new(
+        utils::sqlite_repository(),
+        quota(None, Some(10), QuotaPolicy::EvictOldest),
+    );
+    repo.insert(paste_created("a", "four", 2)).await.unwrap();
+    repo.insert(paste_created("b", "four", 1)).await.unwrap();
+
+    // The id is taken, so the paste is never written
+    let taken = repo.insert(paste_created("b", "sixsix", 0)).await;
+    assert!(matches!(taken, Err(RepositoryError::Conflict(_))));
+
+    for id in ["a", "b"] {
+        assert!(repo
+            .find_one(PasteId::parse(id.to_string()).unwrap())
+            .await
+            .is_ok());
+    }
+    assert_eq!(
+        Usage {
+            pastes: 2,
+            bytes: 8
+        },
+        repo.usage().await.unwrap()
+    );
+
+    // Nor does failing hold up later writes
+    repo.insert(paste_created("c", "four", 0)).await.unwrap();
+    assert!(repo
+        .find_one(PasteId::parse("a".to_string()).unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn quota_never_evicts_for_a_paste_larger_than_the_quota() {
+    let repo = QuotaRepository::new(
+        InMemoryRepository::new(),
+        quota(None, Some(5), QuotaPolicy::EvictOldest),
+    );
+    repo.insert(paste_created("a", "four", 1)).await.unwrap();
+
+    let too_large = repo.insert(paste_created("b", "sixsix", 0)).await;
+    assert!(matches!(
+        too_large,
+        Err(RepositoryError::InsufficientStorage(_))
+    ));
+    assert!(repo
+        .find_one(PasteId::parse("a".to_string()).unwrap())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn find_by_owner_returns_only_owned_pastes() {
+    for (backend, repo) in utils::repositories() {
+        for (id, owner) in [("a", Some("team")), ("b", Some("other")), ("c", None)] {
+            repo.insert(NewPaste {
+                owner: owner.map(str::to_owned),
+                ..new_paste(id, None)
+            })
+            .await
+            .unwrap();
+        }
+
+        let owned = repo.find_by_owner("team".to_string()).await.unwrap();
+        assert_eq!(1, owned.len(), "{}", backend);
+        assert_eq!("a", owned[0].id.as_ref(), "{}", backend);
+        assert_eq!(Some("team".to_string()), owned[0].owner, "{}", backend);
+        assert_eq!(
+            "somecontent",
+            owned[0].content.as_text().unwrap(),
+            "{}",
+            backend
+        );
+    }
+}
+
+#[tokio::test]
+async fn password_hashes_survive_updates() {
+    let password = PastePassword::parse("hunter2".to_string()).unwrap();
+    let hash = password.hash().unwrap();
+
+    for (backend, repo) in utils::repositories() {
+        repo.insert(NewPaste {
+            password_hash: Some(hash.clone()),
+            ..new_paste("locked", None)
+        })
+        .await
+        .unwrap();
+        repo.insert(new_paste("open", None)).await.unwrap();
+
+        let id = PasteId::parse("locked".to_string()).unwrap();
+        repo.update(
+            id.clone(),
+            PasteContent::parse("new".to_string(), usize::MAX).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let locked = repo.find_one(id).await.unwrap();
+        assert_eq!(Some(&hash), locked.password_hash.as_ref(), "{}", backend);
+        assert!(
+            locked.password_hash.unwrap().verify(&password),
+            "{}",
+            backend
+        );
+        let open = repo
+            .find_one(PasteId::parse("open".to_string()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(None, open.password_hash, "{}", backend);
+    }
+}
+
+#[tokio::test]
+async fn api_keys_are_found_by_hash_until_revoked() {
+    for (backend, store) in utils::key_stores() {
+        let key = ApiKey::generate();
+        store
+            .insert_key(StoredApiKey::new("team".to_string(), &key).unwrap())
+            .await
+            .unwrap();
+
+        let found = store.find_key(key.hash()).await.unwrap();
+        assert_eq!("team", found.name, "{}", backend);
+        assert!(
+            store.find_key(ApiKey::generate().hash()).await.is_err(),
+            "{}",
+            backend
+        );
+
+        // Names are unique
+        let taken = store
+            .insert_key(StoredApiKey::new("team".to_string(), &ApiKey::generate()).unwrap())
+            .await;
+        assert!(
+            matches!(taken, Err(RepositoryError::Conflict(_))),
+            "{}",
+            backend
+        );
+        assert_eq!(1, store.list_keys().await.unwrap().len(), "{}", backend);
+
+        store.delete_key("team".to_string()).await.unwrap();
+        assert!(
+            matches!(
+                store.find_key(key.hash()).await,
+                Err(RepositoryError::NotFound(_))
+            ),
+            "{}",
+            backend
+        );
+        assert!(
+            matches!(
+                store.delete_key("team".to_string()).await,
+                Err(RepositoryError::NotFound(_))
+            ),
+            "{}",
+            backend
+        );
+    }
+}
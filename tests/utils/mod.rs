@@ -1,7 +1,18 @@
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use once_cell::sync::Lazy;
-use std::net::TcpListener;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use std::{net::TcpListener, path::PathBuf, sync::Arc};
+use uuid::Uuid;
 use whtpst::{
-    dao::{InMemoryRepository, Repository},
+    compression::{Gzip, Zstd},
+    config::{get_config, Settings},
+    dao::{FileSystemRepository, InMemoryRepository, KeyStore, Repository, SqliteRepository},
+    domain::{ApiKey, StoredApiKey},
+    keyring::Keyring,
     startup::run,
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -19,20 +30,182 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     };
 });
 
+/// The paste size limit test apps are started with.
+pub const MAX_PASTE_BYTES: usize = 64 * 1024;
+
 pub struct TestApp {
     pub address: String,
 }
 
+/// The storage backends the integration tests run against.
+pub enum Backend {
+    InMemory,
+    Sqlite,
+    FileSystem,
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_backend(Backend::InMemory).await
+}
+
+pub async fn spawn_app_with_backend(backend: Backend) -> TestApp {
+    let config = test_config();
+    let codec = config.storage.compression.codec().unwrap();
+    let keyring = config.storage.encryption.keyring().unwrap();
+
+    match backend {
+        Backend::InMemory => spawn_app_with_repo(InMemoryRepository::new(), config).await,
+        Backend::Sqlite => {
+            let repo = sqlite_repository()
+                .with_compression(codec)
+                .with_encryption(keyring);
+            spawn_app_with_repo(repo, config).await
+        }
+        Backend::FileSystem => {
+            let repo = filesystem_repository()
+                .with_compression(codec)
+                .with_encryption(keyring);
+            spawn_app_with_repo(repo, config).await
+        }
+    }
+}
+
+/// An in-memory app started with a custom configuration.
+pub async fn spawn_app_with_config(config: Settings) -> TestApp {
+    spawn_app_with_repo(InMemoryRepository::new(), config).await
+}
+
+/// An in-memory app that knows of an API key for each of `names`, returned
+/// along with the keys.
+pub async fn spawn_app_with_keys(names: &[&str]) -> (TestApp, Vec<String>) {
+    spawn_app_with_keys_and_config(names, test_config()).await
+}
+
+/// As `spawn_app_with_keys`, with a custom configuration.
+pub async fn spawn_app_with_keys_and_config(
+    names: &[&str],
+    config: Settings,
+) -> (TestApp, Vec<String>) {
+    let repo = InMemoryRepository::new();
+    let mut keys = Vec::new();
+    for name in names {
+        let key = ApiKey::generate();
+        repo.insert_key(StoredApiKey::new(name.to_string(), &key).unwrap())
+            .await
+            .unwrap();
+        keys.push(key.expose_secret().to_owned());
+    }
+
+    let app = spawn_app_with_repo(repo, config).await;
+    (app, keys)
+}
+
+/// The configuration test apps are started with, unless told otherwise.
+pub fn test_config() -> Settings {
+    let mut config = get_config().expect("Failed to read configuration");
+    config.limits.max_paste_bytes = MAX_PASTE_BYTES;
+    // Paste URLs should point back at the test server
+    config.application.base_url = None;
+    // Tests make requests faster than any client should
+    config.rate_limits.create = None;
+    config.rate_limits.read = None;
+    config
+}
+
+/// A fresh path in the temporary directory, ending in `suffix`.
+pub fn temp_path(suffix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("whtpst-test-{}{}", Uuid::new_v4(), suffix))
+}
+
+/// A `SqliteRepository` backed by a fresh database file.
+pub fn sqlite_repository() -> SqliteRepository {
+    SqliteRepository::open(temp_path(".db")).expect("Failed to open the sqlite database")
+}
+
+/// A `FileSystemRepository` rooted in a fresh directory.
+pub fn filesystem_repository() -> FileSystemRepository {
+    FileSystemRepository::open(temp_path("")).expect("Failed to open the storage directory")
+}
+
+/// A keyring holding a key for each of `ids`, each derived from its id so
+/// keyrings built apart agree on them.
+pub fn test_keyring(current: Option<&str>, ids: &[&str]) -> Arc<Keyring> {
+    Arc::new(keyring(current, ids))
+}
+
+/// A `test_keyring` that also indexes content under a keyed hash.
+pub fn test_indexed_keyring(current: Option<&str>, ids: &[&str]) -> Arc<Keyring> {
+    let key = Secret::new(BASE64.encode(Sha256::digest(b"index")));
+
+    Arc::new(
+        keyring(current, ids)
+            .with_index_key(&key)
+            .expect("Invalid test index key"),
+    )
+}
+
+fn keyring(current: Option<&str>, ids: &[&str]) -> Keyring {
+    let keys = ids
+        .iter()
+        .map(|id| {
+            let key = BASE64.encode(Sha256::digest(id.as_bytes()));
+            (id.to_string(), Secret::new(key))
+        })
+        .collect();
+
+    Keyring::new(current.map(str::to_owned), &keys).expect("Invalid test keyring")
+}
+
+/// One fresh instance of every repository backend, labelled for assertions.
+/// Backends that can compress or encrypt content also appear doing so.
+pub fn repositories() -> Vec<(&'static str, Box<dyn Repository>)> {
+    vec![
+        ("inmemory", Box::new(InMemoryRepository::new())),
+        ("sqlite", Box::new(sqlite_repository())),
+        (
+            "sqlite+zstd",
+            Box::new(sqlite_repository().with_compression(Some(Arc::new(Zstd::default())))),
+        ),
+        ("filesystem", Box::new(filesystem_repository())),
+        (
+            "filesystem+gzip",
+            Box::new(filesystem_repository().with_compression(Some(Arc::new(Gzip::default())))),
+        ),
+        (
+            "sqlite+aes",
+            Box::new(
+                sqlite_repository()
+                    .with_encryption(Some(test_indexed_keyring(Some("k1"), &["k1"]))),
+            ),
+        ),
+        (
+            "filesystem+gzip+aes",
+            Box::new(
+                filesystem_repository()
+                    .with_compression(Some(Arc::new(Gzip::default())))
+                    .with_encryption(Some(test_indexed_keyring(Some("k1"), &["k1"]))),
+            ),
+        ),
+    ]
+}
+
+/// One fresh key store of every backend, labelled for assertions.
+pub fn key_stores() -> Vec<(&'static str, Box<dyn KeyStore>)> {
+    vec![
+        ("inmemory", Box::new(InMemoryRepository::new())),
+        ("sqlite", Box::new(sqlite_repository())),
+        ("filesystem", Box::new(filesystem_repository())),
+    ]
+}
+
+async fn spawn_app_with_repo<R: Repository + KeyStore>(repo: R, config: Settings) -> TestApp {
     Lazy::force(&TRACING);
 
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a random port");
     let port = listener.local_addr().unwrap().port();
 
-    let repo = InMemoryRepository::new();
-
-    let server = run(listener, repo).expect("Failed to bind addr");
-    let _ = tokio::spawn(server);
+    let server = run(listener, repo, config).expect("Failed to bind addr");
+    tokio::spawn(server);
 
     TestApp {
         address: format!("http://127.0.0.1:{}", port),